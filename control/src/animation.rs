@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{animation::Animation, arbiter::Priority, KBot};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Mirror {
+  /// Play the animation as written.
+  #[default]
+  None,
+  /// Play it on the other side of the body.
+  Swap,
+  /// Play it on both sides at once.
+  Both,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PlayRequest {
+  pub animation: Animation,
+  #[serde(default)]
+  pub mirror: Mirror,
+}
+
+pub async fn play(
+  State(kbot): State<Arc<KBot>>,
+  Json(request): Json<PlayRequest>,
+) -> Result<Json<Animation>, AppError> {
+  let description = kbot.description.as_ref();
+  let animation = match request.mirror {
+    Mirror::None => request.animation,
+    Mirror::Swap => request.animation.mirror::<KBot>(description),
+    Mirror::Both => request.animation.symmetric::<KBot>(description),
+  };
+
+  let lease = kbot.arbiter.acquire(
+    "animation",
+    Priority::Pose,
+    animation.actuators::<KBot>(),
+  )?;
+
+  kbot.play(&lease, &animation).await?;
+
+  Ok(Json(animation))
+}
@@ -0,0 +1,8 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{arbiter::Owner, KBot};
+
+pub async fn owners(State(kbot): State<Arc<KBot>>) -> Json<Vec<Owner>> {
+  Json(kbot.arbiter.owners())
+}
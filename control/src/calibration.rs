@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{
+  calibration::{CalibrationMethod, CalibrationStatus},
+  KBot, Robot,
+};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct StartRequest {
+  /// Defaults to every actuator on the robot.
+  actuator_ids: Option<Vec<u32>>,
+}
+
+pub async fn status(
+  State(kbot): State<Arc<KBot>>,
+) -> Json<Option<CalibrationStatus>> {
+  Json(kbot.calibrator.status().await)
+}
+
+pub async fn start(
+  State(kbot): State<Arc<KBot>>,
+  request: Option<Json<StartRequest>>,
+) -> Result<Json<CalibrationStatus>, AppError> {
+  let request = request.map(|Json(request)| request).unwrap_or_default();
+  let actuator_ids =
+    request.actuator_ids.unwrap_or_else(KBot::list_actuator_ids);
+
+  Ok(Json(kbot.calibrator.start(actuator_ids).await?))
+}
+
+pub async fn prepare(
+  State(kbot): State<Arc<KBot>>,
+  Json(method): Json<CalibrationMethod>,
+) -> Result<Json<CalibrationStatus>, AppError> {
+  Ok(Json(kbot.calibrator.prepare(&kbot, method).await?))
+}
+
+pub async fn zero(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<CalibrationStatus>, AppError> {
+  Ok(Json(kbot.calibrator.zero().await?))
+}
+
+pub async fn skip(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<CalibrationStatus>, AppError> {
+  Ok(Json(kbot.calibrator.skip().await?))
+}
@@ -0,0 +1,32 @@
+use axum::{
+  http::StatusCode,
+  response::{IntoResponse, Response},
+};
+use rpc::{arbiter::Conflict, fall::Fallen};
+
+/// Handler error that reports the underlying failure as a 500, or a 409 when
+/// another controller owns the joints or the robot has fallen.
+pub struct AppError(eyre::Report);
+
+impl IntoResponse for AppError {
+  fn into_response(self) -> Response {
+    let status = if self.0.downcast_ref::<Conflict>().is_some()
+      || self.0.downcast_ref::<Fallen>().is_some()
+    {
+      StatusCode::CONFLICT
+    } else {
+      StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    (status, format!("{:#}", self.0)).into_response()
+  }
+}
+
+impl<E> From<E> for AppError
+where
+  E: Into<eyre::Report>,
+{
+  fn from(err: E) -> Self {
+    Self(err.into())
+  }
+}
@@ -0,0 +1,29 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+  extract::State,
+  response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream};
+use rpc::KBot;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Streams [`rpc::events::RobotEvent`]s to the client as server-sent events.
+pub async fn stream(
+  State(kbot): State<Arc<KBot>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+  let events = stream::unfold(kbot.subscribe(), |mut rx| async move {
+    loop {
+      match rx.recv().await {
+        Ok(event) => {
+          let event = Event::default().json_data(&event).unwrap_or_default();
+          return Some((Ok(event), rx));
+        }
+        Err(RecvError::Lagged(_)) => continue,
+        Err(RecvError::Closed) => return None,
+      }
+    }
+  });
+
+  Sse::new(events).keep_alive(KeepAlive::default())
+}
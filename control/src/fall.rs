@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{fall::FallStatus, KBot};
+
+pub async fn status(State(kbot): State<Arc<KBot>>) -> Json<FallStatus> {
+  Json(kbot.fall_detector.status())
+}
+
+pub async fn reset(State(kbot): State<Arc<KBot>>) -> Json<FallStatus> {
+  kbot.fall_detector.reset();
+
+  Json(kbot.fall_detector.status())
+}
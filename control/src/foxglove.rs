@@ -0,0 +1,339 @@
+use std::{
+  collections::HashMap,
+  net::SocketAddr,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+  extract::{
+    ws::{Message, WebSocket, WebSocketUpgrade},
+    State,
+  },
+  response::Response,
+  routing::get,
+  Router,
+};
+use rpc::{
+  pose::JointGroup,
+  recorder::{
+    ImuSample, LiveMessage, Orientation, RecordedState, StatesMessage, Topic,
+  },
+  velocity::VelocityCommand,
+  KBot,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Subprotocol of <https://github.com/foxglove/ws-protocol>.
+const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+
+/// Topic the Teleop panel publishes `geometry_msgs/Twist` velocities on.
+const VELOCITY_TOPIC: &str = "/cmd_vel";
+
+/// Opcode of binary message frames, in both directions.
+const MESSAGE_DATA: u8 = 0x01;
+
+/// How often joint states, the IMU and orientation are read while a client
+/// is connected.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct Server {
+  kbot: Arc<KBot>,
+  clients: AtomicUsize,
+}
+
+/// Serves Foxglove Studio on `addr`. Every [`Topic`] is advertised as a
+/// JSON channel, and clients may publish velocity commands on `/cmd_vel`.
+pub async fn serve(kbot: Arc<KBot>, addr: SocketAddr) -> eyre::Result<()> {
+  let server = Arc::new(Server {
+    kbot,
+    clients: AtomicUsize::new(0),
+  });
+
+  // Reads go through the unrecorded HAL and are only published to live
+  // subscribers, so a running recording holds what the robot itself read.
+  let poller = server.clone();
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+      interval.tick().await;
+
+      if poller.clients.load(Ordering::Relaxed) == 0 {
+        continue;
+      }
+
+      let kbot = &poller.kbot;
+      let hal = &kbot.unrecorded_hal;
+      let actuators = JointGroup::WholeBody.actuators::<KBot>();
+      match hal.actuators.get_actuators_state(actuators).await {
+        Ok(states) => kbot.recorder.publish(
+          Topic::ActuatorStates,
+          &StatesMessage {
+            states: states.iter().map(RecordedState::from).collect(),
+          },
+        ),
+        Err(e) => {
+          tracing::warn!(error = %e, "Failed to read joint states for Foxglove")
+        }
+      }
+      match hal.imu.get_values().await {
+        Ok(values) => {
+          kbot.recorder.publish(Topic::Imu, &ImuSample::from(&values))
+        }
+        Err(e) => tracing::warn!(error = %e, "Failed to read IMU for Foxglove"),
+      }
+      match hal.imu.get_euler().await {
+        Ok(angles) => kbot
+          .recorder
+          .publish(Topic::Orientation, &Orientation::from(&angles)),
+        Err(e) => {
+          tracing::warn!(error = %e, "Failed to read orientation for Foxglove")
+        }
+      }
+    }
+  });
+
+  let app = Router::new().route("/", get(connect)).with_state(server);
+
+  let listener = tokio::net::TcpListener::bind(addr).await?;
+  tracing::info!(%addr, "Foxglove server listening");
+  axum::serve(listener, app).await?;
+
+  Ok(())
+}
+
+async fn connect(
+  State(server): State<Arc<Server>>,
+  ws: WebSocketUpgrade,
+) -> Response {
+  ws.protocols([SUBPROTOCOL])
+    .on_upgrade(move |socket| async move {
+      server.clients.fetch_add(1, Ordering::Relaxed);
+
+      if let Err(e) = session(&server.kbot, socket).await {
+        tracing::warn!(error = %e, "Foxglove client disconnected");
+      }
+
+      server.clients.fetch_sub(1, Ordering::Relaxed);
+    })
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum ClientOp {
+  Subscribe {
+    subscriptions: Vec<Subscription>,
+  },
+  #[serde(rename_all = "camelCase")]
+  Unsubscribe {
+    subscription_ids: Vec<u32>,
+  },
+  Advertise {
+    channels: Vec<ClientChannel>,
+  },
+  #[serde(rename_all = "camelCase")]
+  Unadvertise {
+    channel_ids: Vec<u32>,
+  },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Subscription {
+  id: u32,
+  channel_id: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct ClientChannel {
+  id: u32,
+  topic: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Vector3 {
+  x: f64,
+  y: f64,
+  z: f64,
+}
+
+/// `geometry_msgs/Twist` as published by the Teleop panel.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Twist {
+  linear: Vector3,
+  angular: Vector3,
+}
+
+/// Channel ids are positions in [`Topic::ALL`], starting at 1.
+fn channel_id(topic: Topic) -> u32 {
+  Topic::ALL
+    .iter()
+    .position(|t| *t == topic)
+    .unwrap_or_default() as u32
+    + 1
+}
+
+async fn session(kbot: &KBot, mut socket: WebSocket) -> eyre::Result<()> {
+  let mut live = kbot.recorder.subscribe();
+
+  let session_id = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis();
+  let server_info = json!({
+    "op": "serverInfo",
+    "name": "kbot",
+    "capabilities": ["clientPublish"],
+    "supportedEncodings": ["json"],
+    "metadata": {},
+    "sessionId": session_id.to_string(),
+  });
+  socket
+    .send(Message::Text(server_info.to_string().into()))
+    .await?;
+
+  let channels = Topic::ALL
+    .iter()
+    .map(|topic| {
+      let (schema_name, schema) = topic.schema();
+      json!({
+        "id": channel_id(*topic),
+        "topic": topic.name(),
+        "encoding": "json",
+        "schemaName": schema_name,
+        "schema": schema.to_string(),
+        "schemaEncoding": "jsonschema",
+      })
+    })
+    .collect::<Vec<_>>();
+  let advertise = json!({ "op": "advertise", "channels": channels });
+  socket
+    .send(Message::Text(advertise.to_string().into()))
+    .await?;
+
+  // Subscription id to channel id.
+  let mut subscriptions = HashMap::new();
+  // Client channel id to topic.
+  let mut client_channels = HashMap::new();
+
+  loop {
+    tokio::select! {
+      message = socket.recv() => {
+        let Some(message) = message else {
+          return Ok(());
+        };
+
+        match message? {
+          Message::Text(text) => {
+            match serde_json::from_str::<ClientOp>(&text) {
+              Ok(ClientOp::Subscribe { subscriptions: added }) => {
+                subscriptions.extend(
+                  added.into_iter().map(|sub| (sub.id, sub.channel_id)),
+                );
+              }
+              Ok(ClientOp::Unsubscribe { subscription_ids }) => {
+                for id in subscription_ids {
+                  subscriptions.remove(&id);
+                }
+              }
+              Ok(ClientOp::Advertise { channels }) => {
+                client_channels.extend(
+                  channels.into_iter().map(|channel| {
+                    (channel.id, channel.topic)
+                  }),
+                );
+              }
+              Ok(ClientOp::Unadvertise { channel_ids }) => {
+                for id in channel_ids {
+                  client_channels.remove(&id);
+                }
+              }
+              Err(e) => {
+                let message = format!("Unsupported message: {e}");
+                socket.send(warning(message)).await?;
+              }
+            }
+          }
+          Message::Binary(data) => {
+            if let Err(e) = client_message(kbot, &client_channels, &data).await
+            {
+              socket.send(warning(e.to_string())).await?;
+            }
+          }
+          Message::Close(_) => return Ok(()),
+          _ => {}
+        }
+      }
+      message = live.recv() => {
+        let message = match message {
+          Ok(message) => message,
+          Err(RecvError::Lagged(_)) => continue,
+          Err(RecvError::Closed) => return Ok(()),
+        };
+
+        let channel = channel_id(message.topic);
+        for (subscription, _) in
+          subscriptions.iter().filter(|(_, id)| **id == channel)
+        {
+          let frame = message_frame(*subscription, &message);
+          socket.send(Message::Binary(frame.into())).await?;
+        }
+      }
+    }
+  }
+}
+
+/// Status message shown to the user as a warning.
+fn warning(message: String) -> Message {
+  let status = json!({ "op": "status", "level": 1, "message": message });
+
+  Message::Text(status.to_string().into())
+}
+
+/// Opcode, subscription id, log time and payload.
+fn message_frame(subscription: u32, message: &LiveMessage) -> Vec<u8> {
+  let mut frame = Vec::with_capacity(13 + message.data.len());
+  frame.push(MESSAGE_DATA);
+  frame.extend(subscription.to_le_bytes());
+  frame.extend(message.log_time.to_le_bytes());
+  frame.extend(message.data.iter());
+
+  frame
+}
+
+/// Handles a message the client published on one of its channels.
+async fn client_message(
+  kbot: &KBot,
+  client_channels: &HashMap<u32, String>,
+  data: &[u8],
+) -> eyre::Result<()> {
+  let [MESSAGE_DATA, a, b, c, d, payload @ ..] = data else {
+    return Err(eyre::eyre!("Unsupported binary message"));
+  };
+  let channel = u32::from_le_bytes([*a, *b, *c, *d]);
+
+  match client_channels.get(&channel).map(String::as_str) {
+    Some(VELOCITY_TOPIC) => {
+      let twist: Twist = serde_json::from_slice(payload)?;
+      kbot.walk_command.set(VelocityCommand {
+        x: twist.linear.x,
+        y: twist.linear.y,
+        yaw: twist.angular.z,
+      })?;
+    }
+    Some(topic) => {
+      return Err(eyre::eyre!("Publishing on {topic} is not supported"))
+    }
+    None => return Err(eyre::eyre!("Unknown client channel {channel}")),
+  }
+
+  Ok(())
+}
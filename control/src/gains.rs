@@ -0,0 +1,22 @@
+use std::sync::Arc;
+
+use axum::{
+  extract::{Path, State},
+  Json,
+};
+use rpc::{gains::GainStatus, KBot};
+
+use crate::error::AppError;
+
+pub async fn status(State(kbot): State<Arc<KBot>>) -> Json<GainStatus> {
+  Json(kbot.gains.status().await)
+}
+
+pub async fn apply(
+  State(kbot): State<Arc<KBot>>,
+  Path(name): Path<String>,
+) -> Result<Json<GainStatus>, AppError> {
+  kbot.gains.apply(&name).await?;
+
+  Ok(Json(kbot.gains.status().await))
+}
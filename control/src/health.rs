@@ -0,0 +1,22 @@
+use std::sync::Arc;
+
+use axum::{
+  extract::{Path, State},
+  Json,
+};
+use rpc::{health::HealthReport, KBot};
+
+use crate::error::AppError;
+
+pub async fn report(State(kbot): State<Arc<KBot>>) -> Json<HealthReport> {
+  Json(kbot.health.report().await)
+}
+
+pub async fn reset(
+  State(kbot): State<Arc<KBot>>,
+  Path(actuator_id): Path<u32>,
+) -> Result<Json<HealthReport>, AppError> {
+  kbot.health.reset(actuator_id).await?;
+
+  Ok(Json(kbot.health.report().await))
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use axum::{
+  extract::{Path, State},
+  Json,
+};
+use rpc::{
+  arbiter::Priority,
+  ik::{IkSolution, IkTarget},
+  kinematics::Limb,
+  KBot,
+};
+
+use crate::error::AppError;
+
+/// Solves for the target without moving.
+pub async fn solve(
+  State(kbot): State<Arc<KBot>>,
+  Path(limb): Path<Limb>,
+  Json(target): Json<IkTarget>,
+) -> Result<Json<IkSolution>, AppError> {
+  Ok(Json(kbot.solve_ik(limb, &target).await?))
+}
+
+pub async fn reach(
+  State(kbot): State<Arc<KBot>>,
+  Path(limb): Path<Limb>,
+  Json(target): Json<IkTarget>,
+) -> Result<Json<IkSolution>, AppError> {
+  let lease = kbot.arbiter.acquire(
+    "reach",
+    Priority::Pose,
+    limb.group().actuators::<KBot>(),
+  )?;
+
+  Ok(Json(kbot.reach(&lease, limb, &target).await?))
+}
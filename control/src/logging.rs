@@ -0,0 +1,81 @@
+use axum::{
+  extract::{MatchedPath, Request},
+  middleware::Next,
+  response::Response,
+};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::Instrument;
+use tracing_subscriber::{
+  fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+/// Used when `RUST_LOG` is not set.
+const DEFAULT_FILTER: &str = "info";
+
+/// Installs the global subscriber.
+///
+/// - `RUST_LOG` picks levels, e.g. `rpc=debug,control=info`.
+/// - `KBOT_LOG_FORMAT=json` logs one JSON object per line.
+/// - `OTEL_EXPORTER_OTLP_ENDPOINT`, e.g. `http://localhost:4318`, also exports
+///   spans to an OpenTelemetry collector over OTLP/HTTP. The returned
+///   provider has to be shut down to flush them.
+pub fn init() -> eyre::Result<Option<SdkTracerProvider>> {
+  let filter = EnvFilter::try_from_default_env()
+    .unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+
+  let format = match std::env::var("KBOT_LOG_FORMAT").as_deref() {
+    Ok("json") => fmt::layer().json().boxed(),
+    _ => fmt::layer().boxed(),
+  };
+
+  let provider = match std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT") {
+    Some(_) => {
+      // The exporter reads the endpoint from the environment itself.
+      let exporter = SpanExporter::builder().with_http().build()?;
+
+      Some(
+        SdkTracerProvider::builder()
+          .with_batch_exporter(exporter)
+          .with_resource(
+            Resource::builder()
+              .with_service_name("kbot-control")
+              .build(),
+          )
+          .build(),
+      )
+    }
+    None => None,
+  };
+  let otel = provider.as_ref().map(|provider| {
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("control"))
+  });
+
+  tracing_subscriber::registry()
+    .with(filter)
+    .with(format)
+    .with(otel)
+    .try_init()?;
+
+  Ok(provider)
+}
+
+/// Runs every request in a span named after its route.
+pub async fn trace_request(request: Request, next: Next) -> Response {
+  let path = request
+    .extensions()
+    .get::<MatchedPath>()
+    .map(|path| path.as_str().to_string())
+    .unwrap_or_else(|| request.uri().path().to_string());
+  let span = tracing::info_span!("request", method = %request.method(), path);
+
+  async move {
+    let response = next.run(request).await;
+    tracing::debug!(status = %response.status(), "Handled request");
+
+    response
+  }
+  .instrument(span)
+  .await
+}
@@ -1,742 +1,548 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+  sync::Arc,
+  time::{Duration, Instant},
+};
 
-use axum::{extract::State, routing::post, Json, Router};
-use kos::hal::{GetActuatorsStateRequest, ModelUids};
+use axum::{
+  extract::{DefaultBodyLimit, State},
+  routing::{get, post},
+  Json, Router,
+};
 use rpc::{
-  proto::actuator::ConfigureActuatorRequest, ActuatorCommand, Axis, Client,
-  CommandActuatorsRequest, Config, JointCommand, KBot, Robot,
+  arbiter::Priority,
+  collision::CollisionConfig,
+  fall::FallConfig,
+  health::HealthConfig,
+  ik::IkConfig,
+  policy::{
+    policy_actuator_ids, HttpPolicy, KosPolicy, Observation, PolicyActions,
+    WalkPolicy, POLICY_ACTUATORS,
+  },
+  pose::{JointGroup, Pose},
+  processes::VideoStreamConfig,
+  profiler::{ProfilerConfig, TickTimer},
+  recorder::{LoopTiming, RecorderConfig, Topic},
+  self_test::SelfTestConfig,
+  sim::SimConfig,
+  telemetry::TelemetryConfig,
+  velocity::VelocityConfig,
+  watchdog::{ControllerHandle, WatchdogConfig},
+  Axis, Config, Joint, JointCommand, KBot, Robot,
 };
-use serde_json::json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+mod animation;
+mod arbiter;
+mod calibration;
+mod error;
+mod events;
+mod fall;
+mod foxglove;
+mod gains;
+mod health;
+mod ik;
+mod logging;
+mod metrics;
+mod models;
+mod pose;
+mod processes;
+mod profiler;
+mod recording;
+mod self_test;
+mod telemetry;
+mod velocity;
+mod watchdog;
+
+use error::AppError;
+
+/// Upper bound on uploaded model files.
+const MAX_MODEL_SIZE: usize = 64 * 1024 * 1024;
 
 #[tokio::main]
 async fn main() {
+  let tracer_provider = logging::init().unwrap();
+
   // let app = rpc
   // let client = rpc::Client::connect("grpc://10.33.85.8:50051")
   //   .await
   //   .unwrap();
 
-  // println!("Connected");
-
   let kbot = KBot::connect(
     "grpc://10.33.85.8:50051".to_string(),
     Config {
       server_url: "example.com".to_string(),
       imu_poll_interval_ms: 1000,
+      calibration_path: "calibration.json".into(),
+      gain_profiles_path: "gains.json".into(),
+      // e.g. KBOT_DESCRIPTION=kbot.urdf
+      robot_description_path: std::env::var_os("KBOT_DESCRIPTION")
+        .map(Into::into),
+      health: HealthConfig::default(),
+      // e.g. KBOT_SELF_TEST=1
+      self_test: std::env::var_os("KBOT_SELF_TEST")
+        .map(|_| SelfTestConfig::default()),
+      fall: FallConfig::default(),
+      watchdog: WatchdogConfig::default(),
+      telemetry: TelemetryConfig::default(),
+      ik: IkConfig::default(),
+      collision: CollisionConfig::default(),
+      simulator: std::env::var_os("KBOT_SIMULATOR")
+        .map(|_| SimConfig::default()),
+      recorder: RecorderConfig::default(),
+      profiler: ProfilerConfig::default(),
+      velocity: VelocityConfig::default(),
+      video: VideoStreamConfig {
+        // e.g. KBOT_CAMERA_URL=rtsp://127.0.0.1:8554/camera
+        source_url: std::env::var("KBOT_CAMERA_URL").ok(),
+        ..VideoStreamConfig::default()
+      },
     },
   )
   .await
   .unwrap();
 
-  println!("Connected");
+  tracing::info!("Connected");
+
+  let kbot = Arc::new(kbot);
+
+  // e.g. KBOT_FOXGLOVE_ADDR=0.0.0.0:8765
+  if let Some(addr) = std::env::var("KBOT_FOXGLOVE_ADDR")
+    .ok()
+    .and_then(|addr| addr.parse().ok())
+  {
+    let kbot = kbot.clone();
+    tokio::spawn(async move {
+      if let Err(e) = foxglove::serve(kbot, addr).await {
+        tracing::error!(error = %e, "Foxglove server stopped");
+      }
+    });
+  }
 
   let app = Router::new()
     .route("/dab", post(dab))
     .route("/muscles", post(muscles))
     .route("/walk", post(walk))
+    .route("/walk/command", get(velocity::status).post(velocity::set))
+    .route("/walk/stop", post(velocity::end))
+    .route("/walk/command/stop", post(velocity::stop))
     .route("/zero", post(zero))
     .route("/info", axum::routing::get(info))
     .route("/test", post(test))
-    .with_state(Arc::new(kbot));
+    .route("/models", get(models::list))
+    .route(
+      "/models",
+      post(models::upload).layer(DefaultBodyLimit::max(MAX_MODEL_SIZE)),
+    )
+    .route("/models/{uid}/load", post(models::load))
+    .route("/models/{uid}/unload", post(models::unload))
+    .route("/models/{uid}/forward", post(models::forward))
+    .route("/processes/kclip/start", post(processes::start_kclip))
+    .route("/processes/kclip/stop", post(processes::stop_kclip))
+    .route(
+      "/processes/video/start",
+      post(processes::start_video_stream),
+    )
+    .route("/processes/video/stop", post(processes::stop_video_stream))
+    .route("/calibration", get(calibration::status))
+    .route("/calibration/start", post(calibration::start))
+    .route("/calibration/prepare", post(calibration::prepare))
+    .route("/calibration/zero", post(calibration::zero))
+    .route("/calibration/skip", post(calibration::skip))
+    .route("/gains", get(gains::status))
+    .route("/gains/{name}", post(gains::apply))
+    .route("/health", get(health::report))
+    .route("/metrics", get(metrics::export))
+    .route("/health/{actuator_id}/reset", post(health::reset))
+    .route("/self-test", get(self_test::last))
+    .route("/self-test", post(self_test::run))
+    .route("/events", get(events::stream))
+    .route("/fall", get(fall::status))
+    .route("/fall/reset", post(fall::reset))
+    .route("/owners", get(arbiter::owners))
+    .route("/pose", get(pose::current).post(pose::apply))
+    .route("/pose/hold", post(pose::hold))
+    .route("/pose/check", post(pose::check))
+    .route("/animation", post(animation::play))
+    .route("/description", get(description))
+    .route("/kinematics", get(telemetry::end_effectors))
+    .route("/ik/{limb}", post(ik::solve))
+    .route("/reach/{limb}", post(ik::reach))
+    .route("/telemetry", get(telemetry::stream))
+    .route("/profiler", get(profiler::report))
+    .route("/profiler/reset", post(profiler::reset))
+    .route("/recording", get(recording::status))
+    .route("/recording/start", post(recording::start))
+    .route("/recording/stop", post(recording::stop))
+    .route("/recording/replay", post(recording::replay))
+    .route("/recording/export", post(recording::export))
+    .route("/watchdog", get(watchdog::status))
+    .route("/watchdog/{name}/heartbeat", post(watchdog::heartbeat))
+    .route("/watchdog/{name}/reset", post(watchdog::reset))
+    .layer(axum::middleware::from_fn(logging::trace_request))
+    .with_state(kbot);
 
   let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-  println!("Listening on port 3000");
+  tracing::info!("Listening on port 3000");
   axum::serve(listener, app).await.unwrap();
 
+  if let Some(provider) = tracer_provider {
+    provider.shutdown().ok();
+  }
+
   // muscles(&kbot).await;
 }
 
-pub async fn dab(State(kbot): State<Arc<rpc::KBot>>) {
-  kbot
-    .command_joint(
-      rpc::Joint::RightShoulder,
-      Some(rpc::Axis::Pitch),
-      JointCommand {
-        position: Some(90.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+pub async fn dab(State(kbot): State<Arc<rpc::KBot>>) -> Result<(), AppError> {
+  let lease = kbot.arbiter.acquire(
+    "dab",
+    Priority::Pose,
+    JointGroup::Arms.actuators::<KBot>(),
+  )?;
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightShoulder,
-      Some(rpc::Axis::Yaw),
-      JointCommand {
-        position: Some(-10.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+  let right = Pose::new()
+    .with(Joint::RightShoulder, Some(Axis::Pitch), 90.)
+    .with(Joint::RightShoulder, Some(Axis::Yaw), -10.)
+    .with(Joint::RightElbow, Some(Axis::Yaw), -90.);
+  let left = Pose::new()
+    .with(Joint::LeftShoulder, Some(Axis::Pitch), 0.)
+    .with(Joint::LeftShoulder, Some(Axis::Yaw), -20.);
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightElbow,
-      Some(rpc::Axis::Yaw),
-      JointCommand {
-        position: Some(-90.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+  kbot.move_to(&lease, right.merge(&left)).await?;
 
-  // left
-  kbot
-    .command_joint(
-      rpc::Joint::LeftShoulder,
-      Some(rpc::Axis::Pitch),
-      JointCommand {
-        position: Some(0.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+  Ok(())
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftShoulder,
-      Some(rpc::Axis::Yaw),
-      JointCommand {
-        position: Some(-20.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+pub async fn description(State(kbot): State<Arc<rpc::KBot>>) -> Json<Value> {
+  Json(json!({
+    "description": kbot.description,
+    "kinematics": kbot.kinematics.as_deref(),
+  }))
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftElbow,
-      Some(rpc::Axis::Yaw),
-      JointCommand {
-        position: Some(30.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+pub async fn info(
+  State(kbot): State<Arc<rpc::KBot>>,
+) -> Result<Json<Value>, AppError> {
+  Ok(Json(json!({
+    "processes": kbot.process_manager.status().await,
+    "owners": kbot.arbiter.owners(),
+  })))
 }
 
-pub async fn info(State(kbot): State<Arc<rpc::KBot>>) {
-  let out = kbot
-    .client
-    .actuator
-    .lock()
-    .await
-    .get_actuators_state(GetActuatorsStateRequest {
-      actuator_ids: vec![33],
-    })
-    .await
-    .unwrap();
+#[derive(Deserialize, Debug, Default)]
+pub struct WalkRequest {
+  /// Run the policy on the robot's inference service instead of the
+  /// off-board Python server.
+  model_uid: Option<String>,
+  /// Stop walking after this long. Without it the walk runs until
+  /// `/walk/stop`, a fall or an error ends it.
+  duration_ms: Option<u64>,
+}
 
-  println!("{:?}", out);
+/// The policy on the robot's inference service if a model is given, the
+/// off-board Python server otherwise.
+pub fn walk_policy(
+  kbot: &KBot,
+  model_uid: Option<String>,
+) -> eyre::Result<WalkPolicy> {
+  Ok(match model_uid {
+    Some(model_uid) => {
+      WalkPolicy::Kos(KosPolicy::new(kbot.client()?.clone(), model_uid))
+    }
+    None => WalkPolicy::Http(HttpPolicy::new("http://localhost:4242/infer")),
+  })
 }
 
-pub async fn walk(State(kbot): State<Arc<rpc::KBot>>) {
-  let start = std::time::Instant::now();
+#[tracing::instrument(skip_all, fields(model_uid))]
+pub async fn walk(
+  State(kbot): State<Arc<rpc::KBot>>,
+  request: Option<Json<WalkRequest>>,
+) -> Result<(), AppError> {
+  let request = request.map(|Json(request)| request).unwrap_or_default();
+  if let Some(model_uid) = &request.model_uid {
+    tracing::Span::current().record("model_uid", model_uid);
+  }
+
+  let mut policy = walk_policy(&kbot, request.model_uid)?;
+
+  let controller = kbot.acquire_controller(
+    "walk",
+    Priority::Policy,
+    POLICY_ACTUATORS
+      .iter()
+      .filter_map(|(joint, axis)| KBot::get_actuator_id(*joint, Some(*axis))),
+  )?;
+
+  kbot.walk_command.start();
+
+  let duration = request.duration_ms.map(Duration::from_millis);
+  let start = Instant::now();
   let mut last_iteration = Instant::now();
-  // kbot
-  //   .command_joint(
-  //     rpc::Joint::LeftAnkle,
-  //     Some(Axis::Pitch),
-  //     JointCommand {
-  //       position: Some(0.),
-  //       torque: None,
-  //       velocity: None,
-  //     },
-  //   )
-  //   .await
-  //   .unwrap();
-  loop {
-    println!("ELAPSED {:?}", last_iteration.elapsed());
+  let mut last_period = None;
+  for iteration in 0.. {
+    let period = last_iteration.elapsed();
     last_iteration = Instant::now();
 
-    // if time is greater than 5 seconds, break
-    if start.elapsed().as_secs() > 5 {
+    if kbot.walk_command.ending()
+      || duration.is_some_and(|duration| start.elapsed() >= duration)
+    {
+      tracing::info!(elapsed = ?start.elapsed(), "Walk finished");
       break;
     }
-    let data = kbot
-      .imu
-      .lock()
-      .await
-      .get_values(())
-      .await
-      .expect("failed to read IMU data :(");
-
-    let data = data.into_inner();
-
-    // kbot.inference.lock().await.load_models(ModelUids::)
-
-    //   "R_Hip_Pitch",
-    // "L_Hip_Pitch",
-    // "R_Hip_Yaw",
-    // "L_Hip_Yaw",
-    // "R_Hip_Roll",
-    // "L_Hip_Roll",
-    // "R_Knee_Pitch",
-    // "L_Knee_Pitch",
-    // "R_Ankle_Pitch",
-    // "L_Ankle_Pitch",
-
-    let actuators = vec![
-      KBot::get_actuator_id(rpc::Joint::RightHip, Some(Axis::Pitch)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::LeftHip, Some(Axis::Pitch)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::RightHip, Some(Axis::Yaw)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::LeftHip, Some(Axis::Yaw)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::RightHip, Some(Axis::Roll)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::LeftHip, Some(Axis::Roll)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::RightKnee, Some(Axis::Pitch)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::LeftKnee, Some(Axis::Pitch)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::RightAnkle, Some(Axis::Pitch)).unwrap(),
-      KBot::get_actuator_id(rpc::Joint::LeftAnkle, Some(Axis::Pitch)).unwrap(),
-    ];
-
-    let mut client = kbot.actuator.lock().await;
-
-    let Ok(states) = client
-      .get_actuators_state(GetActuatorsStateRequest {
-        actuator_ids: actuators,
-      })
-      .await
-    else {
-      eprintln!("damn it failed");
-      continue;
-    };
 
-    // println!("STATE: {:?}", states);
-
-    let states = states.into_inner();
-    // kbot.actuator.lock().a
-
-    let json_val = json!({
-      "base_ang_vel": [data.gyro_x, data.gyro_y, data.gyro_z],
-      "accel": [data.accel_x, data.accel_y, data.accel_z],
-      "commands": [
-        0.6, 0, 0
-      ],
-      "dof_pos": states.states.iter().map(|state|{
-        state.position()
-      }).collect::<Vec<f64>>(),
-      "dof_vel": states.states.iter().map(|state|{
-        state.velocity()
-      }).collect::<Vec<f64>>(),
-      "actions": [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
-    });
+    if let Err(e) = kbot.fall_detector.check() {
+      tracing::warn!("Fall detected, stopping walk");
+      kbot.profiler.log("walk");
+      return Err(e.into());
+    }
 
-    let response = reqwest::Client::new()
-      .post("http://localhost:4242/infer")
-      .json(&json_val)
-      .send()
-      .await;
-
-    let response = response.unwrap();
-    let joints: JointAngles = response.json().await.unwrap();
-    println!("SUCCESSFULY PARSED {:?}", joints);
-
-    // kbot
-    //   .command_joint(
-    //     rpc::Joint::LeftAnkle,
-    //     Some(Axis::Pitch),
-    //     JointCommand {
-    //       position: Some(joints.l_ankle_pitch),
-    //       velocity: None,
-    //       torque: None,
-    //     },
-    //   )
-    //   .await
-    //   .unwrap()
-    drop(client);
-    let kbot = kbot.clone();
-    // kbot
-    //   .command_joint(
-    //     rpc::Joint::LeftHip,
-    //     Some(Axis::Yaw),
-    //     JointCommand {
-    //       position: Some(100.0),
-    //       torque: None,
-    //       velocity: None,
-    //     },
-    //   )
-    //   .await
-    //   .unwrap();
-    command_all_joints(joints, &kbot.clone()).await.unwrap();
-    println!("COMMANDS SENT");
+    match walk_tick(&kbot, &mut policy, &controller, iteration, period).await {
+      WalkTick::Done { inference } => {
+        kbot.metrics.walk_tick(period, last_period, inference);
+        last_period = Some(period);
+      }
+      WalkTick::Skipped => continue,
+      // Dropping the controller lets the watchdog secure the legs.
+      WalkTick::Failed(e) => {
+        kbot.profiler.log("walk");
+        return Err(e.into());
+      }
+    }
   }
-}
 
-pub async fn zero(State(kbot): State<Arc<rpc::KBot>>) {
-  println!("TRYING TO ZERO");
+  controller.finish();
+  kbot.profiler.log("walk");
 
-  // Left arm joints
-  kbot
-    .command_joint(
-      rpc::Joint::LeftShoulder,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  Ok(())
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftShoulder,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+/// Outcome of one iteration of the walk loop.
+enum WalkTick {
+  Done {
+    inference: Duration,
+  },
+  /// The sensors could not be read; try again on the next iteration.
+  Skipped,
+  /// The walk has to stop.
+  Failed(eyre::Report),
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftElbow,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+#[tracing::instrument(
+  skip(kbot, policy, controller, period),
+  fields(period_ms = period.as_secs_f64() * 1000.0),
+)]
+async fn walk_tick(
+  kbot: &KBot,
+  policy: &mut WalkPolicy,
+  controller: &ControllerHandle,
+  iteration: u64,
+  period: Duration,
+) -> WalkTick {
+  let mut tick = kbot.profiler.tick("walk");
+
+  let obs = match read_observation(kbot, &mut tick).await {
+    Ok(obs) => obs,
+    Err(e) => {
+      tracing::warn!(error = %e, "Failed to read observation");
+      kbot.metrics.error("observation");
+      return WalkTick::Skipped;
+    }
+  };
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftGripper,
-      None,
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  kbot.recorder.record(Topic::PolicyObservation, &obs);
 
-  // Right arm joints
-  kbot
-    .command_joint(
-      rpc::Joint::RightShoulder,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  let joints = match tick.time("inference", policy.infer(&obs)).await {
+    Ok(joints) => joints,
+    Err(e) => {
+      tracing::error!(error = %e, "Policy inference failed");
+      kbot.metrics.error("policy");
+      return WalkTick::Failed(e.wrap_err("Policy inference failed"));
+    }
+  };
+  let inference = tick.stage("inference");
+  match PolicyActions::new::<KBot>(&joints) {
+    Ok(actions) => kbot.recorder.record(Topic::PolicyActions, &actions),
+    Err(e) => tracing::warn!(error = %e, "Failed to record policy actions"),
+  }
+  kbot.recorder.record(
+    Topic::LoopTiming,
+    &LoopTiming {
+      period_ms: period.as_secs_f64() * 1000.0,
+      inference_ms: inference.as_secs_f64() * 1000.0,
+    },
+  );
+  tracing::debug!(?joints, ?inference, "Policy inferred joint targets");
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightShoulder,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
+  if let Err(e) = tick
+    .time(
+      "command",
+      controller.command_joints(kbot, joints.into_commands()),
     )
     .await
-    .unwrap();
+  {
+    tracing::error!(error = %e, "Failed to command joints");
+    kbot.metrics.error("command");
+    return WalkTick::Failed(e.wrap_err("Failed to command joints"));
+  }
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightElbow,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  WalkTick::Done { inference }
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightGripper,
-      None,
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
+/// Reads the sensors for the walk policy, timing each read in `tick`.
+async fn read_observation(
+  kbot: &KBot,
+  tick: &mut TickTimer<'_>,
+) -> eyre::Result<Observation> {
+  let commands = kbot.walk_command.step().to_array();
+  let data = tick.time("imu", kbot.hal.imu.get_values()).await?;
+  let states = tick
+    .time(
+      "states",
+      kbot
+        .hal
+        .actuators
+        .get_actuators_state(policy_actuator_ids::<KBot>()?),
     )
-    .await
-    .unwrap();
+    .await?;
 
-  // Left leg joints
-  kbot
-    .command_joint(
-      rpc::Joint::LeftHip,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  Observation::from_readings::<KBot>(&data, &states, commands)
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftHip,
-      Some(Axis::Roll),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+#[tracing::instrument(skip_all)]
+pub async fn zero(State(kbot): State<Arc<rpc::KBot>>) -> Result<(), AppError> {
+  let lease = kbot.arbiter.acquire(
+    "zero",
+    Priority::Pose,
+    JointGroup::WholeBody.actuators::<KBot>(),
+  )?;
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftHip,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  tracing::info!("Zeroing all joints");
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftKnee,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  let pose = JointGroup::WholeBody
+    .axes::<KBot>()
+    .into_iter()
+    .map(|joint| (joint, 0.0))
+    .collect();
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftAnkle,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  kbot.move_to(&lease, pose).await?;
 
-  // Right leg joints
-  kbot
-    .command_joint(
-      rpc::Joint::RightHip,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  Ok(())
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightHip,
-      Some(Axis::Roll),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+pub async fn muscles(
+  State(kbot): State<Arc<rpc::KBot>>,
+) -> Result<(), AppError> {
+  let lease = kbot.arbiter.acquire(
+    "muscles",
+    Priority::Pose,
+    JointGroup::Arms.actuators::<KBot>(),
+  )?;
+
+  let right = Pose::new()
+    .with(Joint::RightElbow, Some(Axis::Yaw), 180.)
+    .with(Joint::RightShoulder, Some(Axis::Yaw), 90.)
+    .with(Joint::RightShoulder, Some(Axis::Pitch), 0.);
+  let left = Pose::new()
+    .with(Joint::LeftShoulder, Some(Axis::Pitch), 0.)
+    .with(Joint::LeftShoulder, Some(Axis::Yaw), -90.);
+
+  kbot.move_to(&lease, right.merge(&left)).await?;
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightHip,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+  Ok(())
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightKnee,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+pub async fn test(State(kbot): State<Arc<rpc::KBot>>) -> Result<(), AppError> {
+  let lease = kbot.arbiter.acquire(
+    "test",
+    Priority::Pose,
+    KBot::get_actuator_id(rpc::Joint::LeftAnkle, Some(Axis::Pitch)),
+  )?;
 
   kbot
     .command_joint(
-      rpc::Joint::RightAnkle,
+      &lease,
+      rpc::Joint::LeftAnkle,
       Some(Axis::Pitch),
       JointCommand {
-        position: Some(0.0),
+        position: Some(20.0),
         torque: None,
         velocity: None,
       },
     )
-    .await
-    .unwrap();
-}
+    .await?;
 
-pub async fn muscles(State(kbot): State<Arc<rpc::KBot>>) {
-  kbot
-    .command_joint(
-      rpc::Joint::RightElbow,
-      Some(rpc::Axis::Yaw),
-      JointCommand {
-        position: Some(180.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
-
-  kbot
-    .command_joint(
-      rpc::Joint::RightShoulder,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(90.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+  Ok(())
+}
 
-  kbot
-    .command_joint(
-      rpc::Joint::RightShoulder,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(0.),
-        velocity: None,
-        torque: None,
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A robot on the simulator, without a KOS connection or files on disk.
+  pub async fn simulated() -> Arc<KBot> {
+    let kbot = KBot::connect(
+      String::new(),
+      Config {
+        server_url: String::new(),
+        imu_poll_interval_ms: 1000,
+        calibration_path: "/nonexistent/calibration.json".into(),
+        gain_profiles_path: "/nonexistent/gains.json".into(),
+        robot_description_path: None,
+        health: HealthConfig::default(),
+        self_test: None,
+        fall: FallConfig::default(),
+        watchdog: WatchdogConfig::default(),
+        telemetry: TelemetryConfig::default(),
+        ik: IkConfig::default(),
+        collision: CollisionConfig::default(),
+        simulator: Some(SimConfig::default()),
+        recorder: RecorderConfig::default(),
+        profiler: ProfilerConfig::default(),
+        velocity: VelocityConfig::default(),
+        video: VideoStreamConfig::default(),
       },
     )
     .await
     .unwrap();
 
-  // left
+    Arc::new(kbot)
+  }
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftShoulder,
-      Some(rpc::Axis::Pitch),
-      JointCommand {
-        position: Some(0.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+  #[tokio::test]
+  async fn zero_and_dab_move_the_simulated_robot() {
+    let kbot = simulated().await;
+    let settle = || tokio::time::sleep(Duration::from_millis(600));
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftShoulder,
-      Some(rpc::Axis::Yaw),
-      JointCommand {
-        position: Some(-90.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
+    assert!(dab(State(kbot.clone())).await.is_ok());
+    settle().await;
 
-  kbot
-    .command_joint(
-      rpc::Joint::LeftElbow,
-      Some(rpc::Axis::Yaw),
-      JointCommand {
-        position: Some(30.),
-        velocity: None,
-        torque: None,
-      },
-    )
-    .await
-    .unwrap();
-}
+    let arms = kbot.read_pose(JointGroup::Arms).await.unwrap();
+    let pitch = arms.get(Joint::RightShoulder, Some(Axis::Pitch)).unwrap();
+    let elbow = arms.get(Joint::RightElbow, Some(Axis::Yaw)).unwrap();
+    assert!((pitch - 90.0).abs() < 1.0, "right shoulder at {pitch}°");
+    assert!((elbow + 90.0).abs() < 1.0, "right elbow at {elbow}°");
 
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Debug)]
-struct JointAngles {
-  #[serde(rename = "L_Ankle_Pitch")]
-  l_ankle_pitch: f64,
-  #[serde(rename = "L_Hip_Pitch")]
-  l_hip_pitch: f64,
-  #[serde(rename = "L_Hip_Yaw")] // SWAPPED WITH ROLL
-  l_hip_roll: f64,
-  #[serde(rename = "L_Hip_Roll")]
-  l_hip_yaw: f64,
-  #[serde(rename = "L_Knee_Pitch")]
-  l_knee_pitch: f64,
-  #[serde(rename = "R_Ankle_Pitch")]
-  r_ankle_pitch: f64,
-  #[serde(rename = "R_Hip_Pitch")]
-  r_hip_pitch: f64,
-  #[serde(rename = "R_Hip_Yaw")] // SWAPPED WITH ROLL
-  r_hip_roll: f64,
-  #[serde(rename = "R_Hip_Roll")]
-  r_hip_yaw: f64,
-  #[serde(rename = "R_Knee_Pitch")]
-  r_knee_pitch: f64,
-}
+    assert!(zero(State(kbot.clone())).await.is_ok());
+    settle().await;
 
-use futures::future;
+    let pose = kbot.read_pose(JointGroup::WholeBody).await.unwrap();
+    assert_eq!(pose.len(), KBot::list_actuator_ids().len());
+    for (joint, position) in pose.iter() {
+      assert!(position.abs() < 1.0, "{joint} at {position}°");
+    }
+    assert!(kbot.arbiter.owners().is_empty());
+  }
 
-async fn command_all_joints(
-  joints: JointAngles,
-  kbot: &KBot,
-) -> Result<(), Box<dyn std::error::Error>> {
-  let commands = vec![
-    kbot.command_joint(
-      rpc::Joint::LeftAnkle,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(joints.l_ankle_pitch),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::LeftHip,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(joints.l_hip_pitch),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::LeftHip,
-      Some(Axis::Roll),
-      JointCommand {
-        position: Some(joints.l_hip_roll),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::LeftHip,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(joints.l_hip_yaw),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::LeftKnee,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(joints.l_knee_pitch),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::RightAnkle,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(joints.r_ankle_pitch),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::RightHip,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(joints.r_hip_pitch),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::RightHip,
-      Some(Axis::Roll),
-      JointCommand {
-        position: Some(joints.r_hip_roll),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::RightHip,
-      Some(Axis::Yaw),
-      JointCommand {
-        position: Some(joints.r_hip_yaw),
-        velocity: None,
-        torque: None,
-      },
-    ),
-    kbot.command_joint(
-      rpc::Joint::RightKnee,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(joints.r_knee_pitch),
-        velocity: None,
-        torque: None,
-      },
-    ),
-  ];
+  #[tokio::test]
+  async fn info_reports_processes_and_owners() {
+    let kbot = simulated().await;
+    let _lease = kbot.arbiter.acquire("test", Priority::Pose, [34]).unwrap();
 
-  future::try_join_all(commands).await?;
-  Ok(())
-}
+    let Ok(Json(info)) = info(State(kbot)).await else {
+      panic!("info failed");
+    };
 
-pub async fn test(State(kbot): State<Arc<rpc::KBot>>) {
-  kbot
-    .command_joint(
-      rpc::Joint::LeftAnkle,
-      Some(Axis::Pitch),
-      JointCommand {
-        position: Some(20.0),
-        torque: None,
-        velocity: None,
-      },
-    )
-    .await
-    .unwrap();
+    assert!(info["processes"].is_object());
+    assert_eq!(info["owners"][0]["controller"], "test");
+  }
 }
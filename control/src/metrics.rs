@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use rpc::KBot;
+
+use crate::error::AppError;
+
+/// Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+pub async fn export(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<impl IntoResponse, AppError> {
+  let tripped = kbot
+    .watchdog
+    .status()
+    .iter()
+    .filter(|controller| controller.tripped)
+    .count();
+  kbot
+    .metrics
+    .set_safety(kbot.fall_detector.has_fallen(), tripped);
+
+  Ok((
+    [(header::CONTENT_TYPE, CONTENT_TYPE)],
+    kbot.metrics.encode()?,
+  ))
+}
@@ -0,0 +1,210 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+  body::Bytes,
+  extract::{Path, Query, State},
+  Json,
+};
+use rpc::{
+  inference::ModelSummary,
+  proto::inference::{tensor::Dimension, ModelMetadata, Tensor},
+  KBot,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+#[derive(Deserialize, Debug)]
+pub struct UploadParams {
+  name: Option<String>,
+  description: Option<String>,
+  version: Option<String>,
+  author: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UploadResponse {
+  uid: String,
+}
+
+/// A tensor as JSON: the size of each dimension and the values in row-major
+/// order.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TensorJson {
+  shape: Vec<u32>,
+  values: Vec<f32>,
+}
+
+impl TensorJson {
+  fn into_tensor(self, name: &str) -> eyre::Result<Tensor> {
+    let size = self.shape.iter().product::<u32>() as usize;
+    if size != self.values.len() {
+      return Err(eyre::eyre!(
+        "Input {name} has shape {:?} but {} values",
+        self.shape,
+        self.values.len()
+      ));
+    }
+
+    Ok(Tensor {
+      shape: self
+        .shape
+        .into_iter()
+        .map(|size| Dimension {
+          size,
+          name: String::new(),
+          dynamic: false,
+        })
+        .collect(),
+      values: self.values,
+    })
+  }
+}
+
+impl From<Tensor> for TensorJson {
+  fn from(tensor: Tensor) -> Self {
+    Self {
+      shape: tensor
+        .shape
+        .iter()
+        .map(|dimension| dimension.size)
+        .collect(),
+      values: tensor.values,
+    }
+  }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ForwardRequest {
+  inputs: HashMap<String, TensorJson>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ForwardResponse {
+  outputs: HashMap<String, TensorJson>,
+}
+
+pub async fn list(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<Vec<ModelSummary>>, AppError> {
+  Ok(Json(kbot.client()?.list_models().await?))
+}
+
+pub async fn upload(
+  State(kbot): State<Arc<KBot>>,
+  Query(params): Query<UploadParams>,
+  model: Bytes,
+) -> Result<Json<UploadResponse>, AppError> {
+  let metadata = ModelMetadata {
+    model_name: params.name,
+    model_description: params.description,
+    model_version: params.version,
+    model_author: params.author,
+  };
+
+  let uid = kbot
+    .client()?
+    .upload_model(model.to_vec(), Some(metadata))
+    .await?;
+
+  Ok(Json(UploadResponse { uid }))
+}
+
+pub async fn load(
+  State(kbot): State<Arc<KBot>>,
+  Path(uid): Path<String>,
+) -> Result<Json<Vec<ModelSummary>>, AppError> {
+  Ok(Json(kbot.client()?.load_models(vec![uid]).await?))
+}
+
+pub async fn unload(
+  State(kbot): State<Arc<KBot>>,
+  Path(uid): Path<String>,
+) -> Result<(), AppError> {
+  kbot.client()?.unload_models(vec![uid]).await?;
+
+  Ok(())
+}
+
+/// Runs a loaded model once on the given named inputs.
+pub async fn forward(
+  State(kbot): State<Arc<KBot>>,
+  Path(uid): Path<String>,
+  Json(request): Json<ForwardRequest>,
+) -> Result<Json<ForwardResponse>, AppError> {
+  let inputs = request
+    .inputs
+    .into_iter()
+    .map(|(name, tensor)| Ok((name.clone(), tensor.into_tensor(&name)?)))
+    .collect::<eyre::Result<HashMap<_, _>>>()?;
+
+  let outputs = kbot.client()?.forward(uid, inputs).await?;
+
+  Ok(Json(ForwardResponse {
+    outputs: outputs
+      .into_iter()
+      .map(|(name, tensor)| (name, tensor.into()))
+      .collect(),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::{http::StatusCode, response::IntoResponse};
+
+  use super::*;
+  use crate::tests::simulated;
+
+  #[test]
+  fn tensors_round_trip_and_need_a_value_per_element() {
+    let tensor = TensorJson {
+      shape: vec![1, 3],
+      values: vec![1.0, 2.0, 3.0],
+    };
+    let converted = tensor.clone().into_tensor("obs").unwrap();
+    assert_eq!(converted.shape[1].size, 3);
+    assert_eq!(TensorJson::from(converted), tensor);
+
+    let short = TensorJson {
+      shape: vec![2, 3],
+      values: vec![1.0],
+    };
+    let error = short.into_tensor("obs").unwrap_err();
+    assert!(error.to_string().contains("obs"), "{error}");
+  }
+
+  #[tokio::test]
+  async fn forward_checks_inputs_and_needs_kos() {
+    let kbot = simulated().await;
+    let request = |values: Vec<f32>| {
+      Json(ForwardRequest {
+        inputs: HashMap::from([(
+          "obs".to_string(),
+          TensorJson {
+            shape: vec![1, 2],
+            values,
+          },
+        )]),
+      })
+    };
+
+    for (values, message) in [
+      (vec![1.0], "Input obs has shape"),
+      (vec![1.0, 2.0], "Not connected to KOS"),
+    ] {
+      let Err(error) =
+        forward(State(kbot.clone()), Path("model".into()), request(values))
+          .await
+      else {
+        panic!("forward without KOS succeeded");
+      };
+      let response = error.into_response();
+      assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+      let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+      let body = String::from_utf8(body.to_vec()).unwrap();
+      assert!(body.contains(message), "{body}");
+    }
+  }
+}
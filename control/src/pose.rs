@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{
+  arbiter::Priority,
+  collision::MotionCheck,
+  pose::{JointGroup, Pose},
+  KBot,
+};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+pub async fn current(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<Pose>, AppError> {
+  Ok(Json(kbot.read_pose(JointGroup::WholeBody).await?))
+}
+
+pub async fn apply(
+  State(kbot): State<Arc<KBot>>,
+  Json(pose): Json<Pose>,
+) -> Result<Json<Pose>, AppError> {
+  let lease =
+    kbot
+      .arbiter
+      .acquire("pose", Priority::Pose, pose.actuators::<KBot>())?;
+
+  kbot.move_to(&lease, pose.clone()).await?;
+
+  Ok(Json(pose))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HoldRequest {
+  pub group: JointGroup,
+}
+
+pub async fn hold(
+  State(kbot): State<Arc<KBot>>,
+  Json(request): Json<HoldRequest>,
+) -> Result<Json<Pose>, AppError> {
+  let lease = kbot.arbiter.acquire(
+    "hold",
+    Priority::Pose,
+    request.group.actuators::<KBot>(),
+  )?;
+
+  Ok(Json(kbot.hold(&lease, request.group).await?))
+}
+
+/// Checks the motion from the current pose to `pose` for self-collisions
+/// without moving.
+pub async fn check(
+  State(kbot): State<Arc<KBot>>,
+  Json(pose): Json<Pose>,
+) -> Result<Json<MotionCheck>, AppError> {
+  let current = kbot.read_pose(JointGroup::WholeBody).await?;
+
+  Ok(Json(
+    kbot
+      .collision_checker()?
+      .check_motion(&current, &current.merge(&pose)),
+  ))
+}
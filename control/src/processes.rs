@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{processes::ProcessStatus, KBot};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct StartKClipRequest {
+  action: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct KClipResponse {
+  clip_uuid: Option<String>,
+}
+
+pub async fn start_kclip(
+  State(kbot): State<Arc<KBot>>,
+  request: Option<Json<StartKClipRequest>>,
+) -> Result<Json<KClipResponse>, AppError> {
+  let request = request.map(|Json(request)| request).unwrap_or_default();
+
+  let clip_uuid = kbot.process_manager.start_kclip(request.action).await?;
+
+  Ok(Json(KClipResponse { clip_uuid }))
+}
+
+pub async fn stop_kclip(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<KClipResponse>, AppError> {
+  let clip_uuid = kbot.process_manager.stop_kclip().await?;
+
+  Ok(Json(KClipResponse { clip_uuid }))
+}
+
+pub async fn start_video_stream(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<ProcessStatus>, AppError> {
+  kbot.process_manager.start_video_stream().await?;
+
+  Ok(Json(kbot.process_manager.status().await))
+}
+
+pub async fn stop_video_stream(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<ProcessStatus>, AppError> {
+  kbot.process_manager.stop_video_stream().await?;
+
+  Ok(Json(kbot.process_manager.status().await))
+}
@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{profiler::LoopReport, KBot};
+
+pub async fn report(State(kbot): State<Arc<KBot>>) -> Json<Vec<LoopReport>> {
+  Json(kbot.profiler.report())
+}
+
+pub async fn reset(State(kbot): State<Arc<KBot>>) -> Json<Vec<LoopReport>> {
+  kbot.profiler.reset();
+
+  Json(kbot.profiler.report())
+}
@@ -0,0 +1,86 @@
+use std::{path::PathBuf, sync::Arc};
+
+use axum::{extract::State, Json};
+use rpc::{
+  export::ExportFormat,
+  recorder::RecordingStatus,
+  replay::{Replay, ReplayReport},
+  KBot,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, walk_policy};
+
+#[derive(Deserialize, Debug, Default)]
+pub struct StartRequest {
+  /// File name without extension. Defaults to the current time.
+  name: Option<String>,
+}
+
+pub async fn status(
+  State(kbot): State<Arc<KBot>>,
+) -> Json<Option<RecordingStatus>> {
+  Json(kbot.recorder.status())
+}
+
+pub async fn start(
+  State(kbot): State<Arc<KBot>>,
+  request: Option<Json<StartRequest>>,
+) -> Result<Json<RecordingStatus>, AppError> {
+  let request = request.map(|Json(request)| request).unwrap_or_default();
+
+  Ok(Json(kbot.recorder.start(request.name)?))
+}
+
+pub async fn stop(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<RecordingStatus>, AppError> {
+  Ok(Json(kbot.recorder.stop().await?))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReplayRequest {
+  name: String,
+  /// Replay through this model on the robot's inference service instead of
+  /// the off-board Python server.
+  model_uid: Option<String>,
+}
+
+pub async fn replay(
+  State(kbot): State<Arc<KBot>>,
+  Json(request): Json<ReplayRequest>,
+) -> Result<Json<ReplayReport>, AppError> {
+  let replay = Replay::load::<KBot>(kbot.recorder.path(&request.name)?).await?;
+  let mut policy = walk_policy(&kbot, request.model_uid)?;
+
+  Ok(Json(replay.run::<KBot>(&mut policy).await?))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportRequest {
+  name: String,
+  format: ExportFormat,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportResponse {
+  path: PathBuf,
+  rows: usize,
+}
+
+/// Writes the recording as a dataset next to it, e.g. `<name>.parquet`.
+pub async fn export(
+  State(kbot): State<Arc<KBot>>,
+  Json(request): Json<ExportRequest>,
+) -> Result<Json<ExportResponse>, AppError> {
+  let input = kbot.recorder.path(&request.name)?;
+  let path = input.with_extension(request.format.extension());
+
+  let output = path.clone();
+  let rows = tokio::task::spawn_blocking(move || {
+    rpc::export::export::<KBot>(&input, &output, request.format)
+  })
+  .await??;
+
+  Ok(Json(ExportResponse { path, rows }))
+}
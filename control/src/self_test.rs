@@ -0,0 +1,24 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{
+  self_test::{SelfTestConfig, SelfTestReport},
+  KBot,
+};
+
+use crate::error::AppError;
+
+pub async fn last(
+  State(kbot): State<Arc<KBot>>,
+) -> Json<Option<SelfTestReport>> {
+  Json(kbot.last_self_test.lock().await.clone())
+}
+
+pub async fn run(
+  State(kbot): State<Arc<KBot>>,
+  config: Option<Json<SelfTestConfig>>,
+) -> Result<Json<SelfTestReport>, AppError> {
+  let config = config.map(|Json(config)| config).unwrap_or_default();
+
+  Ok(Json(kbot.self_test(&config).await?))
+}
@@ -0,0 +1,40 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+  extract::State,
+  response::sse::{Event, KeepAlive, Sse},
+  Json,
+};
+use futures::{stream, Stream};
+use rpc::{kinematics::EndEffector, KBot};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::error::AppError;
+
+/// Streams [`rpc::telemetry::TelemetryFrame`]s to the client as server-sent
+/// events.
+pub async fn stream(
+  State(kbot): State<Arc<KBot>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+  let frames =
+    stream::unfold(kbot.telemetry.subscribe(), |mut rx| async move {
+      loop {
+        match rx.recv().await {
+          Ok(frame) => {
+            let event = Event::default().json_data(&frame).unwrap_or_default();
+            return Some((Ok(event), rx));
+          }
+          Err(RecvError::Lagged(_)) => continue,
+          Err(RecvError::Closed) => return None,
+        }
+      }
+    });
+
+  Sse::new(frames).keep_alive(KeepAlive::default())
+}
+
+pub async fn end_effectors(
+  State(kbot): State<Arc<KBot>>,
+) -> Result<Json<Vec<EndEffector>>, AppError> {
+  Ok(Json(kbot.end_effectors().await?))
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use rpc::{
+  velocity::{VelocityCommand, VelocityStatus},
+  KBot,
+};
+
+use crate::error::AppError;
+
+pub async fn status(State(kbot): State<Arc<KBot>>) -> Json<VelocityStatus> {
+  Json(kbot.walk_command.status())
+}
+
+/// Sets the velocity the walk loop ramps towards. Values outside the limits
+/// are clamped; the response shows the target actually used.
+pub async fn set(
+  State(kbot): State<Arc<KBot>>,
+  Json(command): Json<VelocityCommand>,
+) -> Result<Json<VelocityStatus>, AppError> {
+  kbot.walk_command.set(command)?;
+
+  Ok(Json(kbot.walk_command.status()))
+}
+
+pub async fn stop(State(kbot): State<Arc<KBot>>) -> Json<VelocityStatus> {
+  kbot.walk_command.stop();
+
+  Json(kbot.walk_command.status())
+}
+
+/// Ends the running walk after its current tick.
+pub async fn end(State(kbot): State<Arc<KBot>>) -> Json<VelocityStatus> {
+  kbot.walk_command.end();
+
+  Json(kbot.walk_command.status())
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use axum::{
+  extract::{Path, State},
+  Json,
+};
+use rpc::{watchdog::ControllerStatus, KBot};
+
+use crate::error::AppError;
+
+pub async fn status(
+  State(kbot): State<Arc<KBot>>,
+) -> Json<Vec<ControllerStatus>> {
+  Json(kbot.watchdog.status())
+}
+
+pub async fn heartbeat(
+  State(kbot): State<Arc<KBot>>,
+  Path(name): Path<String>,
+) -> Result<(), AppError> {
+  kbot.watchdog.heartbeat(&name)?;
+
+  Ok(())
+}
+
+pub async fn reset(
+  State(kbot): State<Arc<KBot>>,
+  Path(name): Path<String>,
+) -> Result<Json<Vec<ControllerStatus>>, AppError> {
+  kbot.watchdog.reset(&name)?;
+
+  Ok(Json(kbot.watchdog.status()))
+}
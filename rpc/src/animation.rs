@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  arbiter::Lease, description::RobotDescription, pose::Pose, KBot, Robot,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Keyframe {
+  pub pose: Pose,
+  /// How long to wait after commanding `pose` before the next keyframe.
+  pub duration_ms: u64,
+}
+
+/// A sequence of poses played one after another.
+///
+/// ```json
+/// {
+///   "keyframes": [
+///     { "pose": { "right_shoulder_pitch": 90.0 }, "duration_ms": 500 },
+///     { "pose": { "right_elbow_yaw": -90.0 }, "duration_ms": 300 }
+///   ]
+/// }
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Animation {
+  pub keyframes: Vec<Keyframe>,
+}
+
+impl Animation {
+  /// Plays the animation on the other side of the body.
+  pub fn mirror<R: Robot>(
+    &self,
+    description: Option<&RobotDescription>,
+  ) -> Animation {
+    self.map(|pose| pose.mirror::<R>(description))
+  }
+
+  /// Plays every keyframe on both sides at once, see [`Pose::symmetric`].
+  pub fn symmetric<R: Robot>(
+    &self,
+    description: Option<&RobotDescription>,
+  ) -> Animation {
+    self.map(|pose| pose.symmetric::<R>(description))
+  }
+
+  /// Every actuator the animation moves.
+  pub fn actuators<R: Robot>(&self) -> Vec<u32> {
+    let mut actuators = self
+      .keyframes
+      .iter()
+      .flat_map(|keyframe| keyframe.pose.actuators::<R>())
+      .collect::<Vec<_>>();
+    actuators.sort_unstable();
+    actuators.dedup();
+    actuators
+  }
+
+  pub fn validate<R: Robot>(
+    &self,
+    description: Option<&RobotDescription>,
+  ) -> eyre::Result<()> {
+    for keyframe in &self.keyframes {
+      keyframe.pose.validate::<R>(description)?;
+    }
+
+    Ok(())
+  }
+
+  fn map(&self, f: impl Fn(&Pose) -> Pose) -> Animation {
+    Animation {
+      keyframes: self
+        .keyframes
+        .iter()
+        .map(|keyframe| Keyframe {
+          pose: f(&keyframe.pose),
+          duration_ms: keyframe.duration_ms,
+        })
+        .collect(),
+    }
+  }
+}
+
+impl KBot {
+  /// Validates `animation` up front, then plays it on behalf of `lease`.
+  pub async fn play(
+    &self,
+    lease: &Lease,
+    animation: &Animation,
+  ) -> eyre::Result<()> {
+    animation.validate::<Self>(self.description.as_ref())?;
+
+    for keyframe in &animation.keyframes {
+      self.move_to(lease, keyframe.pose.clone()).await?;
+      tokio::time::sleep(Duration::from_millis(keyframe.duration_ms)).await;
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,304 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+  },
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::events::RobotEvent;
+
+/// How much a motion source outranks others. Higher priorities preempt lower
+/// ones; equal priorities are rejected.
+#[derive(
+  Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+  /// Canned gestures and pose playback.
+  Pose,
+  /// Autonomous control loops such as the walking policy.
+  Policy,
+  /// Live operator control.
+  Teleop,
+  /// An operator physically guiding the robot.
+  Teach,
+}
+
+/// Returned when a claim conflicts with an equal or higher priority owner.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+  pub controller: String,
+  pub owner: String,
+  pub priority: Priority,
+  pub actuators: BTreeSet<u32>,
+}
+
+impl fmt::Display for Conflict {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} cannot take actuators {:?} from {} ({:?})",
+      self.controller, self.actuators, self.owner, self.priority
+    )
+  }
+}
+
+impl std::error::Error for Conflict {}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Owner {
+  pub controller: String,
+  pub priority: Priority,
+  pub actuators: BTreeSet<u32>,
+}
+
+#[derive(Debug)]
+struct Claim {
+  controller: String,
+  priority: Priority,
+  actuators: BTreeSet<u32>,
+  preempted_by: Option<String>,
+}
+
+/// Grants motion sources exclusive ownership of actuators.
+#[derive(Debug)]
+pub struct Arbiter {
+  events: broadcast::Sender<RobotEvent>,
+  next_id: AtomicU64,
+  claims: std::sync::Mutex<BTreeMap<u64, Claim>>,
+}
+
+impl Arbiter {
+  pub fn new(events: broadcast::Sender<RobotEvent>) -> Self {
+    Self {
+      events,
+      next_id: AtomicU64::new(0),
+      claims: std::sync::Mutex::new(BTreeMap::new()),
+    }
+  }
+
+  /// Claims `actuators` for `controller`, preempting lower priority owners.
+  /// The claim is held until the returned lease is dropped.
+  pub fn acquire(
+    self: &Arc<Self>,
+    controller: impl Into<String>,
+    priority: Priority,
+    actuators: impl IntoIterator<Item = u32>,
+  ) -> Result<Lease, Conflict> {
+    let controller = controller.into();
+    let actuators = actuators.into_iter().collect::<BTreeSet<_>>();
+    let mut claims = self.claims.lock().unwrap();
+
+    let overlapping = claims
+      .iter()
+      .filter(|(_, claim)| claim.preempted_by.is_none())
+      .filter(|(_, claim)| !claim.actuators.is_disjoint(&actuators))
+      .map(|(&id, claim)| (id, claim.priority))
+      .collect::<Vec<_>>();
+
+    if let Some(&(id, _)) =
+      overlapping.iter().find(|(_, owner)| *owner >= priority)
+    {
+      let claim = &claims[&id];
+      return Err(Conflict {
+        controller,
+        owner: claim.controller.clone(),
+        priority: claim.priority,
+        actuators: claim.actuators.intersection(&actuators).copied().collect(),
+      });
+    }
+
+    for (id, _) in overlapping {
+      let Some(claim) = claims.get_mut(&id) else {
+        continue;
+      };
+
+      claim.preempted_by = Some(controller.clone());
+      self
+        .events
+        .send(RobotEvent::ControllerPreempted {
+          controller: claim.controller.clone(),
+          by: controller.clone(),
+        })
+        .ok();
+    }
+
+    let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+    claims.insert(
+      id,
+      Claim {
+        controller: controller.clone(),
+        priority,
+        actuators: actuators.clone(),
+        preempted_by: None,
+      },
+    );
+
+    Ok(Lease {
+      id,
+      controller,
+      actuators,
+      arbiter: self.clone(),
+    })
+  }
+
+  /// Current owners, highest priority first.
+  pub fn owners(&self) -> Vec<Owner> {
+    let mut owners = self
+      .claims
+      .lock()
+      .unwrap()
+      .values()
+      .filter(|claim| claim.preempted_by.is_none())
+      .map(|claim| Owner {
+        controller: claim.controller.clone(),
+        priority: claim.priority,
+        actuators: claim.actuators.clone(),
+      })
+      .collect::<Vec<_>>();
+
+    owners.sort_by_key(|owner| std::cmp::Reverse(owner.priority));
+    owners
+  }
+
+  fn preempted_by(&self, id: u64) -> Option<String> {
+    self
+      .claims
+      .lock()
+      .unwrap()
+      .get(&id)
+      .and_then(|claim| claim.preempted_by.clone())
+  }
+
+  fn release(&self, id: u64) {
+    self.claims.lock().unwrap().remove(&id);
+  }
+}
+
+/// Ownership of a set of actuators, released on drop.
+#[derive(Debug)]
+pub struct Lease {
+  id: u64,
+  controller: String,
+  actuators: BTreeSet<u32>,
+  arbiter: Arc<Arbiter>,
+}
+
+impl Lease {
+  pub fn controller(&self) -> &str {
+    &self.controller
+  }
+
+  pub fn actuators(&self) -> &BTreeSet<u32> {
+    &self.actuators
+  }
+
+  pub fn is_preempted(&self) -> bool {
+    self.arbiter.preempted_by(self.id).is_some()
+  }
+
+  /// Fails if the lease was preempted or does not cover `actuators`.
+  pub fn check(&self, actuators: &[u32]) -> eyre::Result<()> {
+    if let Some(by) = self.arbiter.preempted_by(self.id) {
+      return Err(eyre::eyre!("{} was preempted by {by}", self.controller));
+    }
+
+    let foreign = actuators
+      .iter()
+      .filter(|id| !self.actuators.contains(id))
+      .collect::<Vec<_>>();
+    if !foreign.is_empty() {
+      return Err(eyre::eyre!(
+        "{} does not own actuators {foreign:?}",
+        self.controller
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+impl Drop for Lease {
+  fn drop(&mut self) {
+    self.arbiter.release(self.id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn arbiter() -> (Arc<Arbiter>, broadcast::Receiver<RobotEvent>) {
+    let (events, receiver) = broadcast::channel(16);
+
+    (Arc::new(Arbiter::new(events)), receiver)
+  }
+
+  #[test]
+  fn higher_priorities_preempt_lower_ones() {
+    let (arbiter, mut events) = arbiter();
+
+    let walk = arbiter.acquire("walk", Priority::Policy, [33, 34]).unwrap();
+    let teleop = arbiter.acquire("teleop", Priority::Teleop, [34]).unwrap();
+
+    assert!(walk.is_preempted());
+    assert!(!teleop.is_preempted());
+    assert!(matches!(
+      events.try_recv(),
+      Ok(RobotEvent::ControllerPreempted { controller, by })
+        if controller == "walk" && by == "teleop"
+    ));
+
+    let owners = arbiter.owners();
+    assert_eq!(owners.len(), 1);
+    assert_eq!(owners[0].controller, "teleop");
+  }
+
+  #[test]
+  fn equal_and_lower_priorities_conflict() {
+    let (arbiter, _) = arbiter();
+    let _walk = arbiter.acquire("walk", Priority::Policy, [33, 34]).unwrap();
+
+    let conflict = arbiter.acquire("dab", Priority::Policy, [34, 35]);
+    let conflict = conflict.unwrap_err();
+    assert_eq!(conflict.owner, "walk");
+    assert_eq!(conflict.priority, Priority::Policy);
+    assert_eq!(conflict.actuators, BTreeSet::from([34]));
+
+    assert!(arbiter.acquire("pose", Priority::Pose, [33]).is_err());
+    assert!(arbiter.acquire("pose", Priority::Pose, [35]).is_ok());
+  }
+
+  #[test]
+  fn leases_are_released_on_drop() {
+    let (arbiter, _) = arbiter();
+
+    let walk = arbiter.acquire("walk", Priority::Policy, [34]).unwrap();
+    assert!(arbiter.acquire("pose", Priority::Pose, [34]).is_err());
+
+    drop(walk);
+    assert!(arbiter.owners().is_empty());
+    assert!(arbiter.acquire("pose", Priority::Pose, [34]).is_ok());
+  }
+
+  #[test]
+  fn leases_only_cover_their_actuators_until_preempted() {
+    let (arbiter, _) = arbiter();
+
+    let walk = arbiter.acquire("walk", Priority::Policy, [33, 34]).unwrap();
+    assert!(walk.check(&[33, 34]).is_ok());
+    assert!(walk.check(&[34, 35]).is_err());
+
+    let _teleop = arbiter.acquire("teleop", Priority::Teleop, [35]).unwrap();
+    assert!(walk.check(&[33]).is_ok());
+
+    let _teach = arbiter.acquire("teach", Priority::Teach, [33]).unwrap();
+    assert!(walk.check(&[34]).is_err());
+    assert!(walk.check(&[]).is_err());
+  }
+}
@@ -0,0 +1,534 @@
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+  arbiter::Priority,
+  hal::{check_response, Hal},
+  proto::actuator::{
+    ActuatorStateResponse, CalibrateActuatorRequest, ConfigureActuatorRequest,
+  },
+  ActuatorCommand, KBot,
+};
+
+/// Maximum distance from zero, in degrees, accepted after zeroing a joint.
+const ZERO_TOLERANCE: f64 = 1.0;
+
+/// How long a joint has to stop moving for before it counts as settled.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Velocity, in degrees per second, below which a joint counts as stopped.
+const SETTLE_VELOCITY: f64 = 1.0;
+
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a commanded joint gets to start moving. A joint that is already
+/// where it was sent never does, so it is then waited on as it is.
+const START_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How the current joint is brought to its reference position before zeroing.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum CalibrationMethod {
+  /// Torque is disabled and the operator holds the joint at its zero pose.
+  Hand,
+  /// The actuator drives itself into its hard stop, then backs off by
+  /// `offset` degrees to reach its zero pose.
+  HardStop {
+    offset: f64,
+    calibration_speed: Option<f64>,
+    threshold_current: Option<f32>,
+  },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationStep {
+  /// Waiting for the joint to be prepared.
+  Pending,
+  /// The joint is on its way to its reference.
+  Preparing,
+  /// The joint is at its reference and ready to be zeroed.
+  Positioned,
+  /// Every joint in the session has been handled.
+  Done,
+}
+
+/// Persisted outcome of calibrating one actuator.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CalibrationResult {
+  pub method: CalibrationMethod,
+  /// Position reported right after zeroing.
+  pub verified_position: f64,
+  pub passed: bool,
+  /// Seconds since the Unix epoch.
+  pub calibrated_at: u64,
+}
+
+/// Calibration results keyed by actuator ID, as stored on disk.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct CalibrationFile {
+  pub actuators: BTreeMap<u32, CalibrationResult>,
+}
+
+impl CalibrationFile {
+  pub async fn load(path: &Path) -> eyre::Result<Self> {
+    match tokio::fs::read(path).await {
+      Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  /// Writes the file next to `path` first and then moves it into place, so
+  /// an interrupted save never leaves a truncated file behind.
+  pub async fn save(&self, path: &Path) -> eyre::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+
+    tokio::fs::write(&temp, serde_json::to_vec_pretty(self)?).await?;
+    tokio::fs::rename(&temp, path).await?;
+
+    Ok(())
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CalibrationStatus {
+  pub current: Option<u32>,
+  pub step: CalibrationStep,
+  pub remaining: Vec<u32>,
+  pub method: Option<CalibrationMethod>,
+  pub results: BTreeMap<u32, CalibrationResult>,
+}
+
+#[derive(Debug)]
+struct Session {
+  queue: Vec<u32>,
+  step: CalibrationStep,
+  method: Option<CalibrationMethod>,
+  results: BTreeMap<u32, CalibrationResult>,
+}
+
+impl Session {
+  /// Fails while the current joint is being prepared.
+  fn idle(&self) -> eyre::Result<()> {
+    if self.step == CalibrationStep::Preparing {
+      return Err(eyre::eyre!(
+        "Actuator {:?} is still being prepared",
+        self.queue.first()
+      ));
+    }
+
+    Ok(())
+  }
+
+  fn current(&self) -> eyre::Result<u32> {
+    self
+      .queue
+      .first()
+      .copied()
+      .ok_or_else(|| eyre::eyre!("All joints have been calibrated"))
+  }
+
+  fn advance(&mut self) {
+    if !self.queue.is_empty() {
+      self.queue.remove(0);
+    }
+    self.method = None;
+    self.step = if self.queue.is_empty() {
+      CalibrationStep::Done
+    } else {
+      CalibrationStep::Pending
+    };
+  }
+
+  fn status(&self) -> CalibrationStatus {
+    CalibrationStatus {
+      current: self.queue.first().copied(),
+      step: self.step.clone(),
+      remaining: self.queue.iter().skip(1).copied().collect(),
+      method: self.method.clone(),
+      results: self.results.clone(),
+    }
+  }
+}
+
+/// Step-by-step actuator zeroing, one joint at a time.
+///
+/// A session walks through a queue of actuators. For each one the joint is
+/// first prepared with [`Calibrator::prepare`], then zeroed and verified with
+/// [`Calibrator::zero`], which also writes the result to the calibration file.
+#[derive(Debug)]
+pub struct Calibrator {
+  hal: Hal,
+  path: PathBuf,
+  session: Mutex<Option<Session>>,
+}
+
+impl Calibrator {
+  pub fn new(hal: Hal, path: PathBuf) -> Self {
+    Self {
+      hal,
+      path,
+      session: Mutex::new(None),
+    }
+  }
+
+  /// Starts a new session over `actuator_ids`, discarding any previous one.
+  pub async fn start(
+    &self,
+    actuator_ids: Vec<u32>,
+  ) -> eyre::Result<CalibrationStatus> {
+    let mut current = self.session.lock().await;
+
+    // Don't leave a hand-positioned joint limp when abandoning a session.
+    if let Some(previous) = current.as_ref() {
+      previous.idle()?;
+
+      if let (Ok(actuator_id), Some(CalibrationMethod::Hand)) =
+        (previous.current(), &previous.method)
+      {
+        self.set_torque(actuator_id, true).await?;
+      }
+    }
+
+    let session = Session {
+      step: if actuator_ids.is_empty() {
+        CalibrationStep::Done
+      } else {
+        CalibrationStep::Pending
+      },
+      queue: actuator_ids,
+      method: None,
+      results: BTreeMap::new(),
+    };
+    let status = session.status();
+
+    *current = Some(session);
+
+    Ok(status)
+  }
+
+  pub async fn status(&self) -> Option<CalibrationStatus> {
+    self.session.lock().await.as_ref().map(Session::status)
+  }
+
+  /// Brings the current joint to its reference position, claiming it from
+  /// the arbiter for as long as it moves.
+  ///
+  /// The session is not locked while the joint moves, so its status can be
+  /// read in the meantime; every other step is refused until it is done.
+  pub async fn prepare(
+    &self,
+    kbot: &KBot,
+    method: CalibrationMethod,
+  ) -> eyre::Result<CalibrationStatus> {
+    let actuator_id = {
+      let mut session = self.session.lock().await;
+      let session = session
+        .as_mut()
+        .ok_or_else(|| eyre::eyre!("No calibration session running"))?;
+      session.idle()?;
+      let actuator_id = session.current()?;
+
+      session.step = CalibrationStep::Preparing;
+      actuator_id
+    };
+
+    let prepared = self.move_to_reference(kbot, actuator_id, &method).await;
+
+    let mut session = self.session.lock().await;
+    let session = session
+      .as_mut()
+      .ok_or_else(|| eyre::eyre!("No calibration session running"))?;
+
+    match prepared {
+      Ok(()) => {
+        session.method = Some(method);
+        session.step = CalibrationStep::Positioned;
+
+        Ok(session.status())
+      }
+      Err(e) => {
+        session.step = CalibrationStep::Pending;
+
+        Err(e)
+      }
+    }
+  }
+
+  async fn move_to_reference(
+    &self,
+    kbot: &KBot,
+    actuator_id: u32,
+    method: &CalibrationMethod,
+  ) -> eyre::Result<()> {
+    kbot.fall_detector.check()?;
+    let lease =
+      kbot
+        .arbiter
+        .acquire("calibration", Priority::Pose, [actuator_id])?;
+
+    match method {
+      CalibrationMethod::Hand => {
+        self.set_torque(actuator_id, false).await?;
+      }
+      CalibrationMethod::HardStop {
+        offset,
+        calibration_speed,
+        threshold_current,
+      } => {
+        self
+          .hal
+          .actuators
+          .calibrate_actuator(CalibrateActuatorRequest {
+            actuator_id,
+            calibration_speed: *calibration_speed,
+            threshold_current: *threshold_current,
+          })
+          .await?;
+
+        self.wait_until_moving(actuator_id).await?;
+        let stop = self.wait_until_settled(actuator_id).await?;
+
+        kbot
+          .command_actuators(
+            &lease,
+            vec![ActuatorCommand {
+              actuator_id,
+              position: Some(stop.position() + offset),
+              velocity: None,
+              torque: None,
+            }],
+          )
+          .await?;
+
+        self.wait_until_moving(actuator_id).await?;
+        self.wait_until_settled(actuator_id).await?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Zeroes the current joint at its present position, verifies the new
+  /// reading and moves on to the next joint. Only results that pass are
+  /// persisted, so a failed attempt never replaces a good calibration.
+  pub async fn zero(&self) -> eyre::Result<CalibrationStatus> {
+    let mut session = self.session.lock().await;
+    let session = session
+      .as_mut()
+      .ok_or_else(|| eyre::eyre!("No calibration session running"))?;
+    session.idle()?;
+    let actuator_id = session.current()?;
+
+    let Some(method) = session.method.clone() else {
+      return Err(eyre::eyre!("Actuator {actuator_id} has not been prepared"));
+    };
+
+    let response = self
+      .hal
+      .actuators
+      .configure_actuator(ConfigureActuatorRequest {
+        actuator_id,
+        zero_position: Some(true),
+        torque_enabled: Some(true),
+        ..Default::default()
+      })
+      .await?;
+    check_response(actuator_id, &response)?;
+
+    let verified_position = self.read_state(actuator_id).await?.position();
+
+    let result = CalibrationResult {
+      method,
+      verified_position,
+      passed: verified_position.abs() <= ZERO_TOLERANCE,
+      calibrated_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs(),
+    };
+
+    if result.passed {
+      let mut file = CalibrationFile::load(&self.path).await?;
+      file.actuators.insert(actuator_id, result.clone());
+      file.save(&self.path).await?;
+    } else {
+      tracing::warn!(
+        actuator_id,
+        verified_position,
+        "Calibration failed verification and was not saved"
+      );
+    }
+
+    session.results.insert(actuator_id, result);
+    session.advance();
+
+    Ok(session.status())
+  }
+
+  /// Leaves the current joint uncalibrated and moves on to the next one.
+  pub async fn skip(&self) -> eyre::Result<CalibrationStatus> {
+    let mut session = self.session.lock().await;
+    let session = session
+      .as_mut()
+      .ok_or_else(|| eyre::eyre!("No calibration session running"))?;
+    session.idle()?;
+    let actuator_id = session.current()?;
+
+    if matches!(session.method, Some(CalibrationMethod::Hand)) {
+      self.set_torque(actuator_id, true).await?;
+    }
+
+    session.advance();
+
+    Ok(session.status())
+  }
+
+  async fn set_torque(
+    &self,
+    actuator_id: u32,
+    enabled: bool,
+  ) -> eyre::Result<()> {
+    let response = self
+      .hal
+      .actuators
+      .configure_actuator(ConfigureActuatorRequest {
+        actuator_id,
+        torque_enabled: Some(enabled),
+        ..Default::default()
+      })
+      .await?;
+
+    check_response(actuator_id, &response)
+  }
+
+  async fn read_state(
+    &self,
+    actuator_id: u32,
+  ) -> eyre::Result<ActuatorStateResponse> {
+    self
+      .hal
+      .actuators
+      .get_actuators_state(vec![actuator_id])
+      .await?
+      .into_iter()
+      .next()
+      .ok_or_else(|| eyre::eyre!("Actuator {actuator_id} reported no state"))
+  }
+
+  /// Polls the actuator until it starts moving, giving up quietly after
+  /// [`START_TIMEOUT`].
+  async fn wait_until_moving(&self, actuator_id: u32) -> eyre::Result<()> {
+    let start = Instant::now();
+
+    while start.elapsed() < START_TIMEOUT {
+      let state = self.read_state(actuator_id).await?;
+      if state.velocity().abs() >= SETTLE_VELOCITY {
+        return Ok(());
+      }
+
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    tracing::debug!(actuator_id, "Actuator did not start moving");
+
+    Ok(())
+  }
+
+  /// Polls the actuator until it has stopped moving.
+  async fn wait_until_settled(
+    &self,
+    actuator_id: u32,
+  ) -> eyre::Result<ActuatorStateResponse> {
+    let start = Instant::now();
+    let mut still_since = None;
+
+    loop {
+      let state = self.read_state(actuator_id).await?;
+
+      if state.velocity().abs() < SETTLE_VELOCITY {
+        let since = *still_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= SETTLE_TIME {
+          return Ok(state);
+        }
+      } else {
+        still_since = None;
+      }
+
+      if start.elapsed() > SETTLE_TIMEOUT {
+        return Err(eyre::eyre!("Actuator {actuator_id} did not settle"));
+      }
+
+      tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    tests::{config, simulated_with},
+    Config,
+  };
+
+  #[tokio::test]
+  async fn hard_stop_calibration_zeroes_the_joint_and_saves_the_result() {
+    let path = std::env::temp_dir()
+      .join(format!("kbot-calibration-{}.json", std::process::id()));
+    let (kbot, simulator) = simulated_with(Config {
+      calibration_path: path.clone(),
+      ..config()
+    })
+    .await;
+
+    kbot.calibrator.start(vec![35, 34]).await.unwrap();
+    let status = kbot
+      .calibrator
+      .prepare(
+        &kbot,
+        CalibrationMethod::HardStop {
+          offset: 20.0,
+          calibration_speed: Some(120.0),
+          threshold_current: None,
+        },
+      )
+      .await
+      .unwrap();
+    assert_eq!(status.step, CalibrationStep::Positioned);
+
+    // Backed off 20° from the hard stop at the lower joint limit.
+    let position = kbot.calibrator.read_state(35).await.unwrap().position();
+    assert!((position + 40.0).abs() < 1.0, "ankle at {position}°");
+
+    let status = kbot.calibrator.zero().await.unwrap();
+    assert!(status.results[&35].passed);
+    assert_eq!(status.current, Some(34));
+
+    let file = CalibrationFile::load(&path).await.unwrap();
+    assert!(file.actuators[&35].passed);
+    assert!(!path.with_extension("json.tmp").exists());
+
+    // A rejected zeroing is neither recorded nor saved.
+    kbot
+      .calibrator
+      .prepare(&kbot, CalibrationMethod::Hand)
+      .await
+      .unwrap();
+    simulator.set_rejecting(34, true);
+    assert!(kbot.calibrator.zero().await.is_err());
+
+    let file = CalibrationFile::load(&path).await.unwrap();
+    assert_eq!(file.actuators.keys().collect::<Vec<_>>(), [&35]);
+
+    std::fs::remove_file(&path).ok();
+  }
+}
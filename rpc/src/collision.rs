@@ -0,0 +1,418 @@
+use std::sync::Arc;
+
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  kinematics::{KinematicModel, Limb},
+  pose::Pose,
+};
+
+/// What to do with a motion that would make the robot hit itself.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionAction {
+  /// Refuse the whole motion.
+  Reject,
+  /// Move as far as possible without colliding, then stop.
+  Truncate,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CollisionConfig {
+  /// Capsule around the torso, as two points in the torso frame.
+  pub torso: [[f64; 3]; 2],
+  pub torso_radius: f64,
+  pub arm_radius: f64,
+  pub leg_radius: f64,
+  /// Extra clearance, in meters, required between any two capsules.
+  pub margin: f64,
+  /// Largest joint movement, in degrees, between checks along a motion.
+  pub step: f64,
+  /// Time between the checked waypoints when a motion is commanded, so one
+  /// step every `step_ms` sets the speed of a move.
+  pub step_ms: u64,
+  pub action: CollisionAction,
+}
+
+impl Default for CollisionConfig {
+  fn default() -> Self {
+    Self {
+      torso: [[0.0, 0.0, 0.1], [0.0, 0.0, 0.4]],
+      torso_radius: 0.12,
+      arm_radius: 0.04,
+      leg_radius: 0.06,
+      margin: 0.01,
+      step: 2.0,
+      step_ms: 20,
+      action: CollisionAction::Reject,
+    }
+  }
+}
+
+/// Which capsule a collision involves.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "body", rename_all = "snake_case")]
+pub enum Body {
+  Torso,
+  /// The link after the `segment`th joint of `limb`.
+  Limb {
+    limb: Limb,
+    segment: usize,
+  },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Collision {
+  pub a: Body,
+  pub b: Body,
+  /// How far the capsules overlap, in meters, including the margin.
+  pub depth: f64,
+}
+
+/// Outcome of checking a motion from one pose to another.
+#[derive(Serialize, Debug, Clone)]
+pub struct MotionCheck {
+  /// Fraction of the motion, from 0 to 1, that is collision free.
+  pub safe_fraction: f64,
+  /// The furthest collision-free pose along the motion.
+  pub safe_pose: Pose,
+  pub collision: Option<Collision>,
+  /// Every collision-free pose that was checked, from the start of the
+  /// motion to `safe_pose`.
+  #[serde(skip)]
+  pub waypoints: Vec<Pose>,
+}
+
+struct Capsule {
+  body: Body,
+  a: Point3<f64>,
+  b: Point3<f64>,
+  radius: f64,
+}
+
+/// Checks poses and motions against a capsule model built from the
+/// kinematic chains: one capsule per link, plus one for the torso.
+#[derive(Debug)]
+pub struct CollisionChecker {
+  config: CollisionConfig,
+  kinematics: Arc<KinematicModel>,
+}
+
+impl CollisionChecker {
+  pub fn new(config: CollisionConfig, kinematics: Arc<KinematicModel>) -> Self {
+    Self { config, kinematics }
+  }
+
+  pub fn config(&self) -> &CollisionConfig {
+    &self.config
+  }
+
+  /// Collisions between different limbs, or between a limb and the torso.
+  /// Links of the same limb are not checked against each other. Joints
+  /// missing from `pose` are taken to be at zero.
+  pub fn check(&self, pose: &Pose) -> Vec<Collision> {
+    let capsules = self.capsules(pose);
+    let mut collisions = Vec::new();
+
+    for (i, a) in capsules.iter().enumerate() {
+      for b in &capsules[i + 1..] {
+        if let (Body::Limb { limb: x, .. }, Body::Limb { limb: y, .. }) =
+          (a.body, b.body)
+        {
+          if x == y {
+            continue;
+          }
+        }
+
+        let distance = segment_distance(&a.a, &a.b, &b.a, &b.b);
+        let depth = a.radius + b.radius + self.config.margin - distance;
+        if depth > 0.0 {
+          collisions.push(Collision {
+            a: a.body,
+            b: b.body,
+            depth,
+          });
+        }
+      }
+    }
+
+    collisions
+  }
+
+  /// Walks from `from` to `to` in steps of at most [`CollisionConfig::step`]
+  /// degrees per joint, stopping at the first collision. Contacts already
+  /// present at `from` are ignored so a limb can always be moved out of one.
+  pub fn check_motion(&self, from: &Pose, to: &Pose) -> MotionCheck {
+    let existing = self
+      .check(from)
+      .into_iter()
+      .map(|collision| (collision.a, collision.b))
+      .collect::<Vec<_>>();
+
+    let largest = to
+      .iter()
+      .map(|(joint, target)| {
+        let start = from.get(joint.joint, joint.axis).unwrap_or(target);
+        (target - start).abs()
+      })
+      .fold(0.0, f64::max);
+    let steps = (largest / self.config.step.max(f64::EPSILON))
+      .ceil()
+      .max(1.0);
+
+    let mut safe = MotionCheck {
+      safe_fraction: 0.0,
+      safe_pose: from.clone(),
+      collision: None,
+      waypoints: Vec::new(),
+    };
+
+    for step in 0..=steps as usize {
+      let t = step as f64 / steps;
+      let pose = interpolate(from, to, t);
+
+      let collision = self
+        .check(&pose)
+        .into_iter()
+        .find(|collision| !existing.contains(&(collision.a, collision.b)));
+      if let Some(collision) = collision {
+        safe.collision = Some(collision);
+        return safe;
+      }
+
+      safe.safe_fraction = t;
+      safe.safe_pose = pose.clone();
+      safe.waypoints.push(pose);
+    }
+
+    safe
+  }
+
+  fn capsules(&self, pose: &Pose) -> Vec<Capsule> {
+    let [a, b] = self.config.torso;
+    let mut capsules = vec![Capsule {
+      body: Body::Torso,
+      a: Point3::from(a),
+      b: Point3::from(b),
+      radius: self.config.torso_radius,
+    }];
+
+    for chain in &self.kinematics.chains {
+      let radius = match chain.limb {
+        Limb::LeftArm | Limb::RightArm => self.config.arm_radius,
+        Limb::LeftLeg | Limb::RightLeg => self.config.leg_radius,
+      };
+
+      let points = chain
+        .frames(pose)
+        .iter()
+        .map(|frame| Point3::from(frame.translation.vector))
+        .collect::<Vec<_>>();
+
+      for (segment, ends) in points.windows(2).enumerate() {
+        // Joints stacked in one housing have no link between them.
+        if (ends[1] - ends[0]).norm() < radius {
+          continue;
+        }
+
+        capsules.push(Capsule {
+          body: Body::Limb {
+            limb: chain.limb,
+            segment,
+          },
+          a: ends[0],
+          b: ends[1],
+          radius,
+        });
+      }
+    }
+
+    capsules
+  }
+}
+
+/// Linear interpolation between two poses; joints only in `to` jump
+/// straight there.
+pub fn interpolate(from: &Pose, to: &Pose, t: f64) -> Pose {
+  to.iter()
+    .map(|(joint, target)| {
+      let start = from.get(joint.joint, joint.axis).unwrap_or(target);
+      (joint, start + (target - start) * t)
+    })
+    .collect()
+}
+
+/// Shortest distance between segments `p1`-`q1` and `p2`-`q2`.
+fn segment_distance(
+  p1: &Point3<f64>,
+  q1: &Point3<f64>,
+  p2: &Point3<f64>,
+  q2: &Point3<f64>,
+) -> f64 {
+  let d1: Vector3<f64> = q1 - p1;
+  let d2: Vector3<f64> = q2 - p2;
+  let r = p1 - p2;
+  let a = d1.norm_squared();
+  let e = d2.norm_squared();
+  let f = d2.dot(&r);
+
+  let (s, t) = if a <= f64::EPSILON && e <= f64::EPSILON {
+    (0.0, 0.0)
+  } else if a <= f64::EPSILON {
+    (0.0, (f / e).clamp(0.0, 1.0))
+  } else {
+    let c = d1.dot(&r);
+    if e <= f64::EPSILON {
+      ((-c / a).clamp(0.0, 1.0), 0.0)
+    } else {
+      let b = d1.dot(&d2);
+      let denominator = a * e - b * b;
+      let mut s = if denominator > f64::EPSILON {
+        ((b * f - c * e) / denominator).clamp(0.0, 1.0)
+      } else {
+        0.0
+      };
+      let mut t = (b * s + f) / e;
+
+      if t < 0.0 {
+        t = 0.0;
+        s = (-c / a).clamp(0.0, 1.0);
+      } else if t > 1.0 {
+        t = 1.0;
+        s = ((b - c) / a).clamp(0.0, 1.0);
+      }
+
+      (s, t)
+    }
+  };
+
+  ((p1 + d1 * s) - (p2 + d2 * t)).norm()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    kinematics::{Chain, Frame, KinematicJoint},
+    pose::JointAxis,
+    Axis, Joint,
+  };
+
+  fn point(x: f64, y: f64, z: f64) -> Point3<f64> {
+    Point3::new(x, y, z)
+  }
+
+  /// An arm hanging beside the torso that rolls into it for negative
+  /// positions.
+  fn checker() -> CollisionChecker {
+    let arm = Chain {
+      limb: Limb::LeftArm,
+      joints: vec![KinematicJoint {
+        joint: shoulder(),
+        origin: Frame::translation(0.0, 0.2, 0.3),
+        axis: [1.0, 0.0, 0.0],
+        limits: None,
+      }],
+      tip: Frame::translation(0.0, 0.0, -0.3),
+    };
+
+    CollisionChecker::new(
+      CollisionConfig::default(),
+      Arc::new(KinematicModel { chains: vec![arm] }),
+    )
+  }
+
+  fn shoulder() -> JointAxis {
+    JointAxis::new(Joint::LeftShoulder, Some(Axis::Yaw))
+  }
+
+  fn arm_at(position: f64) -> Pose {
+    Pose::new().with(Joint::LeftShoulder, Some(Axis::Yaw), position)
+  }
+
+  #[test]
+  fn segment_distance_handles_every_arrangement() {
+    let distance = |p1, q1, p2, q2| segment_distance(&p1, &q1, &p2, &q2);
+
+    // Parallel, side by side.
+    let side_by_side = distance(
+      point(0.0, 0.0, 0.0),
+      point(0.0, 0.0, 1.0),
+      point(0.0, 0.5, 0.0),
+      point(0.0, 0.5, 1.0),
+    );
+    assert!((side_by_side - 0.5).abs() < 1e-9);
+
+    // Crossing at right angles, one above the other.
+    let crossing = distance(
+      point(-1.0, 0.0, 0.0),
+      point(1.0, 0.0, 0.0),
+      point(0.0, -1.0, 0.3),
+      point(0.0, 1.0, 0.3),
+    );
+    assert!((crossing - 0.3).abs() < 1e-9);
+
+    // Collinear with a gap, so the closest points are the ends.
+    let end_to_end = distance(
+      point(0.0, 0.0, 0.0),
+      point(0.0, 0.0, 1.0),
+      point(0.0, 0.0, 1.5),
+      point(0.0, 0.0, 2.0),
+    );
+    assert!((end_to_end - 0.5).abs() < 1e-9);
+
+    // A point against a segment, and against another point.
+    let to_point = distance(
+      point(0.0, 0.0, 0.0),
+      point(0.0, 0.0, 1.0),
+      point(0.2, 0.0, 0.5),
+      point(0.2, 0.0, 0.5),
+    );
+    assert!((to_point - 0.2).abs() < 1e-9);
+    let points = distance(
+      point(0.0, 0.0, 0.0),
+      point(0.0, 0.0, 0.0),
+      point(0.3, 0.4, 0.0),
+      point(0.3, 0.4, 0.0),
+    );
+    assert!((points - 0.5).abs() < 1e-9);
+  }
+
+  #[test]
+  fn check_motion_stops_before_the_first_collision() {
+    let checker = checker();
+    let motion = checker.check_motion(&arm_at(0.0), &arm_at(-90.0));
+
+    let collision = motion.collision.unwrap();
+    assert_eq!(collision.a, Body::Torso);
+    assert!(motion.safe_fraction > 0.0 && motion.safe_fraction < 1.0);
+    assert!(checker.check(&motion.safe_pose).is_empty());
+
+    // Every waypoint is one step or less from the one before it and the
+    // last is where the motion stops.
+    assert_eq!(motion.waypoints.first(), Some(&arm_at(0.0)));
+    assert_eq!(motion.waypoints.last(), Some(&motion.safe_pose));
+    for pair in motion.waypoints.windows(2) {
+      let step = pair[1].get(Joint::LeftShoulder, Some(Axis::Yaw)).unwrap()
+        - pair[0].get(Joint::LeftShoulder, Some(Axis::Yaw)).unwrap();
+      assert!(step.abs() <= checker.config().step + 1e-9);
+    }
+  }
+
+  #[test]
+  fn check_motion_completes_free_motions_and_escapes_contacts() {
+    let checker = checker();
+
+    let outward = checker.check_motion(&arm_at(0.0), &arm_at(45.0));
+    assert!(outward.collision.is_none());
+    assert_eq!(outward.safe_fraction, 1.0);
+    assert_eq!(outward.safe_pose, arm_at(45.0));
+
+    // Already touching the torso, moving away is allowed.
+    assert!(!checker.check(&arm_at(-90.0)).is_empty());
+    let escape = checker.check_motion(&arm_at(-90.0), &arm_at(0.0));
+    assert!(escape.collision.is_none());
+    assert_eq!(escape.safe_pose, arm_at(0.0));
+  }
+}
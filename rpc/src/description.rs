@@ -0,0 +1,1129 @@
+use std::{collections::HashMap, path::Path};
+
+use nalgebra::{Isometry3, Translation3, Unit, UnitQuaternion, Vector3};
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  kinematics::{Chain, Frame, KinematicJoint, KinematicModel, Limb},
+  pose::JointAxis,
+  Axis, Joint, Robot,
+};
+
+/// A collision or visual shape, in meters.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Geometry {
+  Box {
+    size: [f64; 3],
+  },
+  Sphere {
+    radius: f64,
+  },
+  Cylinder {
+    radius: f64,
+    length: f64,
+  },
+  /// A cylinder of `length` along Z with hemispherical caps.
+  Capsule {
+    radius: f64,
+    length: f64,
+  },
+  Mesh {
+    filename: String,
+  },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Shape {
+  /// Placement of the shape in its link's frame.
+  pub origin: Frame,
+  pub geometry: Geometry,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Link {
+  pub name: String,
+  pub shapes: Vec<Shape>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct JointInfo {
+  /// Name in the source file, e.g. `L_Hip_Pitch`.
+  pub name: String,
+  /// The robot joint this corresponds to, if the name could be mapped.
+  pub joint: Option<JointAxis>,
+  /// Whether the joint rotates. Fixed, sliding and ball joints are kept only
+  /// for their placement.
+  pub revolute: bool,
+  pub parent: String,
+  pub child: String,
+  /// Placement of the child link relative to the parent link at zero.
+  pub origin: Frame,
+  pub axis: [f64; 3],
+  /// Position limits in degrees.
+  pub limits: Option<[f64; 2]>,
+}
+
+/// Joints, limits and link geometry read from a URDF or MJCF file.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RobotDescription {
+  pub name: String,
+  pub links: Vec<Link>,
+  pub joints: Vec<JointInfo>,
+}
+
+impl RobotDescription {
+  /// Loads a `.urdf` or MJCF `.xml` file, telling them apart by their root
+  /// element.
+  pub async fn load(path: &Path) -> eyre::Result<Self> {
+    let xml = tokio::fs::read_to_string(path).await?;
+    let document = Document::parse(&xml)?;
+
+    match document.root_element().tag_name().name() {
+      "robot" => Self::from_urdf(&document),
+      "mujoco" => Self::from_mjcf(&document),
+      other => Err(eyre::eyre!(
+        "{} is neither URDF nor MJCF (root element <{other}>)",
+        path.display()
+      )),
+    }
+  }
+
+  pub fn from_urdf(document: &Document) -> eyre::Result<Self> {
+    let robot = document.root_element();
+    let mut description = Self {
+      name: robot.attribute("name").unwrap_or_default().to_string(),
+      ..Default::default()
+    };
+
+    for link in robot.children().filter(|node| node.has_tag_name("link")) {
+      let mut shapes = Vec::new();
+
+      for collision in link
+        .children()
+        .filter(|node| node.has_tag_name("collision"))
+      {
+        let Some(geometry) = child(collision, "geometry")
+          .and_then(|geometry| geometry.first_element_child())
+        else {
+          continue;
+        };
+
+        shapes.push(Shape {
+          origin: urdf_origin(collision)?,
+          geometry: match geometry.tag_name().name() {
+            "box" => Geometry::Box {
+              size: vector(geometry.attribute("size").unwrap_or("0 0 0"))?,
+            },
+            "sphere" => Geometry::Sphere {
+              radius: number(geometry, "radius")?,
+            },
+            "cylinder" => Geometry::Cylinder {
+              radius: number(geometry, "radius")?,
+              length: number(geometry, "length")?,
+            },
+            "capsule" => Geometry::Capsule {
+              radius: number(geometry, "radius")?,
+              length: number(geometry, "length")?,
+            },
+            "mesh" => Geometry::Mesh {
+              filename: geometry
+                .attribute("filename")
+                .unwrap_or_default()
+                .to_string(),
+            },
+            other => return Err(eyre::eyre!("Unknown URDF geometry {other}")),
+          },
+        });
+      }
+
+      description.links.push(Link {
+        name: link.attribute("name").unwrap_or_default().to_string(),
+        shapes,
+      });
+    }
+
+    for joint in robot.children().filter(|node| node.has_tag_name("joint")) {
+      let name = joint.attribute("name").unwrap_or_default().to_string();
+      let kind = joint.attribute("type").unwrap_or("fixed");
+      let revolute = matches!(kind, "revolute" | "continuous");
+      let link = |tag| {
+        child(joint, tag)
+          .and_then(|node| node.attribute("link"))
+          .map(str::to_string)
+          .ok_or_else(|| eyre::eyre!("Joint {name} has no {tag} link"))
+      };
+
+      // Continuous joints have no position limits, and a revolute joint's
+      // `<limit>` may give only effort and velocity.
+      let limits = match child(joint, "limit") {
+        Some(limit) if kind == "revolute" => {
+          match (
+            optional_number(limit, "lower")?,
+            optional_number(limit, "upper")?,
+          ) {
+            (None, None) => None,
+            // URDF defaults a missing bound to zero.
+            (lower, upper) => Some([
+              lower.unwrap_or_default().to_degrees(),
+              upper.unwrap_or_default().to_degrees(),
+            ]),
+          }
+        }
+        _ => None,
+      };
+
+      description.joints.push(JointInfo {
+        joint: revolute.then(|| parse_joint_name(&name)).flatten(),
+        revolute,
+        parent: link("parent")?,
+        child: link("child")?,
+        origin: urdf_origin(joint)?,
+        axis: match child(joint, "axis").and_then(|axis| axis.attribute("xyz"))
+        {
+          Some(xyz) => vector(xyz)?,
+          None => [1.0, 0.0, 0.0],
+        },
+        limits,
+        name,
+      });
+    }
+
+    Ok(description)
+  }
+
+  pub fn from_mjcf(document: &Document) -> eyre::Result<Self> {
+    let mujoco = document.root_element();
+    let radians = child(mujoco, "compiler")
+      .and_then(|compiler| compiler.attribute("angle"))
+      == Some("radian");
+    let mjcf = Mjcf {
+      defaults: Defaults::parse(mujoco),
+      radians,
+    };
+
+    let mut description = Self {
+      name: mujoco.attribute("model").unwrap_or_default().to_string(),
+      ..Default::default()
+    };
+
+    let Some(worldbody) = child(mujoco, "worldbody") else {
+      return Err(eyre::eyre!("MJCF has no <worldbody>"));
+    };
+
+    description.links.push(Link {
+      name: "world".to_string(),
+      shapes: mjcf.shapes(worldbody, "main", &Isometry3::identity())?,
+    });
+    for body in worldbody
+      .children()
+      .filter(|node| node.has_tag_name("body"))
+    {
+      mjcf.body(
+        body,
+        "world",
+        &Isometry3::identity(),
+        "main",
+        &mut description,
+      )?;
+    }
+
+    Ok(description)
+  }
+
+  pub fn joint(&self, joint: JointAxis) -> Option<&JointInfo> {
+    self.joints.iter().find(|info| info.joint == Some(joint))
+  }
+
+  pub fn link(&self, name: &str) -> Option<&Link> {
+    self.links.iter().find(|link| link.name == name)
+  }
+
+  pub fn limits(&self, joint: JointAxis) -> Option<[f64; 2]> {
+    self.joint(joint)?.limits
+  }
+
+  /// Whether `joint` and [`JointAxis::mirror`] count in the same (`1.0`) or
+  /// opposite (`-1.0`) directions, from their axes in the root frame. `None`
+  /// if either is missing or the two axes are not mirror images.
+  pub fn mirror_sign(&self, joint: JointAxis) -> Option<f64> {
+    let [x, y, z] = self.root_axis(joint)?;
+    let other = self.root_axis(joint.mirror())?;
+
+    // Reflecting a rotation across the XZ plane flips its X and Z
+    // components.
+    let alignment = Vector3::new(-x, y, -z).dot(&Vector3::from(other));
+
+    (alignment.abs() > 0.9).then(|| alignment.signum())
+  }
+
+  /// The unit axis of `joint` in the root link's frame with every joint
+  /// above it at zero.
+  fn root_axis(&self, joint: JointAxis) -> Option<[f64; 3]> {
+    let info = self.joint(joint)?;
+    let mut rotation = info.origin.isometry().rotation;
+    let mut link = info.parent.as_str();
+
+    // Bounded by the number of joints in case the file has a loop.
+    for _ in &self.joints {
+      let Some(parent) = self.joints.iter().find(|info| info.child == link)
+      else {
+        break;
+      };
+      rotation = parent.origin.isometry().rotation * rotation;
+      link = &parent.parent;
+    }
+
+    let axis =
+      Unit::try_new(rotation * Vector3::from(info.axis), f64::EPSILON)?;
+
+    Some([axis.x, axis.y, axis.z])
+  }
+
+  /// Differences between the file and `R`'s actuator map: revolute joints
+  /// that could not be mapped or have no actuator, and actuators with no
+  /// joint in the file.
+  pub fn mismatches<R: Robot>(&self) -> Vec<String> {
+    let mut mismatches = Vec::new();
+
+    for info in self.joints.iter().filter(|info| info.revolute) {
+      match info.joint {
+        None => mismatches.push(format!("{} is not a known joint", info.name)),
+        Some(joint) if joint.actuator_id::<R>().is_none() => {
+          mismatches.push(format!("{} ({joint}) has no actuator", info.name))
+        }
+        Some(_) => {}
+      }
+    }
+
+    for actuator_id in R::list_actuator_ids() {
+      let described = JointAxis::from_actuator_id::<R>(actuator_id)
+        .is_some_and(|joint| self.joint(joint).is_some());
+      if !described {
+        mismatches.push(format!("Actuator {actuator_id} has no joint"));
+      }
+    }
+
+    mismatches
+  }
+
+  /// Builds a chain for every limb from the root link out to the link moved
+  /// by the limb's last mapped joint, and on through any fixed links below
+  /// it. Unmapped joints in between are treated as fixed at zero.
+  pub fn kinematics(&self) -> eyre::Result<KinematicModel> {
+    let by_child = self
+      .joints
+      .iter()
+      .map(|info| (info.child.as_str(), info))
+      .collect::<HashMap<_, _>>();
+
+    // Joints from the root down to `info`, inclusive.
+    let path = |info: &JointInfo| {
+      let mut path = vec![info.clone()];
+      while let Some(parent) =
+        by_child.get(path[path.len() - 1].parent.as_str())
+      {
+        path.push((*parent).clone());
+      }
+      path.reverse();
+      path
+    };
+
+    let mut chains = Vec::new();
+    for limb in Limb::ALL {
+      let in_limb = |info: &JointInfo| {
+        info.revolute
+          && info
+            .joint
+            .is_some_and(|joint| limb.group().contains(joint.joint))
+      };
+
+      let Some(path) = self
+        .joints
+        .iter()
+        .filter(|info| in_limb(info))
+        .map(path)
+        .max_by_key(|path| path.iter().filter(|info| in_limb(info)).count())
+      else {
+        continue;
+      };
+
+      let mut offset = Isometry3::identity();
+      let mut joints = Vec::new();
+      for info in &path {
+        offset *= info.origin.isometry();
+
+        if let Some(joint) = info.joint.filter(|_| in_limb(info)) {
+          joints.push(KinematicJoint {
+            joint,
+            origin: Frame::from_isometry(&offset),
+            axis: info.axis,
+            limits: info.limits,
+          });
+          offset = Isometry3::identity();
+        }
+      }
+
+      // The hand or foot is usually a fixed link below the last joint.
+      let mut link = path.last().map(|info| info.child.as_str());
+      while let Some(fixed) = link.and_then(|link| {
+        self
+          .joints
+          .iter()
+          .find(|info| info.parent == link && !info.revolute)
+      }) {
+        offset *= fixed.origin.isometry();
+        link = Some(&fixed.child);
+      }
+
+      chains.push(Chain {
+        limb,
+        joints,
+        tip: Frame::from_isometry(&offset),
+      });
+    }
+
+    if chains.is_empty() {
+      return Err(eyre::eyre!("No limb joints found in {}", self.name));
+    }
+
+    Ok(KinematicModel { chains })
+  }
+}
+
+/// Maps simulation joint names such as `L_Hip_Pitch`, `left_hip_pitch` or
+/// `dof_left_hip_pitch_04` to a robot joint.
+pub fn parse_joint_name(name: &str) -> Option<JointAxis> {
+  let tokens = name
+    .split(|c: char| !c.is_ascii_alphanumeric())
+    .map(str::to_ascii_lowercase)
+    .collect::<Vec<_>>();
+  let has = |options: &[&str]| {
+    tokens.iter().any(|token| options.contains(&token.as_str()))
+  };
+
+  let left = has(&["l", "left"]);
+  let right = has(&["r", "right"]);
+  if left == right {
+    return None;
+  }
+
+  let joint = match () {
+    _ if has(&["shoulder"]) => [Joint::LeftShoulder, Joint::RightShoulder],
+    _ if has(&["elbow"]) => [Joint::LeftElbow, Joint::RightElbow],
+    _ if has(&["gripper", "hand"]) => [Joint::LeftGripper, Joint::RightGripper],
+    _ if has(&["hip"]) => [Joint::LeftHip, Joint::RightHip],
+    _ if has(&["knee"]) => [Joint::LeftKnee, Joint::RightKnee],
+    _ if has(&["ankle"]) => [Joint::LeftAnkle, Joint::RightAnkle],
+    _ => return None,
+  }[usize::from(right)];
+
+  let axis = match () {
+    _ if has(&["pitch"]) => Some(Axis::Pitch),
+    _ if has(&["yaw"]) => Some(Axis::Yaw),
+    _ if has(&["roll"]) => Some(Axis::Roll),
+    // Knees and ankles only bend one way, so files often leave it off.
+    _ if has(&["knee", "ankle"]) => Some(Axis::Pitch),
+    _ => None,
+  };
+
+  Some(JointAxis::new(joint, axis))
+}
+
+fn child<'a, 'input>(
+  node: Node<'a, 'input>,
+  tag: &str,
+) -> Option<Node<'a, 'input>> {
+  node.children().find(|child| child.has_tag_name(tag))
+}
+
+fn number(node: Node, attribute: &str) -> eyre::Result<f64> {
+  let value = node.attribute(attribute).ok_or_else(|| {
+    eyre::eyre!("<{}> is missing {attribute}", node.tag_name().name())
+  })?;
+
+  Ok(value.trim().parse()?)
+}
+
+fn optional_number(node: Node, attribute: &str) -> eyre::Result<Option<f64>> {
+  node
+    .attribute(attribute)
+    .map(|value| Ok(value.trim().parse()?))
+    .transpose()
+}
+
+fn numbers(value: &str) -> eyre::Result<Vec<f64>> {
+  Ok(
+    value
+      .split_whitespace()
+      .map(str::parse)
+      .collect::<Result<_, _>>()?,
+  )
+}
+
+fn vector(value: &str) -> eyre::Result<[f64; 3]> {
+  numbers(value)?
+    .try_into()
+    .map_err(|_| eyre::eyre!("Expected three numbers, got {value:?}"))
+}
+
+fn urdf_origin(node: Node) -> eyre::Result<Frame> {
+  let Some(origin) = child(node, "origin") else {
+    return Ok(Frame::default());
+  };
+
+  Ok(Frame {
+    xyz: vector(origin.attribute("xyz").unwrap_or("0 0 0"))?,
+    rpy: vector(origin.attribute("rpy").unwrap_or("0 0 0"))?,
+  })
+}
+
+/// A MJCF `<default>` class: the class it nests in, and the attributes it
+/// sets keyed by element tag.
+struct DefaultClass {
+  parent: Option<String>,
+  elements: HashMap<String, Vec<(String, String)>>,
+}
+
+/// Attributes set through MJCF `<default>` classes, keyed by class name.
+struct Defaults {
+  classes: HashMap<String, DefaultClass>,
+}
+
+impl Defaults {
+  fn parse(mujoco: Node) -> Self {
+    let mut defaults = Self {
+      classes: HashMap::new(),
+    };
+
+    for default in mujoco
+      .children()
+      .filter(|node| node.has_tag_name("default"))
+    {
+      defaults.class(default, None);
+    }
+
+    defaults
+  }
+
+  fn class(&mut self, default: Node, parent: Option<String>) {
+    let name = default.attribute("class").unwrap_or("main").to_string();
+
+    let elements = default
+      .children()
+      .filter(|node| node.is_element() && !node.has_tag_name("default"))
+      .map(|node| {
+        (
+          node.tag_name().name().to_string(),
+          node
+            .attributes()
+            .map(|attribute| {
+              (attribute.name().to_string(), attribute.value().to_string())
+            })
+            .collect(),
+        )
+      })
+      .collect();
+    self
+      .classes
+      .insert(name.clone(), DefaultClass { parent, elements });
+
+    for nested in default
+      .children()
+      .filter(|node| node.has_tag_name("default"))
+    {
+      self.class(nested, Some(name.clone()));
+    }
+  }
+
+  /// Looks `attribute` up on `node`, then on its class and the classes that
+  /// class inherits from.
+  fn get<'a>(
+    &'a self,
+    node: Node<'a, '_>,
+    class: &str,
+    attribute: &str,
+  ) -> Option<&'a str> {
+    if let Some(value) = node.attribute(attribute) {
+      return Some(value);
+    }
+
+    let tag = node.tag_name().name();
+    let mut class = Some(node.attribute("class").unwrap_or(class));
+
+    while let Some(name) = class {
+      let DefaultClass { parent, elements } = self.classes.get(name)?;
+      let value = elements.get(tag).and_then(|attributes| {
+        attributes
+          .iter()
+          .find(|(name, _)| name == attribute)
+          .map(|(_, value)| value.as_str())
+      });
+      if value.is_some() {
+        return value;
+      }
+      class = parent.as_deref();
+    }
+
+    None
+  }
+}
+
+struct Mjcf {
+  defaults: Defaults,
+  /// Angles in the file are radians rather than MuJoCo's default degrees.
+  radians: bool,
+}
+
+impl Mjcf {
+  fn angle(&self, value: f64) -> f64 {
+    if self.radians {
+      value
+    } else {
+      value.to_radians()
+    }
+  }
+
+  /// Position and orientation of `node` from its `pos` and one of `quat`,
+  /// `euler` or `axisangle`. An orientation set on the node itself wins over
+  /// any from its `<default>` class.
+  fn frame(&self, node: Node, class: &str) -> eyre::Result<Isometry3<f64>> {
+    const ORIENTATIONS: [&str; 3] = ["quat", "euler", "axisangle"];
+
+    let [x, y, z] = match self.defaults.get(node, class, "pos") {
+      Some(pos) => vector(pos)?,
+      None => [0.0; 3],
+    };
+
+    let orientation = ORIENTATIONS
+      .into_iter()
+      .find_map(|kind| Some((kind, node.attribute(kind)?)))
+      .or_else(|| {
+        ORIENTATIONS
+          .into_iter()
+          .find_map(|kind| Some((kind, self.defaults.get(node, class, kind)?)))
+      });
+
+    let rotation = match orientation {
+      Some(("quat", quat)) => {
+        let [w, i, j, k]: [f64; 4] = numbers(quat)?
+          .try_into()
+          .map_err(|_| eyre::eyre!("Expected four numbers, got {quat:?}"))?;
+        UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(w, i, j, k))
+      }
+      Some(("euler", euler)) => {
+        // MuJoCo's default `eulerseq` is intrinsic XYZ.
+        let [a, b, c] = vector(euler)?;
+        UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.angle(a))
+          * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.angle(b))
+          * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), self.angle(c))
+      }
+      Some((_, axisangle)) => {
+        let values = numbers(axisangle)?;
+        let [x, y, z, angle] = values[..] else {
+          return Err(eyre::eyre!("Expected four numbers, got {axisangle:?}"));
+        };
+        UnitQuaternion::from_axis_angle(
+          &Unit::new_normalize(Vector3::new(x, y, z)),
+          self.angle(angle),
+        )
+      }
+      None => UnitQuaternion::identity(),
+    };
+
+    Ok(Isometry3::from_parts(Translation3::new(x, y, z), rotation))
+  }
+
+  fn body(
+    &self,
+    body: Node,
+    parent: &str,
+    offset: &Isometry3<f64>,
+    class: &str,
+    description: &mut RobotDescription,
+  ) -> eyre::Result<()> {
+    let class = body.attribute("childclass").unwrap_or(class);
+    let name = body
+      .attribute("name")
+      .map(str::to_string)
+      .unwrap_or_else(|| format!("body{}", description.links.len()));
+
+    // Joints rotate about their own `pos` inside the body, so the link frame
+    // is moved to the last one and children are placed relative to it. A
+    // body with several hinges gets a link without geometry between each of
+    // them.
+    let hinges = body
+      .children()
+      .filter(|node| {
+        node.has_tag_name("joint")
+          && self
+            .defaults
+            .get(*node, class, "type")
+            .is_none_or(|kind| kind == "hinge")
+      })
+      .collect::<Vec<_>>();
+
+    // A floating base is the root of the robot rather than a link attached
+    // to the world.
+    let floating = body.children().any(|node| {
+      node.has_tag_name("freejoint")
+        || (node.has_tag_name("joint")
+          && self.defaults.get(node, class, "type") == Some("free"))
+    });
+
+    let mut link = parent.to_string();
+    let mut frame = offset.inverse() * self.frame(body, class)?;
+    let mut joint_offset = Isometry3::identity();
+
+    for (i, &joint) in hinges.iter().enumerate() {
+      let joint_name = joint
+        .attribute("name")
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{name}_joint{i}"));
+      let child = if i + 1 == hinges.len() {
+        name.clone()
+      } else {
+        format!("{name}/{joint_name}")
+      };
+
+      let [x, y, z] = match self.defaults.get(joint, class, "pos") {
+        Some(pos) => vector(pos)?,
+        None => [0.0; 3],
+      };
+      let position = Isometry3::translation(x, y, z);
+      frame *= joint_offset.inverse() * position;
+      joint_offset = position;
+
+      let limited = self.defaults.get(joint, class, "limited") != Some("false");
+      let limits = match self.defaults.get(joint, class, "range") {
+        Some(range) if limited => {
+          let values = numbers(range)?;
+          let [min, max] = values[..] else {
+            return Err(eyre::eyre!("Expected two numbers, got {range:?}"));
+          };
+          Some([self.angle(min).to_degrees(), self.angle(max).to_degrees()])
+        }
+        _ => None,
+      };
+
+      description.joints.push(JointInfo {
+        joint: parse_joint_name(&joint_name),
+        name: joint_name,
+        revolute: true,
+        parent: link,
+        child: child.clone(),
+        origin: Frame::from_isometry(&frame),
+        axis: match self.defaults.get(joint, class, "axis") {
+          Some(axis) => vector(axis)?,
+          None => [0.0, 0.0, 1.0],
+        },
+        limits,
+      });
+
+      if child != name {
+        description.links.push(Link {
+          name: child.clone(),
+          shapes: Vec::new(),
+        });
+      }
+
+      link = child;
+      frame = Isometry3::identity();
+    }
+
+    if hinges.is_empty() && !floating {
+      description.joints.push(JointInfo {
+        name: format!("{name}_fixed"),
+        joint: None,
+        revolute: false,
+        parent: parent.to_string(),
+        child: name.clone(),
+        origin: Frame::from_isometry(&frame),
+        axis: [0.0, 0.0, 1.0],
+        limits: None,
+      });
+    }
+
+    description.links.push(Link {
+      name: name.clone(),
+      shapes: self.shapes(body, class, &joint_offset)?,
+    });
+
+    for child in body.children().filter(|node| node.has_tag_name("body")) {
+      self.body(child, &name, &joint_offset, class, description)?;
+    }
+
+    Ok(())
+  }
+
+  fn shapes(
+    &self,
+    body: Node,
+    class: &str,
+    offset: &Isometry3<f64>,
+  ) -> eyre::Result<Vec<Shape>> {
+    let mut shapes = Vec::new();
+
+    for geom in body.children().filter(|node| node.has_tag_name("geom")) {
+      let size = match self.defaults.get(geom, class, "size") {
+        Some(size) => numbers(size)?,
+        None => Vec::new(),
+      };
+      let size = |i: usize| size.get(i).copied().unwrap_or_default();
+      let kind = self.defaults.get(geom, class, "type").unwrap_or("sphere");
+
+      // Capsules and cylinders may be given as a segment instead of a
+      // position and orientation.
+      let (frame, half_length) = match self.defaults.get(geom, class, "fromto")
+      {
+        Some(fromto) => {
+          let values = numbers(fromto)?;
+          let [ax, ay, az, bx, by, bz] = values[..] else {
+            return Err(eyre::eyre!("Expected six numbers, got {fromto:?}"));
+          };
+          let (a, b) = (Vector3::new(ax, ay, az), Vector3::new(bx, by, bz));
+          let rotation =
+            UnitQuaternion::rotation_between(&Vector3::z(), &(b - a))
+              .unwrap_or_else(|| {
+                UnitQuaternion::from_axis_angle(
+                  &Vector3::x_axis(),
+                  std::f64::consts::PI,
+                )
+              });
+          let center = (a + b) / 2.0;
+
+          (
+            Isometry3::from_parts(center.into(), rotation),
+            (b - a).norm() / 2.0,
+          )
+        }
+        None => (self.frame(geom, class)?, size(1)),
+      };
+
+      let geometry = match kind {
+        "sphere" => Geometry::Sphere { radius: size(0) },
+        "capsule" => Geometry::Capsule {
+          radius: size(0),
+          length: half_length * 2.0,
+        },
+        "cylinder" => Geometry::Cylinder {
+          radius: size(0),
+          length: half_length * 2.0,
+        },
+        "box" => Geometry::Box {
+          size: [size(0) * 2.0, size(1) * 2.0, size(2) * 2.0],
+        },
+        "mesh" => Geometry::Mesh {
+          filename: self
+            .defaults
+            .get(geom, class, "mesh")
+            .unwrap_or_default()
+            .to_string(),
+        },
+        // Planes, ellipsoids and height fields are not used for limbs.
+        _ => continue,
+      };
+
+      shapes.push(Shape {
+        origin: Frame::from_isometry(&(offset.inverse() * frame)),
+        geometry,
+      });
+    }
+
+    Ok(shapes)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    pose::{mirror_sign, Pose},
+    KBot,
+  };
+
+  /// Hip pitch axes point the same way on both sides, the right hip roll
+  /// frame is turned around and the hip yaw axes do not match at all.
+  const HIPS: &str = r#"
+    <robot name="hips">
+      <link name="base"/>
+      <link name="left_yaw"/>
+      <link name="left_roll"/>
+      <link name="left_pitch"/>
+      <link name="right_yaw"/>
+      <link name="right_roll"/>
+      <link name="right_pitch"/>
+
+      <joint name="left_hip_yaw" type="revolute">
+        <parent link="base"/>
+        <child link="left_yaw"/>
+        <origin xyz="0 0.1 0"/>
+        <axis xyz="0 0 1"/>
+        <limit lower="-1" upper="1" effort="1" velocity="1"/>
+      </joint>
+      <joint name="left_hip_roll" type="revolute">
+        <parent link="left_yaw"/>
+        <child link="left_roll"/>
+        <axis xyz="1 0 0"/>
+        <limit lower="-1" upper="1" effort="1" velocity="1"/>
+      </joint>
+      <joint name="left_hip_pitch" type="revolute">
+        <parent link="left_roll"/>
+        <child link="left_pitch"/>
+        <axis xyz="0 1 0"/>
+        <limit lower="-1" upper="1" effort="1" velocity="1"/>
+      </joint>
+
+      <joint name="right_hip_yaw" type="revolute">
+        <parent link="base"/>
+        <child link="right_yaw"/>
+        <origin xyz="0 -0.1 0"/>
+        <axis xyz="1 0 0"/>
+        <limit lower="-1" upper="1" effort="1" velocity="1"/>
+      </joint>
+      <joint name="right_hip_roll" type="revolute">
+        <parent link="right_yaw"/>
+        <child link="right_roll"/>
+        <origin rpy="0 0 3.14159265358979"/>
+        <axis xyz="1 0 0"/>
+        <limit lower="-1" upper="1" effort="1" velocity="1"/>
+      </joint>
+      <joint name="right_hip_pitch" type="revolute">
+        <parent link="right_roll"/>
+        <child link="right_pitch"/>
+        <axis xyz="0 1 0"/>
+        <limit lower="-1" upper="1" effort="1" velocity="1"/>
+      </joint>
+    </robot>
+  "#;
+
+  /// A wheel and an arm whose `<limit>`s leave out position bounds.
+  const WHEELED: &str = r#"
+    <robot name="wheeled">
+      <link name="base"/>
+      <link name="wheel"/>
+      <link name="upper_arm"/>
+      <link name="forearm"/>
+
+      <joint name="wheel_spin" type="continuous">
+        <parent link="base"/>
+        <child link="wheel"/>
+        <axis xyz="0 1 0"/>
+        <limit effort="5" velocity="10"/>
+      </joint>
+      <joint name="left_shoulder_pitch" type="revolute">
+        <parent link="base"/>
+        <child link="upper_arm"/>
+        <limit effort="5" velocity="10"/>
+      </joint>
+      <joint name="left_elbow_yaw" type="revolute">
+        <parent link="upper_arm"/>
+        <child link="forearm"/>
+        <origin xyz="0 0 -0.3"/>
+        <limit lower="-1.5707963267948966" upper="0.7853981633974483"
+          effort="5" velocity="10"/>
+      </joint>
+    </robot>
+  "#;
+
+  /// An arm whose geoms take their shape and orientation from a class.
+  const MJCF: &str = r#"
+    <mujoco model="arm">
+      <compiler angle="degree"/>
+      <default>
+        <joint range="-90 90"/>
+        <default class="turned">
+          <geom type="box" size="0.1 0.2 0.3" euler="0 0 90"/>
+        </default>
+      </default>
+      <worldbody>
+        <body name="torso" pos="0 0 1">
+          <freejoint/>
+          <body name="upper_arm" pos="0 0.2 0.3">
+            <joint name="left_shoulder_pitch" axis="0 1 0" pos="0 0 0.05"/>
+            <geom class="turned"/>
+            <geom class="turned" quat="1 0 0 0"/>
+            <body name="forearm" pos="0 0 -0.3">
+              <joint name="left_elbow_yaw" limited="false"/>
+            </body>
+          </body>
+        </body>
+      </worldbody>
+    </mujoco>
+  "#;
+
+  /// A hip with two hinges in one body.
+  const MJCF_HIP: &str = r#"
+    <mujoco model="leg">
+      <worldbody>
+        <body name="torso">
+          <freejoint/>
+          <body name="thigh" pos="0 0.1 0">
+            <joint name="left_hip_pitch" axis="0 1 0"/>
+            <joint name="left_hip_roll" axis="1 0 0" pos="0 0 -0.05"/>
+            <geom type="capsule" fromto="0 0 0 0 0 -0.35" size="0.05"/>
+            <body name="foot" pos="0 0 -0.4"/>
+          </body>
+        </body>
+      </worldbody>
+    </mujoco>
+  "#;
+
+  fn urdf(xml: &str) -> RobotDescription {
+    RobotDescription::from_urdf(&Document::parse(xml).unwrap()).unwrap()
+  }
+
+  fn hip(axis: Axis) -> JointAxis {
+    JointAxis::new(Joint::LeftHip, Some(axis))
+  }
+
+  #[test]
+  fn mirror_sign_follows_the_joint_axes() {
+    let description = urdf(HIPS);
+
+    assert_eq!(description.mirror_sign(hip(Axis::Yaw)), None);
+    // Rolling about a backward axis on the right mirrors rolling about a
+    // forward one on the left.
+    assert_eq!(description.mirror_sign(hip(Axis::Roll)), Some(1.0));
+    // The turned frame also points the right pitch axis to the right.
+    assert_eq!(description.mirror_sign(hip(Axis::Pitch)), Some(-1.0));
+    assert_eq!(
+      description.mirror_sign(hip(Axis::Pitch).mirror()),
+      Some(-1.0)
+    );
+
+    let description = urdf(&HIPS.replace("0 0 3.14159265358979", "0 0 0"));
+    assert_eq!(description.mirror_sign(hip(Axis::Roll)), Some(-1.0));
+    assert_eq!(description.mirror_sign(hip(Axis::Pitch)), Some(1.0));
+  }
+
+  #[test]
+  fn kinematics_take_link_lengths_from_the_file() {
+    let kinematics =
+      urdf(&HIPS.replace("<axis", "<origin xyz=\"0 0 -0.2\"/><axis"))
+        .kinematics()
+        .unwrap();
+    let foot = |limb| {
+      kinematics
+        .end_effector(limb, &Pose::new())
+        .map(|effector| effector.position)
+    };
+
+    assert_eq!(kinematics.chains.len(), 2);
+    assert!(kinematics.chain(Limb::LeftArm).is_none());
+    assert_eq!(foot(Limb::LeftLeg), Some([0.0, 0.1, -0.4]));
+  }
+
+  #[test]
+  fn mirror_sign_falls_back_to_the_robot_table() {
+    let description = urdf(HIPS);
+
+    assert_eq!(
+      mirror_sign::<KBot>(hip(Axis::Roll), Some(&description)),
+      1.0
+    );
+    assert_eq!(mirror_sign::<KBot>(hip(Axis::Roll), None), -1.0);
+    assert_eq!(
+      mirror_sign::<KBot>(hip(Axis::Yaw), Some(&description)),
+      -1.0
+    );
+  }
+
+  fn assert_close(a: [f64; 3], b: [f64; 3]) {
+    let distance = (Vector3::from(a) - Vector3::from(b)).norm();
+    assert!(distance < 1e-9, "{a:?} != {b:?}");
+  }
+
+  #[test]
+  fn urdf_limits_without_bounds_do_not_fail_the_parse() {
+    let description = urdf(WHEELED);
+    let arm = |joint, axis| {
+      description
+        .joint(JointAxis::new(joint, Some(axis)))
+        .unwrap()
+        .clone()
+    };
+
+    let wheel = &description.joints[0];
+    assert!(wheel.revolute);
+    assert_eq!(wheel.limits, None);
+
+    let shoulder = arm(Joint::LeftShoulder, Axis::Pitch);
+    assert_eq!(shoulder.limits, None);
+    assert_eq!(shoulder.axis, [1.0, 0.0, 0.0]);
+
+    let [min, max] = arm(Joint::LeftElbow, Axis::Yaw).limits.unwrap();
+    assert!((min + 90.0).abs() < 1e-9 && (max - 45.0).abs() < 1e-9);
+    assert!(description.kinematics().is_ok());
+  }
+
+  #[test]
+  fn mjcf_geoms_and_joints_use_their_default_classes() {
+    let description =
+      RobotDescription::from_mjcf(&Document::parse(MJCF).unwrap()).unwrap();
+    assert_eq!(description.name, "arm");
+
+    let shoulder = description
+      .joint(JointAxis::new(Joint::LeftShoulder, Some(Axis::Pitch)))
+      .unwrap();
+    assert_eq!(shoulder.parent, "torso");
+    assert_eq!(shoulder.axis, [0.0, 1.0, 0.0]);
+    assert_eq!(shoulder.limits, Some([-90.0, 90.0]));
+    // The link frame sits at the joint, not the body origin.
+    assert_close(shoulder.origin.xyz, [0.0, 0.2, 0.35]);
+
+    let elbow = description
+      .joint(JointAxis::new(Joint::LeftElbow, Some(Axis::Yaw)))
+      .unwrap();
+    assert_eq!(elbow.limits, None);
+    assert_close(elbow.origin.xyz, [0.0, 0.0, -0.35]);
+
+    let shapes = &description.link("upper_arm").unwrap().shapes;
+    assert_eq!(shapes.len(), 2);
+    assert_eq!(
+      shapes[0].geometry,
+      Geometry::Box {
+        size: [0.2, 0.4, 0.6]
+      }
+    );
+    assert_close(shapes[0].origin.xyz, [0.0, 0.0, -0.05]);
+    assert_close(shapes[0].origin.rpy, [0.0, 0.0, 90f64.to_radians()]);
+    // The geom's own orientation wins over its class's.
+    assert_close(shapes[1].origin.rpy, [0.0; 3]);
+  }
+
+  #[test]
+  fn mjcf_bodies_with_several_hinges_chain_them() {
+    let description =
+      RobotDescription::from_mjcf(&Document::parse(MJCF_HIP).unwrap()).unwrap();
+
+    let pitch = description.joint(hip(Axis::Pitch)).unwrap();
+    let roll = description.joint(hip(Axis::Roll)).unwrap();
+    assert_eq!(pitch.parent, "torso");
+    assert_eq!(pitch.child, roll.parent);
+    assert_eq!(roll.child, "thigh");
+    assert_close(pitch.origin.xyz, [0.0, 0.1, 0.0]);
+    assert_close(roll.origin.xyz, [0.0, 0.0, -0.05]);
+    assert!(description.link(&pitch.child).unwrap().shapes.is_empty());
+
+    let kinematics = description.kinematics().unwrap();
+    let foot = |pose: &Pose| {
+      kinematics
+        .end_effector(Limb::LeftLeg, pose)
+        .unwrap()
+        .position
+    };
+
+    assert_eq!(kinematics.chain(Limb::LeftLeg).unwrap().joints.len(), 2);
+    assert_close(foot(&Pose::new()), [0.0, 0.1, -0.4]);
+    assert_close(
+      foot(&Pose::new().with(Joint::LeftHip, Some(Axis::Pitch), 90.0)),
+      [-0.4, 0.1, 0.0],
+    );
+    assert_close(
+      foot(&Pose::new().with(Joint::LeftHip, Some(Axis::Roll), 90.0)),
+      [0.0, 0.45, -0.05],
+    );
+  }
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+use crate::{fall::FallAction, watchdog::WatchdogAction};
+
+/// Notable things happening on the robot, broadcast to anyone subscribed
+/// through [`crate::KBot::subscribe`].
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RobotEvent {
+  Fall {
+    /// Angle between the measured and upright gravity vector, in degrees.
+    tilt: f64,
+    /// Magnitude of the acceleration sample, in m/s^2.
+    accel: f64,
+    action: FallAction,
+  },
+  FallReset,
+  /// A controller went silent and its joints were put in a safe state.
+  WatchdogTripped {
+    controller: String,
+    action: WatchdogAction,
+  },
+  /// A controller lost its actuators to a higher priority one.
+  ControllerPreempted {
+    controller: String,
+    by: String,
+  },
+}
@@ -0,0 +1,287 @@
+use std::{collections::HashMap, fs::File, path::Path, sync::Arc};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  mcap::{self, McapMessage},
+  policy::{Observation, PolicyActions, POLICY_ACTUATORS, POLICY_JOINTS},
+  recorder::{StatesMessage, Topic},
+  Robot,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+  Csv,
+  Parquet,
+}
+
+impl ExportFormat {
+  pub fn extension(self) -> &'static str {
+    match self {
+      ExportFormat::Csv => "csv",
+      ExportFormat::Parquet => "parquet",
+    }
+  }
+}
+
+/// Converts the MCAP recording at `input` into a dataset at `output` and
+/// returns the number of rows written.
+pub fn export<R: Robot>(
+  input: &Path,
+  output: &Path,
+  format: ExportFormat,
+) -> eyre::Result<usize> {
+  let messages = mcap::read_messages(&std::fs::read(input)?)?;
+  let dataset = Dataset::from_recording::<R>(&messages)?;
+  dataset.write(output, format)?;
+
+  Ok(dataset.rows())
+}
+
+/// One row per walk loop tick of a recording.
+///
+/// Columns use the field names of `ml/inference/inference_server.py`:
+/// vectors are split into `<field>_0` to `<field>_2`, and per-joint values
+/// into `<field>_<joint>` with the joint names of its `OUTPUT_FIELDS`.
+/// `actions_<joint>` are the unscaled outputs the policy returned for the
+/// row's observation, the same values the server feeds back as the next
+/// `actions` input. They keep the policy's output names, which swap hip yaw
+/// and roll, so the actuators they drive are in `target_<joint>`: the
+/// position commanded to the actuator whose `dof_pos_<joint>` is in the same
+/// row. `torque_<joint>` and `temperature_<joint>` are the latest actuator
+/// readings.
+#[derive(Debug, Clone)]
+pub struct Dataset {
+  /// Nanoseconds since the Unix epoch.
+  pub log_time: Vec<u64>,
+  pub columns: Vec<(String, Vec<Option<f64>>)>,
+}
+
+impl Dataset {
+  pub fn from_recording<R: Robot>(
+    messages: &[McapMessage],
+  ) -> eyre::Result<Self> {
+    let actuator_ids = POLICY_ACTUATORS
+      .iter()
+      .map(|(joint, axis)| R::get_actuator_id(*joint, Some(*axis)))
+      .collect::<Vec<_>>();
+
+    let mut log_time = Vec::new();
+    let mut rows = Vec::new();
+    let mut states = HashMap::new();
+    for message in messages {
+      match Topic::from_name(&message.topic) {
+        Some(Topic::ActuatorStates) => {
+          let message: StatesMessage = serde_json::from_slice(&message.data)?;
+          for state in message.states {
+            states.insert(state.actuator_id, state);
+          }
+        }
+        Some(Topic::PolicyObservation) => {
+          let obs: Observation = serde_json::from_slice(&message.data)?;
+          let state =
+            |index: usize| actuator_ids[index].and_then(|id| states.get(&id));
+
+          let mut row = HashMap::new();
+          for (field, values) in [
+            ("base_ang_vel", obs.base_ang_vel),
+            ("accel", obs.accel),
+            ("commands", obs.commands),
+          ] {
+            for (index, value) in values.into_iter().enumerate() {
+              row.insert(format!("{field}_{index}"), value);
+            }
+          }
+          for (index, joint) in POLICY_JOINTS.iter().enumerate() {
+            let mut insert = |field: &str, value: Option<f64>| {
+              if let Some(value) = value {
+                row.insert(format!("{field}_{joint}"), value);
+              }
+            };
+            insert("dof_pos", obs.dof_pos.get(index).copied());
+            insert("dof_vel", obs.dof_vel.get(index).copied());
+            insert("torque", state(index).and_then(|state| state.torque));
+            insert(
+              "temperature",
+              state(index).and_then(|state| state.temperature),
+            );
+          }
+
+          log_time.push(message.log_time);
+          rows.push(row);
+        }
+        Some(Topic::PolicyActions) => {
+          let actions: PolicyActions = serde_json::from_slice(&message.data)?;
+          let Some(row) = rows.last_mut() else {
+            continue;
+          };
+
+          for (joint, value) in POLICY_JOINTS.iter().zip(actions.actions) {
+            row.insert(format!("actions_{joint}"), value);
+          }
+          for (joint, id) in POLICY_JOINTS.iter().zip(&actuator_ids) {
+            if let Some(target) = id.and_then(|id| actions.targets.get(&id)) {
+              row.insert(format!("target_{joint}"), *target);
+            }
+          }
+        }
+        Some(
+          Topic::Commands | Topic::Imu | Topic::Orientation | Topic::LoopTiming,
+        )
+        | None => {}
+      }
+    }
+
+    let mut names = ["base_ang_vel", "accel", "commands"]
+      .iter()
+      .flat_map(|field| (0..3).map(move |index| format!("{field}_{index}")))
+      .collect::<Vec<_>>();
+    for field in [
+      "dof_pos",
+      "dof_vel",
+      "actions",
+      "target",
+      "torque",
+      "temperature",
+    ] {
+      names
+        .extend(POLICY_JOINTS.iter().map(|joint| format!("{field}_{joint}")));
+    }
+
+    let columns = names
+      .into_iter()
+      .map(|name| {
+        let values = rows.iter().map(|row| row.get(&name).copied()).collect();
+        (name, values)
+      })
+      .collect();
+
+    Ok(Self { log_time, columns })
+  }
+
+  pub fn rows(&self) -> usize {
+    self.log_time.len()
+  }
+
+  pub fn write(&self, path: &Path, format: ExportFormat) -> eyre::Result<()> {
+    match format {
+      ExportFormat::Csv => self.write_csv(path),
+      ExportFormat::Parquet => self.write_parquet(path),
+    }
+  }
+
+  /// Missing values are left empty.
+  pub fn write_csv(&self, path: &Path) -> eyre::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    writer.write_record(
+      std::iter::once("log_time")
+        .chain(self.columns.iter().map(|(name, _)| name.as_str())),
+    )?;
+    for (row, log_time) in self.log_time.iter().enumerate() {
+      writer.write_record(std::iter::once(log_time.to_string()).chain(
+        self.columns.iter().map(|(_, values)| {
+          values[row]
+            .map(|value| value.to_string())
+            .unwrap_or_default()
+        }),
+      ))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+  }
+
+  /// Missing values are written as nulls.
+  pub fn write_parquet(&self, path: &Path) -> eyre::Result<()> {
+    let schema = Arc::new(Schema::new(
+      std::iter::once(Field::new("log_time", DataType::UInt64, false))
+        .chain(
+          self
+            .columns
+            .iter()
+            .map(|(name, _)| Field::new(name, DataType::Float64, true)),
+        )
+        .collect::<Vec<_>>(),
+    ));
+
+    let arrays = std::iter::once(Arc::new(UInt64Array::from(
+      self.log_time.clone(),
+    )) as ArrayRef)
+    .chain(self.columns.iter().map(|(_, values)| {
+      Arc::new(Float64Array::from(values.clone())) as ArrayRef
+    }))
+    .collect();
+    let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{policy::JointAngles, KBot};
+
+  fn message(
+    topic: Topic,
+    log_time: u64,
+    data: &impl Serialize,
+  ) -> McapMessage {
+    McapMessage {
+      topic: topic.name().to_string(),
+      log_time,
+      data: serde_json::to_vec(data).unwrap(),
+    }
+  }
+
+  #[test]
+  fn actions_are_raw_and_targets_follow_the_actuators() {
+    let obs = Observation {
+      base_ang_vel: [0.0; 3],
+      accel: [0.0, 0.0, 9.81],
+      commands: [0.0; 3],
+      dof_pos: (0..10).map(|index| index as f64).collect(),
+      dof_vel: vec![0.0; 10],
+      actions: vec![0.0; 10],
+    };
+    // Scaled outputs, so the raw ones are 0, 1, 2, ... in policy order.
+    let scaled = (0..10).map(|index| index as f64 * 0.25).collect::<Vec<_>>();
+    let joints = JointAngles::from_ordered(&scaled).unwrap();
+    let actions = PolicyActions::new::<KBot>(&joints).unwrap();
+
+    let dataset = Dataset::from_recording::<KBot>(&[
+      message(Topic::PolicyObservation, 1, &obs),
+      message(Topic::PolicyActions, 2, &actions),
+    ])
+    .unwrap();
+    let column = |name: &str| {
+      dataset
+        .columns
+        .iter()
+        .find(|(column, _)| column == name)
+        .map(|(_, values)| values[0])
+        .unwrap()
+    };
+
+    assert_eq!(dataset.rows(), 1);
+    for (index, joint) in POLICY_JOINTS.iter().enumerate() {
+      assert_eq!(column(&format!("actions_{joint}")), Some(index as f64));
+    }
+    // The policy's hip yaw output drives the hip roll actuator.
+    assert_eq!(column("dof_pos_L_Hip_Yaw"), Some(3.0));
+    assert_eq!(column("target_L_Hip_Yaw"), Some(1.25));
+    assert_eq!(column("target_L_Hip_Roll"), Some(0.75));
+    assert_eq!(column("target_R_Hip_Yaw"), Some(1.0));
+    assert_eq!(column("target_R_Hip_Roll"), Some(0.5));
+    assert_eq!(column("target_L_Ankle_Pitch"), Some(2.25));
+  }
+}
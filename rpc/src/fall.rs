@@ -0,0 +1,458 @@
+use std::{
+  collections::BTreeMap,
+  fmt,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::Instrument;
+
+use crate::{
+  events::RobotEvent,
+  hal::{check_response, check_results, Hal},
+  health::HealthMonitor,
+  profiler::Profiler,
+  proto::actuator::ConfigureActuatorRequest,
+  ActuatorCommand,
+};
+
+/// Standard gravity, in m/s^2.
+const GRAVITY: f64 = 9.81;
+
+/// What to do once a fall is detected. Whatever the action, every joint
+/// command is refused with [`Fallen`] until the detector is reset.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum FallAction {
+  /// Only refuse further commands, leaving the joints where they are.
+  Stop,
+  /// Turn torque off on every actuator.
+  Limp,
+  /// Move to a protective pose, given as positions keyed by actuator ID.
+  Crouch { positions: BTreeMap<u32, f64> },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FallConfig {
+  pub poll_interval_ms: u64,
+  /// Accelerometer reading direction when the robot stands upright.
+  pub up: [f64; 3],
+  /// Tilt, in degrees, past which the robot is falling.
+  pub max_tilt: f64,
+  /// How long the tilt has to stay past `max_tilt`.
+  pub tilt_time_ms: u64,
+  /// Deviation of the acceleration magnitude from 1g, in m/s^2, that counts
+  /// as an impact.
+  pub impact_accel: f64,
+  /// Tilt, in degrees, past which an impact counts as a fall.
+  pub impact_tilt: f64,
+  pub action: FallAction,
+}
+
+impl Default for FallConfig {
+  fn default() -> Self {
+    Self {
+      poll_interval_ms: 20,
+      up: [0.0, 0.0, 1.0],
+      max_tilt: 45.0,
+      tilt_time_ms: 100,
+      impact_accel: 15.0,
+      impact_tilt: 25.0,
+      action: FallAction::Stop,
+    }
+  }
+}
+
+/// Returned for commands sent after a fall, until the detector is reset.
+#[derive(Debug, Clone)]
+pub struct Fallen;
+
+impl fmt::Display for Fallen {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "The robot has fallen; reset the fall detector first")
+  }
+}
+
+impl std::error::Error for Fallen {}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FallStatus {
+  pub fallen: bool,
+  pub tilt: Option<f64>,
+}
+
+/// Watches the IMU for tipping and impacts and reacts to a fall.
+#[derive(Debug)]
+pub struct FallDetector {
+  hal: Hal,
+  config: FallConfig,
+  actuator_ids: Vec<u32>,
+  health: Arc<HealthMonitor>,
+  events: broadcast::Sender<RobotEvent>,
+  profiler: Arc<Profiler>,
+  fallen: AtomicBool,
+  tilt: std::sync::Mutex<Option<f64>>,
+}
+
+impl FallDetector {
+  pub fn new(
+    hal: Hal,
+    config: FallConfig,
+    actuator_ids: Vec<u32>,
+    health: Arc<HealthMonitor>,
+    events: broadcast::Sender<RobotEvent>,
+    profiler: Arc<Profiler>,
+  ) -> Self {
+    Self {
+      hal,
+      config,
+      actuator_ids,
+      health,
+      events,
+      profiler,
+      fallen: AtomicBool::new(false),
+      tilt: std::sync::Mutex::new(None),
+    }
+  }
+
+  /// Whether a fall was detected and has not been reset. Control loops should
+  /// stop commanding joints while this is set.
+  pub fn has_fallen(&self) -> bool {
+    self.fallen.load(Ordering::SeqCst)
+  }
+
+  /// Fails with [`Fallen`] while a fall has not been reset.
+  pub fn check(&self) -> Result<(), Fallen> {
+    if self.has_fallen() {
+      return Err(Fallen);
+    }
+
+    Ok(())
+  }
+
+  pub fn status(&self) -> FallStatus {
+    FallStatus {
+      fallen: self.has_fallen(),
+      tilt: *self.tilt.lock().unwrap(),
+    }
+  }
+
+  /// Re-arms the detector once the robot is back on its feet.
+  pub fn reset(&self) {
+    if self.fallen.swap(false, Ordering::SeqCst) {
+      self.events.send(RobotEvent::FallReset).ok();
+    }
+  }
+
+  pub fn spawn(self: &Arc<Self>) {
+    let detector = self.clone();
+
+    tokio::spawn(
+      async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(
+          detector.config.poll_interval_ms,
+        ));
+        let mut tilted_since = None;
+
+        loop {
+          interval.tick().await;
+
+          let mut tick = detector.profiler.tick("fall");
+          let data = match tick.time("imu", detector.hal.imu.get_values()).await
+          {
+            Ok(data) => data,
+            Err(e) => {
+              tracing::warn!(error = %e, "Fall detector failed to read IMU");
+              continue;
+            }
+          };
+
+          let accel = [data.accel_x, data.accel_y, data.accel_z];
+          let magnitude = accel.iter().map(|a| a * a).sum::<f64>().sqrt();
+          let tilt = tilt(accel, detector.config.up);
+          *detector.tilt.lock().unwrap() = Some(tilt);
+
+          if detector.has_fallen() {
+            continue;
+          }
+
+          let tipped = if tilt > detector.config.max_tilt {
+            let since = *tilted_since.get_or_insert_with(Instant::now);
+            since.elapsed()
+              >= Duration::from_millis(detector.config.tilt_time_ms)
+          } else {
+            tilted_since = None;
+            false
+          };
+
+          let impact = (magnitude - GRAVITY).abs()
+            > detector.config.impact_accel
+            && tilt > detector.config.impact_tilt;
+
+          if tipped || impact {
+            detector.fallen.store(true, Ordering::SeqCst);
+            tilted_since = None;
+
+            tracing::warn!(tilt, accel = magnitude, "Fall detected");
+
+            if let Err(e) = tick.time("protect", detector.protect()).await {
+              tracing::error!(error = %e, "Fall protection failed");
+            }
+
+            detector
+              .events
+              .send(RobotEvent::Fall {
+                tilt,
+                accel: magnitude,
+                action: detector.config.action.clone(),
+              })
+              .ok();
+          }
+        }
+      }
+      .instrument(tracing::info_span!("fall_detector")),
+    );
+  }
+
+  async fn protect(&self) -> eyre::Result<()> {
+    match &self.config.action {
+      FallAction::Stop => {}
+      FallAction::Limp => {
+        for &actuator_id in &self.actuator_ids {
+          let response = self
+            .hal
+            .actuators
+            .configure_actuator(ConfigureActuatorRequest {
+              actuator_id,
+              torque_enabled: Some(false),
+              ..Default::default()
+            })
+            .await?;
+
+          check_response(actuator_id, &response)?;
+        }
+      }
+      FallAction::Crouch { positions } => {
+        let commands = positions
+          .iter()
+          .map(|(&actuator_id, &position)| ActuatorCommand {
+            actuator_id,
+            position: Some(position),
+            velocity: None,
+            torque: None,
+          })
+          .collect::<Vec<_>>();
+
+        self.health.record_commands(&commands).await;
+
+        check_results(&self.hal.actuators.command_actuators(commands).await?)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Angle, in degrees, between an accelerometer sample and the upright axis.
+fn tilt(accel: [f64; 3], up: [f64; 3]) -> f64 {
+  let norm = |v: [f64; 3]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+  let dot = accel.iter().zip(up).map(|(a, u)| a * u).sum::<f64>();
+  let denom = norm(accel) * norm(up);
+
+  if denom <= f64::EPSILON {
+    return 0.0;
+  }
+
+  (dot / denom).clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    gains::{GainManager, GainProfiles},
+    health::HealthConfig,
+    metrics::Metrics,
+    profiler::ProfilerConfig,
+    sim::{SimConfig, Simulator},
+    KBot,
+  };
+
+  /// Accelerometer reading of a robot at rest after rolling and then
+  /// pitching by the given angles, in degrees.
+  fn at_rest(roll: f64, pitch: f64) -> [f64; 3] {
+    let (roll, pitch) = (roll.to_radians(), pitch.to_radians());
+
+    [
+      -GRAVITY * pitch.sin(),
+      GRAVITY * roll.sin() * pitch.cos(),
+      GRAVITY * roll.cos() * pitch.cos(),
+    ]
+  }
+
+  #[test]
+  fn tilt_is_the_angle_from_upright() {
+    let up = FallConfig::default().up;
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+
+    assert!(close(tilt(at_rest(0.0, 0.0), up), 0.0));
+    assert!(close(tilt(at_rest(30.0, 0.0), up), 30.0));
+    assert!(close(tilt(at_rest(0.0, -45.0), up), 45.0));
+    assert!(close(tilt(at_rest(90.0, 0.0), up), 90.0));
+    assert!(close(
+      tilt(at_rest(60.0, 60.0), up),
+      0.25f64.acos().to_degrees()
+    ));
+    assert!(close(tilt([0.0, 0.0, -GRAVITY], up), 180.0));
+  }
+
+  #[test]
+  fn tilt_is_measured_from_the_configured_up_axis() {
+    assert!((tilt([GRAVITY, 0.0, 0.0], [1.0, 0.0, 0.0])).abs() < 1e-6);
+    assert!((tilt([0.0, 0.0, GRAVITY], [1.0, 0.0, 0.0]) - 90.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn tilt_without_acceleration_is_upright() {
+    assert_eq!(tilt([0.0; 3], [0.0, 0.0, 1.0]), 0.0);
+    assert_eq!(tilt([0.0, 0.0, GRAVITY], [0.0; 3]), 0.0);
+  }
+
+  fn detector(
+    orientation: [f64; 3],
+    action: FallAction,
+  ) -> (
+    Arc<FallDetector>,
+    Simulator,
+    broadcast::Receiver<RobotEvent>,
+  ) {
+    let simulator = Simulator::new::<KBot>(
+      SimConfig {
+        orientation,
+        ..Default::default()
+      },
+      None,
+    );
+    let hal = simulator.hal();
+    let gains = GainManager::new::<KBot>(
+      hal.clone(),
+      Vec::new(),
+      GainProfiles::default(),
+    )
+    .unwrap();
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let profiler = Arc::new(Profiler::new(ProfilerConfig::default(), metrics));
+    let health = Arc::new(HealthMonitor::new(
+      hal.clone(),
+      HealthConfig::default(),
+      Vec::new(),
+      Arc::new(gains),
+      profiler.clone(),
+    ));
+    let (events, receiver) = broadcast::channel(16);
+
+    let detector = Arc::new(FallDetector::new(
+      hal,
+      FallConfig {
+        action,
+        ..Default::default()
+      },
+      vec![34, 35],
+      health,
+      events,
+      profiler,
+    ));
+
+    (detector, simulator, receiver)
+  }
+
+  async fn enable(detector: &FallDetector, actuator_id: u32) {
+    let response = detector
+      .hal
+      .actuators
+      .configure_actuator(ConfigureActuatorRequest {
+        actuator_id,
+        torque_enabled: Some(true),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+    assert!(response.success);
+  }
+
+  async fn position(detector: &FallDetector, actuator_id: u32) -> f64 {
+    detector
+      .hal
+      .actuators
+      .get_actuators_state(vec![actuator_id])
+      .await
+      .unwrap()[0]
+      .position()
+  }
+
+  #[tokio::test]
+  async fn tipping_over_crouches() {
+    let action = FallAction::Crouch {
+      positions: BTreeMap::from([(34, 30.0)]),
+    };
+    let (detector, simulator, mut events) = detector([90.0, 0.0, 0.0], action);
+    simulator.spawn();
+    enable(&detector, 34).await;
+    detector.spawn();
+
+    let event = tokio::time::timeout(Duration::from_secs(1), events.recv())
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(matches!(
+      event,
+      RobotEvent::Fall {
+        action: FallAction::Crouch { .. },
+        ..
+      }
+    ));
+    assert!(detector.has_fallen());
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let knee = position(&detector, 34).await;
+    assert!((knee - 30.0).abs() < 1.0, "knee at {knee}°");
+  }
+
+  #[tokio::test]
+  async fn standing_upright_is_not_a_fall() {
+    let (detector, _simulator, mut events) =
+      detector([10.0, 0.0, 0.0], FallAction::Limp);
+    detector.spawn();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!detector.has_fallen());
+    assert!(events.try_recv().is_err());
+    assert!(detector.status().tilt.is_some_and(|tilt| tilt < 11.0));
+  }
+
+  #[tokio::test]
+  async fn going_limp_turns_torque_off() {
+    let (detector, simulator, _) = detector([0.0; 3], FallAction::Limp);
+    enable(&detector, 34).await;
+    enable(&detector, 35).await;
+
+    detector.protect().await.unwrap();
+    assert_eq!(simulator.torque_enabled(34), Some(false));
+    assert_eq!(simulator.torque_enabled(35), Some(false));
+  }
+
+  #[tokio::test]
+  async fn stopping_leaves_the_joints_alone() {
+    let (detector, simulator, _) = detector([0.0; 3], FallAction::Stop);
+    enable(&detector, 34).await;
+
+    detector.protect().await.unwrap();
+    assert_eq!(simulator.torque_enabled(34), Some(true));
+    assert_eq!(position(&detector, 34).await, 0.0);
+  }
+}
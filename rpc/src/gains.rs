@@ -0,0 +1,411 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+  hal::{check_response, Hal},
+  pose::JointAxis,
+  proto::actuator::ConfigureActuatorRequest,
+  Robot,
+};
+
+/// Controller gains and torque limits for one actuator. Unset fields are left
+/// untouched on the actuator.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ActuatorGains {
+  pub kp: Option<f64>,
+  pub kd: Option<f64>,
+  pub ki: Option<f64>,
+  pub max_torque: Option<f64>,
+  pub protective_torque: Option<f32>,
+  pub protection_time: Option<f32>,
+}
+
+impl ActuatorGains {
+  /// Fills the unset fields of `self` from `fallback`.
+  pub fn or(&self, fallback: &ActuatorGains) -> ActuatorGains {
+    ActuatorGains {
+      kp: self.kp.or(fallback.kp),
+      kd: self.kd.or(fallback.kd),
+      ki: self.ki.or(fallback.ki),
+      max_torque: self.max_torque.or(fallback.max_torque),
+      protective_torque: self.protective_torque.or(fallback.protective_torque),
+      protection_time: self.protection_time.or(fallback.protection_time),
+    }
+  }
+
+  /// Names of the fields that are not set.
+  pub fn unset(&self) -> Vec<&'static str> {
+    [
+      ("kp", self.kp.is_none()),
+      ("kd", self.kd.is_none()),
+      ("ki", self.ki.is_none()),
+      ("max_torque", self.max_torque.is_none()),
+      ("protective_torque", self.protective_torque.is_none()),
+      ("protection_time", self.protection_time.is_none()),
+    ]
+    .into_iter()
+    .filter_map(|(name, unset)| unset.then_some(name))
+    .collect()
+  }
+
+  pub fn to_request(&self, actuator_id: u32) -> ConfigureActuatorRequest {
+    ConfigureActuatorRequest {
+      actuator_id,
+      kp: self.kp,
+      kd: self.kd,
+      ki: self.ki,
+      max_torque: self.max_torque,
+      protective_torque: self.protective_torque,
+      protection_time: self.protection_time,
+      ..Default::default()
+    }
+  }
+}
+
+/// A named set of gains, e.g. "stiff" for standing or "soft" for teach mode.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct GainProfile {
+  /// Applied to every joint without an entry in `joints`.
+  #[serde(default)]
+  pub default: ActuatorGains,
+  /// Per-joint overrides, keyed by joint name, e.g. `left_knee_pitch`.
+  #[serde(default)]
+  pub joints: BTreeMap<JointAxis, ActuatorGains>,
+}
+
+impl GainProfile {
+  pub fn gains(&self, joint: JointAxis) -> ActuatorGains {
+    match self.joints.get(&joint) {
+      Some(gains) => gains.or(&self.default),
+      None => self.default.clone(),
+    }
+  }
+
+  /// Gains of every actuator in `actuator_ids`, filled in from `defaults`.
+  /// Fails if any field is left unset, since the actuator would keep
+  /// whatever the previous profile set it to.
+  fn resolve<R: Robot>(
+    &self,
+    defaults: &ActuatorGains,
+    actuator_ids: &[u32],
+  ) -> eyre::Result<BTreeMap<u32, ActuatorGains>> {
+    for joint in self.joints.keys() {
+      if !joint
+        .actuator_id::<R>()
+        .is_some_and(|actuator_id| actuator_ids.contains(&actuator_id))
+      {
+        return Err(eyre::eyre!("Joint {joint} has no actuator"));
+      }
+    }
+
+    actuator_ids
+      .iter()
+      .map(|&actuator_id| {
+        let gains = match JointAxis::from_actuator_id::<R>(actuator_id) {
+          Some(joint) => self.gains(joint),
+          None => self.default.clone(),
+        }
+        .or(defaults);
+
+        let unset = gains.unset();
+        if !unset.is_empty() {
+          return Err(eyre::eyre!(
+            "{} of actuator {actuator_id} is not set",
+            unset.join(", ")
+          ));
+        }
+
+        Ok((actuator_id, gains))
+      })
+      .collect()
+  }
+}
+
+/// Gain profiles as stored on disk.
+///
+/// ```json
+/// {
+///   "initial": "stiff",
+///   "defaults": {
+///     "kp": 150.0, "kd": 5.0, "ki": 0.0, "max_torque": 40.0,
+///     "protective_torque": 30.0, "protection_time": 1.0
+///   },
+///   "profiles": {
+///     "stiff": {},
+///     "soft": {
+///       "default": { "kp": 20.0, "kd": 1.0, "max_torque": 20.0 },
+///       "joints": { "left_knee_pitch": { "kp": 40.0 } }
+///     }
+///   }
+/// }
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct GainProfiles {
+  /// Profile applied when the robot is initialized.
+  pub initial: Option<String>,
+  /// Gains every profile is applied over. Together with a profile they have
+  /// to set every field, so that switching profiles leaves nothing behind
+  /// from the previous one.
+  #[serde(default)]
+  pub defaults: ActuatorGains,
+  #[serde(default)]
+  pub profiles: BTreeMap<String, GainProfile>,
+}
+
+impl GainProfiles {
+  /// Loads profiles from `path`, or none if the file does not exist.
+  pub async fn load(path: &Path) -> eyre::Result<Self> {
+    match tokio::fs::read(path).await {
+      Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(e.into()),
+    }
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GainStatus {
+  pub active: Option<String>,
+  pub profiles: BTreeMap<String, GainProfile>,
+}
+
+/// Applies gain profiles to the actuators and tracks which one is active.
+#[derive(Debug)]
+pub struct GainManager {
+  hal: Hal,
+  profiles: BTreeMap<String, GainProfile>,
+  /// Gains of every actuator keyed by profile, then by actuator ID.
+  resolved: BTreeMap<String, BTreeMap<u32, ActuatorGains>>,
+  active: Mutex<Option<String>>,
+}
+
+impl GainManager {
+  pub fn new<R: Robot>(
+    hal: Hal,
+    actuator_ids: Vec<u32>,
+    profiles: GainProfiles,
+  ) -> eyre::Result<Self> {
+    let resolved = profiles
+      .profiles
+      .iter()
+      .map(|(name, profile)| {
+        let gains = profile
+          .resolve::<R>(&profiles.defaults, &actuator_ids)
+          .map_err(|e| eyre::eyre!("Gain profile {name}: {e}"))?;
+
+        Ok((name.clone(), gains))
+      })
+      .collect::<eyre::Result<_>>()?;
+
+    Ok(Self {
+      hal,
+      profiles: profiles.profiles,
+      resolved,
+      active: Mutex::new(None),
+    })
+  }
+
+  /// Gains profile `name` sets on an actuator.
+  pub fn gains(
+    &self,
+    name: &str,
+    actuator_id: u32,
+  ) -> eyre::Result<ActuatorGains> {
+    self
+      .resolved
+      .get(name)
+      .ok_or_else(|| eyre::eyre!("Unknown gain profile {name}"))?
+      .get(&actuator_id)
+      .cloned()
+      .ok_or_else(|| eyre::eyre!("Unknown actuator {actuator_id}"))
+  }
+
+  /// Gains the active profile set on an actuator, if a profile is active.
+  pub async fn active_gains(&self, actuator_id: u32) -> Option<ActuatorGains> {
+    let active = self.active.lock().await;
+
+    self.gains(active.as_deref()?, actuator_id).ok()
+  }
+
+  pub async fn status(&self) -> GainStatus {
+    GainStatus {
+      active: self.active.lock().await.clone(),
+      profiles: self.profiles.clone(),
+    }
+  }
+
+  /// Configures every actuator with the gains from profile `name`. If an
+  /// actuator fails, the ones already configured go back to the previously
+  /// active profile.
+  pub async fn apply(&self, name: &str) -> eyre::Result<()> {
+    let Some(profile) = self.resolved.get(name) else {
+      return Err(eyre::eyre!("Unknown gain profile {name}"));
+    };
+
+    let mut active = self.active.lock().await;
+    let mut configured = Vec::new();
+
+    for (&actuator_id, gains) in profile {
+      if let Err(e) = self.configure(actuator_id, gains).await {
+        self.roll_back(active.as_deref(), &configured).await;
+
+        return Err(e.wrap_err(format!(
+          "Failed to apply gain profile {name} to actuator {actuator_id}"
+        )));
+      }
+      configured.push(actuator_id);
+    }
+
+    *active = Some(name.to_string());
+
+    Ok(())
+  }
+
+  async fn roll_back(&self, previous: Option<&str>, actuator_ids: &[u32]) {
+    let Some(previous) = previous.and_then(|name| self.resolved.get(name))
+    else {
+      if !actuator_ids.is_empty() {
+        tracing::warn!(
+          ?actuator_ids,
+          "No gain profile was active to roll back to"
+        );
+      }
+      return;
+    };
+
+    for actuator_id in actuator_ids {
+      let Some(gains) = previous.get(actuator_id) else {
+        continue;
+      };
+
+      if let Err(e) = self.configure(*actuator_id, gains).await {
+        tracing::error!(actuator_id, error = %e, "Failed to roll back gains");
+      }
+    }
+  }
+
+  async fn configure(
+    &self,
+    actuator_id: u32,
+    gains: &ActuatorGains,
+  ) -> eyre::Result<()> {
+    let response = self
+      .hal
+      .actuators
+      .configure_actuator(gains.to_request(actuator_id))
+      .await?;
+
+    check_response(actuator_id, &response)
+  }
+
+  /// Records `name` as active without reconfiguring the actuators, for when
+  /// the gains were already sent as part of another request.
+  pub(crate) async fn set_active(&self, name: Option<String>) {
+    *self.active.lock().await = name;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    sim::{SimConfig, Simulator},
+    Axis, Joint, KBot,
+  };
+
+  fn full() -> ActuatorGains {
+    ActuatorGains {
+      kp: Some(150.0),
+      kd: Some(5.0),
+      ki: Some(0.0),
+      max_torque: Some(40.0),
+      protective_torque: Some(30.0),
+      protection_time: Some(1.0),
+    }
+  }
+
+  #[test]
+  fn joint_overrides_fall_back_to_profile_then_defaults() {
+    let profile: GainProfile = serde_json::from_str(
+      r#"{
+        "default": { "kp": 20.0, "max_torque": 20.0 },
+        "joints": { "left_knee_pitch": { "kp": 40.0 } }
+      }"#,
+    )
+    .unwrap();
+
+    let gains = profile.resolve::<KBot>(&full(), &[34, 44]).unwrap();
+
+    assert_eq!(gains[&34].kp, Some(40.0));
+    assert_eq!(gains[&44].kp, Some(20.0));
+    assert_eq!(gains[&34].max_torque, Some(20.0));
+    assert_eq!(gains[&44].kd, Some(5.0));
+    assert_eq!(
+      profile
+        .gains(JointAxis::new(Joint::LeftKnee, Some(Axis::Pitch)))
+        .kp,
+      Some(40.0)
+    );
+  }
+
+  #[test]
+  fn resolve_rejects_unset_fields() {
+    let profile = GainProfile {
+      default: ActuatorGains {
+        kp: Some(20.0),
+        ..Default::default()
+      },
+      joints: BTreeMap::new(),
+    };
+
+    let defaults = ActuatorGains {
+      max_torque: None,
+      ..full()
+    };
+
+    let error = profile.resolve::<KBot>(&defaults, &[34]).unwrap_err();
+    assert!(error.to_string().contains("max_torque"), "{error}");
+  }
+
+  #[test]
+  fn resolve_rejects_joints_without_actuators() {
+    let profile: GainProfile =
+      serde_json::from_str(r#"{ "joints": { "left_elbow_yaw": {} } }"#)
+        .unwrap();
+
+    assert!(profile.resolve::<KBot>(&full(), &[34]).is_err());
+  }
+
+  #[tokio::test]
+  async fn rejected_actuators_roll_back_the_others() {
+    let simulator = Simulator::new::<KBot>(SimConfig::default(), None);
+    let profiles: GainProfiles = serde_json::from_str(
+      r#"{
+        "defaults": {
+          "kd": 5.0, "ki": 0.0, "max_torque": 40.0,
+          "protective_torque": 30.0, "protection_time": 1.0
+        },
+        "profiles": {
+          "soft": { "default": { "kp": 20.0 } },
+          "stiff": { "default": { "kp": 150.0 } }
+        }
+      }"#,
+    )
+    .unwrap();
+    let gains =
+      GainManager::new::<KBot>(simulator.hal(), vec![33, 34, 35], profiles)
+        .unwrap();
+
+    gains.apply("soft").await.unwrap();
+    simulator.set_rejecting(35, true);
+
+    let error = gains.apply("stiff").await.unwrap_err();
+    assert!(format!("{error:#}").contains("rejected"), "{error:#}");
+    assert_eq!(gains.status().await.active.as_deref(), Some("soft"));
+    for actuator_id in [33, 34, 35] {
+      assert_eq!(simulator.kp(actuator_id), Some(20.0));
+    }
+  }
+}
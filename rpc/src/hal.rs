@@ -0,0 +1,277 @@
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{
+  proto::{
+    actuator::{
+      ActuatorCommand, ActuatorStateResponse, CalibrateActuatorRequest,
+      CommandActuatorsRequest, ConfigureActuatorRequest,
+      GetActuatorsStateRequest,
+    },
+    common::{ActionResponse, ActionResult},
+    imu::{EulerAnglesResponse, ImuValuesResponse, QuaternionResponse},
+    led_matrix::WriteBufferRequest,
+    sound::{play_audio_request::Data, AudioConfig, PlayAudioRequest},
+  },
+  Client,
+};
+
+/// Fails naming every actuator whose result reports it rejected a command.
+pub fn check_results(results: &[ActionResult]) -> eyre::Result<()> {
+  let failures = results
+    .iter()
+    .filter(|result| !result.success)
+    .map(|result| match &result.error {
+      Some(error) => format!("{} ({})", result.actuator_id, error.message),
+      None => result.actuator_id.to_string(),
+    })
+    .collect::<Vec<_>>();
+
+  if failures.is_empty() {
+    Ok(())
+  } else {
+    Err(eyre::eyre!(
+      "Actuators rejected the command: {}",
+      failures.join(", ")
+    ))
+  }
+}
+
+/// Fails if an actuator reports it rejected a configuration.
+pub fn check_response(
+  actuator_id: u32,
+  response: &ActionResponse,
+) -> eyre::Result<()> {
+  if response.success {
+    return Ok(());
+  }
+
+  match &response.error {
+    Some(error) => Err(eyre::eyre!(
+      "Actuator {actuator_id} rejected the configuration: {}",
+      error.message
+    )),
+    None => Err(eyre::eyre!(
+      "Actuator {actuator_id} rejected the configuration"
+    )),
+  }
+}
+
+/// Commands and reads the robot's actuators. Positions are in degrees.
+#[async_trait]
+pub trait ActuatorBackend: Debug + Send + Sync {
+  async fn command_actuators(
+    &self,
+    commands: Vec<ActuatorCommand>,
+  ) -> eyre::Result<Vec<ActionResult>>;
+
+  async fn configure_actuator(
+    &self,
+    request: ConfigureActuatorRequest,
+  ) -> eyre::Result<ActionResponse>;
+
+  /// Starts driving an actuator into its hard stop. Returns once the
+  /// calibration has been started, not when it is done.
+  async fn calibrate_actuator(
+    &self,
+    request: CalibrateActuatorRequest,
+  ) -> eyre::Result<()>;
+
+  async fn get_actuators_state(
+    &self,
+    actuator_ids: Vec<u32>,
+  ) -> eyre::Result<Vec<ActuatorStateResponse>>;
+}
+
+#[async_trait]
+pub trait ImuBackend: Debug + Send + Sync {
+  async fn get_values(&self) -> eyre::Result<ImuValuesResponse>;
+
+  async fn get_euler(&self) -> eyre::Result<EulerAnglesResponse>;
+
+  async fn get_quaternion(&self) -> eyre::Result<QuaternionResponse>;
+}
+
+#[async_trait]
+pub trait LedMatrixBackend: Debug + Send + Sync {
+  /// Writes one bit per LED, row by row.
+  async fn write_buffer(&self, buffer: Vec<u8>)
+    -> eyre::Result<ActionResponse>;
+}
+
+#[async_trait]
+pub trait SoundBackend: Debug + Send + Sync {
+  /// Plays raw PCM `audio` in the format described by `config`.
+  async fn play_audio(
+    &self,
+    config: AudioConfig,
+    audio: Vec<u8>,
+  ) -> eyre::Result<ActionResponse>;
+}
+
+/// The hardware a robot is driven through. Everything that talks to
+/// actuators, the IMU, the LED matrix or the speaker goes through here, so
+/// the gRPC client can be swapped for the simulator or a test double.
+#[derive(Debug, Clone)]
+pub struct Hal {
+  pub actuators: Arc<dyn ActuatorBackend>,
+  pub imu: Arc<dyn ImuBackend>,
+  pub led_matrix: Arc<dyn LedMatrixBackend>,
+  pub sound: Arc<dyn SoundBackend>,
+}
+
+impl Hal {
+  /// Talks to KOS over gRPC.
+  pub fn grpc(client: &Client) -> Self {
+    let client = Arc::new(client.clone());
+
+    Self {
+      actuators: client.clone(),
+      imu: client.clone(),
+      led_matrix: client.clone(),
+      sound: client,
+    }
+  }
+}
+
+#[async_trait]
+impl ActuatorBackend for Client {
+  #[tracing::instrument(
+    level = "debug",
+    skip_all,
+    fields(actuator_ids = ?crate::actuator_ids(&commands)),
+  )]
+  async fn command_actuators(
+    &self,
+    commands: Vec<ActuatorCommand>,
+  ) -> eyre::Result<Vec<ActionResult>> {
+    Ok(
+      self
+        .actuator
+        .lock()
+        .await
+        .command_actuators(CommandActuatorsRequest { commands })
+        .await?
+        .into_inner()
+        .results,
+    )
+  }
+
+  #[tracing::instrument(
+    level = "debug",
+    skip_all,
+    fields(actuator_id = request.actuator_id),
+  )]
+  async fn configure_actuator(
+    &self,
+    request: ConfigureActuatorRequest,
+  ) -> eyre::Result<ActionResponse> {
+    Ok(
+      self
+        .actuator
+        .lock()
+        .await
+        .configure_actuator(request)
+        .await?
+        .into_inner(),
+    )
+  }
+
+  #[tracing::instrument(
+    level = "debug",
+    skip_all,
+    fields(actuator_id = request.actuator_id),
+  )]
+  async fn calibrate_actuator(
+    &self,
+    request: CalibrateActuatorRequest,
+  ) -> eyre::Result<()> {
+    self
+      .actuator
+      .lock()
+      .await
+      .calibrate_actuator(request)
+      .await?;
+
+    Ok(())
+  }
+
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn get_actuators_state(
+    &self,
+    actuator_ids: Vec<u32>,
+  ) -> eyre::Result<Vec<ActuatorStateResponse>> {
+    Ok(
+      self
+        .actuator
+        .lock()
+        .await
+        .get_actuators_state(GetActuatorsStateRequest { actuator_ids })
+        .await?
+        .into_inner()
+        .states,
+    )
+  }
+}
+
+#[async_trait]
+impl ImuBackend for Client {
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn get_values(&self) -> eyre::Result<ImuValuesResponse> {
+    Ok(self.imu.lock().await.get_values(()).await?.into_inner())
+  }
+
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn get_euler(&self) -> eyre::Result<EulerAnglesResponse> {
+    Ok(self.imu.lock().await.get_euler(()).await?.into_inner())
+  }
+
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn get_quaternion(&self) -> eyre::Result<QuaternionResponse> {
+    Ok(self.imu.lock().await.get_quaternion(()).await?.into_inner())
+  }
+}
+
+#[async_trait]
+impl LedMatrixBackend for Client {
+  #[tracing::instrument(level = "debug", skip_all)]
+  async fn write_buffer(
+    &self,
+    buffer: Vec<u8>,
+  ) -> eyre::Result<ActionResponse> {
+    Ok(
+      self
+        .led_matrix
+        .lock()
+        .await
+        .write_buffer(WriteBufferRequest { buffer })
+        .await?
+        .into_inner(),
+    )
+  }
+}
+
+#[async_trait]
+impl SoundBackend for Client {
+  #[tracing::instrument(level = "debug", skip_all)]
+  async fn play_audio(
+    &self,
+    config: AudioConfig,
+    audio: Vec<u8>,
+  ) -> eyre::Result<ActionResponse> {
+    // The first message configures the stream, the rest carry the samples.
+    let requests = [Data::Config(config), Data::AudioData(audio)]
+      .map(|data| PlayAudioRequest { data: Some(data) });
+
+    Ok(
+      self
+        .sound
+        .lock()
+        .await
+        .play_audio(tokio_stream::iter(requests))
+        .await?
+        .into_inner(),
+    )
+  }
+}
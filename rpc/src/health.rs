@@ -0,0 +1,699 @@
+use std::{
+  collections::{BTreeMap, HashMap, VecDeque},
+  sync::Arc,
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::Instrument;
+
+use crate::{
+  gains::GainManager,
+  hal::{check_response, Hal},
+  profiler::Profiler,
+  proto::actuator::{ActuatorStateResponse, ConfigureActuatorRequest},
+  ActuatorCommand,
+};
+
+/// How many alerts are kept for reporting.
+const ALERT_HISTORY: usize = 100;
+
+/// Smoothing factor for the temperature and current trend estimates.
+const TREND_ALPHA: f64 = 0.2;
+
+/// What the monitor does to an actuator once it reports a fault.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum FaultAction {
+  /// Only raise an alert.
+  Alert,
+  /// Lower the actuator's torque limit.
+  Derate { max_torque: f64 },
+  /// Turn the actuator's torque off.
+  Disable,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct HealthConfig {
+  pub poll_interval_ms: u64,
+  /// Temperature, in °C, above which a warning is raised.
+  pub temperature_warning: f64,
+  /// Temperature, in °C, above which the actuator is faulted.
+  pub temperature_limit: f64,
+  /// Current, in A, above which the actuator is faulted.
+  pub current_limit: f64,
+  /// Position error, in degrees, that counts towards a stall.
+  pub stall_position_error: f64,
+  /// Torque, in Nm, that counts towards a stall.
+  pub stall_torque: f64,
+  /// How long both stall thresholds have to be exceeded for.
+  pub stall_time_ms: u64,
+  /// Consecutive polls an actuator may miss before it is unresponsive.
+  pub max_missed_polls: u32,
+  pub fault_action: FaultAction,
+}
+
+impl Default for HealthConfig {
+  fn default() -> Self {
+    Self {
+      poll_interval_ms: 200,
+      temperature_warning: 60.0,
+      temperature_limit: 75.0,
+      current_limit: 10.0,
+      stall_position_error: 10.0,
+      stall_torque: 5.0,
+      stall_time_ms: 1000,
+      max_missed_polls: 5,
+      fault_action: FaultAction::Alert,
+    }
+  }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthLevel {
+  Ok,
+  Warning,
+  Fault,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+  Temperature,
+  Current,
+  Stall,
+  Unresponsive,
+  Fault,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Alert {
+  pub actuator_id: u32,
+  pub kind: AlertKind,
+  pub level: HealthLevel,
+  pub message: String,
+  /// Seconds since the Unix epoch.
+  pub at: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ActuatorHealth {
+  pub actuator_id: u32,
+  pub online: bool,
+  pub level: Option<HealthLevel>,
+  pub temperature: Option<f64>,
+  /// Smoothed rate of change, in °C/s.
+  pub temperature_trend: f64,
+  pub current: Option<f32>,
+  /// Smoothed rate of change, in A/s.
+  pub current_trend: f64,
+  pub torque: Option<f64>,
+  pub position_error: Option<f64>,
+  pub faults: Vec<String>,
+  pub missed_polls: u32,
+  pub derated: bool,
+  pub disabled: bool,
+  #[serde(skip)]
+  last_sample: Option<Instant>,
+  #[serde(skip)]
+  stalled_since: Option<Instant>,
+  #[serde(skip)]
+  active_alerts: Vec<AlertKind>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HealthReport {
+  pub actuators: Vec<ActuatorHealth>,
+  pub alerts: Vec<Alert>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+  actuators: BTreeMap<u32, ActuatorHealth>,
+  alerts: VecDeque<Alert>,
+  targets: HashMap<u32, f64>,
+}
+
+/// Background watcher for actuator temperature, current, stalls and faults.
+#[derive(Debug)]
+pub struct HealthMonitor {
+  hal: Hal,
+  config: HealthConfig,
+  actuator_ids: Vec<u32>,
+  /// Where the torque limit of a derated actuator is restored from.
+  gains: Arc<GainManager>,
+  profiler: Arc<Profiler>,
+  state: Mutex<HealthState>,
+}
+
+impl HealthMonitor {
+  pub fn new(
+    hal: Hal,
+    config: HealthConfig,
+    actuator_ids: Vec<u32>,
+    gains: Arc<GainManager>,
+    profiler: Arc<Profiler>,
+  ) -> Self {
+    Self {
+      hal,
+      config,
+      actuator_ids,
+      gains,
+      profiler,
+      state: Mutex::new(HealthState::default()),
+    }
+  }
+
+  /// Starts polling the actuators in the background.
+  pub fn spawn(self: &Arc<Self>) {
+    let monitor = self.clone();
+
+    tokio::spawn(
+      async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(
+          monitor.config.poll_interval_ms,
+        ));
+
+        loop {
+          interval.tick().await;
+
+          let mut tick = monitor.profiler.tick("health");
+          if let Err(e) = tick.time("poll", monitor.poll()).await {
+            tracing::warn!(error = %e, "Health poll failed");
+          }
+        }
+      }
+      .instrument(tracing::info_span!("health_monitor")),
+    );
+  }
+
+  /// Remembers commanded positions so stalls can be detected.
+  pub async fn record_commands(&self, commands: &[ActuatorCommand]) {
+    let mut state = self.state.lock().await;
+
+    for command in commands {
+      if let Some(position) = command.position {
+        state.targets.insert(command.actuator_id, position);
+      }
+    }
+  }
+
+  pub async fn report(&self) -> HealthReport {
+    let state = self.state.lock().await;
+
+    HealthReport {
+      actuators: state.actuators.values().cloned().collect(),
+      alerts: state.alerts.iter().cloned().collect(),
+    }
+  }
+
+  /// Clears the alerts of an actuator and undoes any automatic action taken.
+  /// The torque limit of a derated actuator goes back to the one of the
+  /// active gain profile.
+  pub async fn reset(&self, actuator_id: u32) -> eyre::Result<()> {
+    let (derated, disabled) = {
+      let state = self.state.lock().await;
+      let Some(health) = state.actuators.get(&actuator_id) else {
+        return Err(eyre::eyre!("Unknown actuator {actuator_id}"));
+      };
+
+      (health.derated, health.disabled)
+    };
+
+    if derated {
+      let max_torque = self
+        .gains
+        .active_gains(actuator_id)
+        .await
+        .and_then(|gains| gains.max_torque)
+        .ok_or_else(|| {
+          eyre::eyre!(
+            "No gain profile is active to restore the torque limit of \
+             actuator {actuator_id} from"
+          )
+        })?;
+
+      self
+        .configure(ConfigureActuatorRequest {
+          actuator_id,
+          max_torque: Some(max_torque),
+          ..Default::default()
+        })
+        .await?;
+    }
+
+    if disabled {
+      self
+        .configure(ConfigureActuatorRequest {
+          actuator_id,
+          torque_enabled: Some(true),
+          ..Default::default()
+        })
+        .await?;
+    }
+
+    let mut state = self.state.lock().await;
+    let Some(health) = state.actuators.get_mut(&actuator_id) else {
+      return Err(eyre::eyre!("Unknown actuator {actuator_id}"));
+    };
+
+    health.derated = false;
+    health.disabled = false;
+    health.stalled_since = None;
+    health.active_alerts.clear();
+    health.level = Some(HealthLevel::Ok);
+    state
+      .alerts
+      .retain(|alert| alert.actuator_id != actuator_id);
+
+    Ok(())
+  }
+
+  async fn poll(&self) -> eyre::Result<()> {
+    let states = self
+      .hal
+      .actuators
+      .get_actuators_state(self.actuator_ids.clone())
+      .await?
+      .into_iter()
+      .map(|state| (state.actuator_id, state))
+      .collect::<HashMap<_, _>>();
+
+    let mut state = self.state.lock().await;
+
+    for &actuator_id in &self.actuator_ids {
+      let target = state.targets.get(&actuator_id).copied();
+      let health =
+        state
+          .actuators
+          .entry(actuator_id)
+          .or_insert_with(|| ActuatorHealth {
+            actuator_id,
+            ..Default::default()
+          });
+
+      let raised = match states.get(&actuator_id) {
+        Some(sample) if sample.online => self.update(health, sample, target),
+        _ => self.missed(health),
+      };
+
+      for (kind, level, message) in raised {
+        state.alerts.push_back(Alert {
+          actuator_id,
+          kind,
+          level,
+          message,
+          at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        });
+        if state.alerts.len() > ALERT_HISTORY {
+          state.alerts.pop_front();
+        }
+      }
+    }
+
+    // Every faulted actuator not acted on yet, so an action that failed is
+    // retried on the next poll while its alert stays active.
+    let actions = state
+      .actuators
+      .values()
+      .filter(|health| health.level == Some(HealthLevel::Fault))
+      .filter(|health| match self.config.fault_action {
+        FaultAction::Alert => false,
+        FaultAction::Derate { .. } => !health.derated,
+        FaultAction::Disable => !health.disabled,
+      })
+      .map(|health| health.actuator_id)
+      .collect::<Vec<_>>();
+
+    // Configuring takes a round trip per actuator, which reports and resets
+    // should not wait for.
+    drop(state);
+
+    for actuator_id in actions {
+      let request = match self.config.fault_action {
+        FaultAction::Alert => continue,
+        FaultAction::Derate { max_torque } => ConfigureActuatorRequest {
+          actuator_id,
+          max_torque: Some(max_torque),
+          ..Default::default()
+        },
+        FaultAction::Disable => ConfigureActuatorRequest {
+          actuator_id,
+          torque_enabled: Some(false),
+          ..Default::default()
+        },
+      };
+
+      if let Err(e) = self.configure(request).await {
+        tracing::error!(
+          actuator_id,
+          error = %e,
+          "Failed to act on actuator fault"
+        );
+        continue;
+      }
+
+      let mut state = self.state.lock().await;
+      if let Some(health) = state.actuators.get_mut(&actuator_id) {
+        match self.config.fault_action {
+          FaultAction::Alert => {}
+          FaultAction::Derate { .. } => health.derated = true,
+          FaultAction::Disable => health.disabled = true,
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Folds a new sample into `health`, returning newly raised alerts.
+  fn update(
+    &self,
+    health: &mut ActuatorHealth,
+    sample: &ActuatorStateResponse,
+    target: Option<f64>,
+  ) -> Vec<(AlertKind, HealthLevel, String)> {
+    let now = Instant::now();
+
+    if let Some(dt) = health.last_sample.map(|last| (now - last).as_secs_f64())
+    {
+      if dt > 0.0 {
+        if let (Some(previous), Some(current)) =
+          (health.temperature, sample.temperature)
+        {
+          health.temperature_trend += TREND_ALPHA
+            * ((current - previous) / dt - health.temperature_trend);
+        }
+        if let (Some(previous), Some(current)) =
+          (health.current, sample.current)
+        {
+          health.current_trend += TREND_ALPHA
+            * ((current - previous) as f64 / dt - health.current_trend);
+        }
+      }
+    }
+
+    health.online = true;
+    health.missed_polls = 0;
+    health.last_sample = Some(now);
+    health.temperature = sample.temperature;
+    health.current = sample.current;
+    health.torque = sample.torque;
+    health.faults = sample.faults.clone();
+    health.position_error = target
+      .zip(sample.position)
+      .map(|(target, pos)| target - pos);
+
+    let mut conditions = Vec::new();
+
+    if let Some(temperature) = sample.temperature {
+      if temperature >= self.config.temperature_limit {
+        conditions.push((
+          AlertKind::Temperature,
+          HealthLevel::Fault,
+          format!("Temperature {temperature:.1}°C over limit"),
+        ));
+      } else if temperature >= self.config.temperature_warning {
+        conditions.push((
+          AlertKind::Temperature,
+          HealthLevel::Warning,
+          format!(
+            "Temperature {temperature:.1}°C rising at {:.2}°C/s",
+            health.temperature_trend
+          ),
+        ));
+      }
+    }
+
+    if let Some(current) = sample.current {
+      if current as f64 >= self.config.current_limit {
+        conditions.push((
+          AlertKind::Current,
+          HealthLevel::Fault,
+          format!("Current {current:.2}A over limit"),
+        ));
+      }
+    }
+
+    let straining = health
+      .position_error
+      .is_some_and(|error| error.abs() >= self.config.stall_position_error)
+      && sample
+        .torque
+        .is_some_and(|torque| torque.abs() >= self.config.stall_torque);
+
+    if straining {
+      let since = *health.stalled_since.get_or_insert(now);
+      if now - since >= Duration::from_millis(self.config.stall_time_ms) {
+        conditions.push((
+          AlertKind::Stall,
+          HealthLevel::Fault,
+          format!(
+            "Stalled {:.1}° from target at {:.2}Nm",
+            health.position_error.unwrap_or_default(),
+            sample.torque.unwrap_or_default()
+          ),
+        ));
+      }
+    } else {
+      health.stalled_since = None;
+    }
+
+    if !sample.faults.is_empty() {
+      conditions.push((
+        AlertKind::Fault,
+        HealthLevel::Fault,
+        format!("Actuator reported {}", sample.faults.join(", ")),
+      ));
+    }
+
+    Self::settle(health, conditions)
+  }
+
+  fn missed(
+    &self,
+    health: &mut ActuatorHealth,
+  ) -> Vec<(AlertKind, HealthLevel, String)> {
+    health.online = false;
+    health.missed_polls += 1;
+
+    let mut conditions = Vec::new();
+    if health.missed_polls >= self.config.max_missed_polls {
+      conditions.push((
+        AlertKind::Unresponsive,
+        HealthLevel::Fault,
+        format!("No state for {} polls", health.missed_polls),
+      ));
+    }
+
+    Self::settle(health, conditions)
+  }
+
+  /// Updates the health level from the current conditions and returns only
+  /// those that were not already active, so each one alerts once.
+  fn settle(
+    health: &mut ActuatorHealth,
+    conditions: Vec<(AlertKind, HealthLevel, String)>,
+  ) -> Vec<(AlertKind, HealthLevel, String)> {
+    health.level = Some(
+      conditions
+        .iter()
+        .map(|(_, level, _)| *level)
+        .max()
+        .unwrap_or(HealthLevel::Ok),
+    );
+
+    let previous = std::mem::replace(
+      &mut health.active_alerts,
+      conditions.iter().map(|(kind, _, _)| *kind).collect(),
+    );
+
+    conditions
+      .into_iter()
+      .filter(|(kind, _, _)| !previous.contains(kind))
+      .collect()
+  }
+
+  async fn configure(
+    &self,
+    request: ConfigureActuatorRequest,
+  ) -> eyre::Result<()> {
+    let actuator_id = request.actuator_id;
+    let response = self.hal.actuators.configure_actuator(request).await?;
+
+    check_response(actuator_id, &response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    gains::GainProfiles,
+    metrics::Metrics,
+    profiler::ProfilerConfig,
+    sim::{SimConfig, Simulator},
+    KBot,
+  };
+
+  fn monitor(config: HealthConfig) -> (HealthMonitor, Simulator) {
+    let simulator = Simulator::new::<KBot>(SimConfig::default(), None);
+    let hal = simulator.hal();
+    let gains = GainManager::new::<KBot>(
+      hal.clone(),
+      Vec::new(),
+      GainProfiles::default(),
+    )
+    .unwrap();
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let profiler = Arc::new(Profiler::new(ProfilerConfig::default(), metrics));
+
+    let monitor =
+      HealthMonitor::new(hal, config, vec![34, 35], Arc::new(gains), profiler);
+
+    (monitor, simulator)
+  }
+
+  fn monitor_with(fault_action: FaultAction) -> (HealthMonitor, Simulator) {
+    monitor(HealthConfig {
+      fault_action,
+      ..Default::default()
+    })
+  }
+
+  async fn health(monitor: &HealthMonitor, actuator_id: u32) -> ActuatorHealth {
+    monitor
+      .report()
+      .await
+      .actuators
+      .into_iter()
+      .find(|health| health.actuator_id == actuator_id)
+      .unwrap()
+  }
+
+  async fn alerts(monitor: &HealthMonitor, kind: AlertKind) -> usize {
+    let alerts = monitor.report().await.alerts;
+
+    alerts.iter().filter(|alert| alert.kind == kind).count()
+  }
+
+  async fn enable(monitor: &HealthMonitor, actuator_id: u32) {
+    monitor
+      .configure(ConfigureActuatorRequest {
+        actuator_id,
+        torque_enabled: Some(true),
+        kp: Some(100.0),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn alerts_are_raised_once_and_clear() {
+    let (monitor, simulator) = monitor(HealthConfig::default());
+
+    simulator.set_temperature(34, 65.0);
+    monitor.poll().await.unwrap();
+    monitor.poll().await.unwrap();
+    assert_eq!(health(&monitor, 34).await.level, Some(HealthLevel::Warning));
+    assert_eq!(alerts(&monitor, AlertKind::Temperature).await, 1);
+
+    simulator.set_temperature(34, 30.0);
+    monitor.poll().await.unwrap();
+    assert_eq!(health(&monitor, 34).await.level, Some(HealthLevel::Ok));
+
+    simulator.set_temperature(34, 65.0);
+    monitor.poll().await.unwrap();
+    assert_eq!(alerts(&monitor, AlertKind::Temperature).await, 2);
+  }
+
+  #[tokio::test]
+  async fn over_temperature_derates_or_disables() {
+    let (monitor, simulator) =
+      monitor_with(FaultAction::Derate { max_torque: 10.0 });
+
+    // Warnings only alert.
+    simulator.set_temperature(34, 65.0);
+    monitor.poll().await.unwrap();
+    assert!(!health(&monitor, 34).await.derated);
+
+    simulator.set_temperature(34, 80.0);
+    monitor.poll().await.unwrap();
+    assert_eq!(health(&monitor, 34).await.level, Some(HealthLevel::Fault));
+    assert!(health(&monitor, 34).await.derated);
+    assert_eq!(simulator.max_torque(34), Some(10.0));
+    assert_eq!(simulator.max_torque(35), None);
+
+    let (monitor, simulator) = monitor_with(FaultAction::Disable);
+    enable(&monitor, 34).await;
+
+    simulator.set_temperature(34, 80.0);
+    monitor.poll().await.unwrap();
+    assert!(health(&monitor, 34).await.disabled);
+    assert_eq!(simulator.torque_enabled(34), Some(false));
+  }
+
+  #[tokio::test]
+  async fn rejected_fault_actions_keep_the_alert_and_are_retried() {
+    let (monitor, simulator) = monitor_with(FaultAction::Disable);
+    enable(&monitor, 34).await;
+
+    simulator.set_rejecting(34, true);
+    simulator.set_temperature(34, 80.0);
+    monitor.poll().await.unwrap();
+
+    let health_34 = health(&monitor, 34).await;
+    assert!(!health_34.disabled);
+    assert_eq!(health_34.level, Some(HealthLevel::Fault));
+    assert_eq!(simulator.torque_enabled(34), Some(true));
+
+    simulator.set_rejecting(34, false);
+    monitor.poll().await.unwrap();
+    assert!(health(&monitor, 34).await.disabled);
+    assert_eq!(simulator.torque_enabled(34), Some(false));
+    assert_eq!(alerts(&monitor, AlertKind::Temperature).await, 1);
+  }
+
+  #[tokio::test]
+  async fn stalled_and_unresponsive_actuators_fault() {
+    let (monitor, simulator) = monitor(HealthConfig {
+      stall_time_ms: 0,
+      max_missed_polls: 2,
+      ..Default::default()
+    });
+    simulator.spawn();
+    enable(&monitor, 35).await;
+
+    // The ankle stops at its 60° limit, short of the target.
+    let commands = vec![ActuatorCommand {
+      actuator_id: 35,
+      position: Some(80.0),
+      velocity: None,
+      torque: None,
+    }];
+    monitor.record_commands(&commands).await;
+    monitor
+      .hal
+      .actuators
+      .command_actuators(commands)
+      .await
+      .unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    monitor.poll().await.unwrap();
+    assert_eq!(alerts(&monitor, AlertKind::Stall).await, 1);
+
+    simulator.set_online(34, false);
+    monitor.poll().await.unwrap();
+    assert_eq!(alerts(&monitor, AlertKind::Unresponsive).await, 0);
+    monitor.poll().await.unwrap();
+    assert_eq!(alerts(&monitor, AlertKind::Unresponsive).await, 1);
+    assert!(!health(&monitor, 34).await.online);
+  }
+}
@@ -0,0 +1,328 @@
+use nalgebra::{DMatrix, DVector, Isometry3, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  arbiter::Lease,
+  kinematics::{Chain, KinematicJoint, Limb},
+  pose::Pose,
+  KBot, Robot,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct IkConfig {
+  pub max_iterations: usize,
+  /// Largest acceptable distance, in meters, from the target position.
+  pub position_tolerance: f64,
+  /// Largest acceptable angle, in degrees, from the target orientation.
+  pub orientation_tolerance: f64,
+  /// Damping of the least-squares step. Higher values are slower to converge
+  /// but better behaved near singularities.
+  pub damping: f64,
+  /// Meters of position error one radian of orientation error is worth.
+  pub orientation_weight: f64,
+}
+
+impl Default for IkConfig {
+  fn default() -> Self {
+    Self {
+      max_iterations: 200,
+      position_tolerance: 0.005,
+      orientation_tolerance: 2.0,
+      damping: 0.05,
+      orientation_weight: 0.1,
+    }
+  }
+}
+
+/// Where to put a hand or foot, in the torso frame.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct IkTarget {
+  /// Position in meters.
+  pub position: [f64; 3],
+  /// Roll, pitch and yaw in degrees. The 3-DOF arms can only reach a few
+  /// orientations at any given point, so this is mostly useful for the legs.
+  #[serde(default)]
+  pub rpy: Option<[f64; 3]>,
+}
+
+impl IkTarget {
+  fn rotation(&self) -> Option<UnitQuaternion<f64>> {
+    let [roll, pitch, yaw] = self.rpy?;
+
+    Some(UnitQuaternion::from_euler_angles(
+      roll.to_radians(),
+      pitch.to_radians(),
+      yaw.to_radians(),
+    ))
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IkSolution {
+  /// Positions, in degrees, of the joints in the chain.
+  pub pose: Pose,
+  /// Distance in meters from the target position.
+  pub position_error: f64,
+  /// Angle in degrees from the target orientation, if one was given.
+  pub orientation_error: Option<f64>,
+  pub iterations: usize,
+  pub converged: bool,
+}
+
+impl Chain {
+  /// Finds joint positions that put the end effector at `target`, starting
+  /// from `seed` and keeping every joint within its limits.
+  ///
+  /// Returns the closest solution found even if it did not converge.
+  pub fn inverse<R: Robot>(
+    &self,
+    target: &IkTarget,
+    seed: &Pose,
+    config: &IkConfig,
+  ) -> IkSolution {
+    let position = Vector3::from(target.position);
+    let rotation = target.rotation();
+
+    let mut pose = self
+      .joints
+      .iter()
+      .map(|joint| {
+        let angle = seed
+          .get(joint.joint.joint, joint.joint.axis)
+          .unwrap_or_default();
+        (joint.joint, clamp::<R>(joint, angle))
+      })
+      .collect::<Pose>();
+
+    let mut iterations = 0;
+    loop {
+      let frames = self.frames(&pose);
+      let tip = frames.last().copied().unwrap_or_else(Isometry3::identity);
+      let (position_error, orientation_error) =
+        errors(&tip, &position, rotation.as_ref());
+
+      let converged = position_error.norm() <= config.position_tolerance
+        && orientation_error.is_none_or(|error| {
+          error.norm().to_degrees() <= config.orientation_tolerance
+        });
+
+      if converged || iterations >= config.max_iterations {
+        return IkSolution {
+          pose,
+          position_error: position_error.norm(),
+          orientation_error: orientation_error
+            .map(|error| error.norm().to_degrees()),
+          iterations,
+          converged,
+        };
+      }
+
+      let rows = if rotation.is_some() { 6 } else { 3 };
+      let mut error = DVector::zeros(rows);
+      error.fixed_rows_mut::<3>(0).copy_from(&position_error);
+      if let Some(orientation_error) = orientation_error {
+        error
+          .fixed_rows_mut::<3>(3)
+          .copy_from(&(orientation_error * config.orientation_weight));
+      }
+
+      let mut jacobian = DMatrix::zeros(rows, self.joints.len());
+      for (i, (joint, frame)) in self.joints.iter().zip(&frames).enumerate() {
+        let axis = frame.rotation * Vector3::from(joint.axis).normalize();
+        let lever = tip.translation.vector - frame.translation.vector;
+
+        jacobian
+          .fixed_view_mut::<3, 1>(0, i)
+          .copy_from(&axis.cross(&lever));
+        if rows == 6 {
+          jacobian
+            .fixed_view_mut::<3, 1>(3, i)
+            .copy_from(&(axis * config.orientation_weight));
+        }
+      }
+
+      // Damped least squares: dq = Jᵀ (J Jᵀ + λ² I)⁻¹ e
+      let damped = &jacobian * jacobian.transpose()
+        + DMatrix::identity(rows, rows) * config.damping.powi(2);
+      let step = jacobian.transpose()
+        * damped
+          .lu()
+          .solve(&error)
+          .unwrap_or_else(|| DVector::zeros(rows));
+
+      for (joint, delta) in self.joints.iter().zip(step.iter()) {
+        let angle = pose
+          .get(joint.joint.joint, joint.joint.axis)
+          .unwrap_or_default()
+          + delta.to_degrees();
+        pose.set(
+          joint.joint.joint,
+          joint.joint.axis,
+          clamp::<R>(joint, angle),
+        );
+      }
+
+      iterations += 1;
+    }
+  }
+}
+
+fn clamp<R: Robot>(joint: &KinematicJoint, angle: f64) -> f64 {
+  let limits = joint
+    .limits
+    .map(|[min, max]| (min, max))
+    .or_else(|| R::joint_limits(joint.joint.joint, joint.joint.axis));
+
+  match limits {
+    Some((min, max)) => angle.clamp(min, max),
+    None => angle,
+  }
+}
+
+/// Position error in meters and, if a rotation is given, orientation error as
+/// a scaled axis in radians.
+fn errors(
+  tip: &Isometry3<f64>,
+  position: &Vector3<f64>,
+  rotation: Option<&UnitQuaternion<f64>>,
+) -> (Vector3<f64>, Option<Vector3<f64>>) {
+  let position_error = position - tip.translation.vector;
+  let orientation_error =
+    rotation.map(|rotation| (rotation * tip.rotation.inverse()).scaled_axis());
+
+  (position_error, orientation_error)
+}
+
+impl KBot {
+  /// Solves for `limb` reaching `target`, starting from its current joint
+  /// positions.
+  pub async fn solve_ik(
+    &self,
+    limb: Limb,
+    target: &IkTarget,
+  ) -> eyre::Result<IkSolution> {
+    let Some(chain) = self.kinematic_model()?.chain(limb) else {
+      return Err(eyre::eyre!("No kinematic chain for {limb:?}"));
+    };
+
+    let seed = self.read_pose(limb.group()).await?;
+
+    Ok(chain.inverse::<Self>(target, &seed, &self.config.ik))
+  }
+
+  /// Moves `limb` so its end effector is at `target`. Fails without moving if
+  /// the target is out of reach.
+  pub async fn reach(
+    &self,
+    lease: &Lease,
+    limb: Limb,
+    target: &IkTarget,
+  ) -> eyre::Result<IkSolution> {
+    let solution = self.solve_ik(limb, target).await?;
+
+    if !solution.converged {
+      return Err(eyre::eyre!(
+        "{limb:?} cannot reach {:?}, closest is {:.3} m away",
+        target.position,
+        solution.position_error
+      ));
+    }
+
+    self.move_to(lease, solution.pose.clone()).await?;
+
+    Ok(solution)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{kinematics::Frame, pose::JointAxis, Axis, Joint};
+
+  fn joint(joint: Joint, z: f64, limits: Option<[f64; 2]>) -> KinematicJoint {
+    KinematicJoint {
+      joint: JointAxis::new(joint, Some(Axis::Pitch)),
+      origin: Frame::translation(0.0, 0.0, z),
+      axis: [0.0, 1.0, 0.0],
+      limits,
+    }
+  }
+
+  /// Two 0.3 m links pitching about Y, hanging down from the origin.
+  fn leg(knee_limits: Option<[f64; 2]>) -> Chain {
+    Chain {
+      limb: Limb::LeftLeg,
+      joints: vec![
+        joint(Joint::LeftHip, 0.0, Some([-90.0, 90.0])),
+        joint(Joint::LeftKnee, -0.3, knee_limits),
+      ],
+      tip: Frame::translation(0.0, 0.0, -0.3),
+    }
+  }
+
+  fn target(position: [f64; 3]) -> IkTarget {
+    IkTarget {
+      position,
+      rpy: None,
+    }
+  }
+
+  #[test]
+  fn converges_on_reachable_targets() {
+    let chain = leg(None);
+    let goal = Pose::new()
+      .with(Joint::LeftHip, Some(Axis::Pitch), 30.0)
+      .with(Joint::LeftKnee, Some(Axis::Pitch), -45.0);
+    let position = chain.forward(&goal).translation.vector;
+
+    let solution = chain.inverse::<KBot>(
+      &target(position.into()),
+      &Pose::new(),
+      &IkConfig::default(),
+    );
+
+    assert!(solution.converged, "{solution:?}");
+    let reached = chain.forward(&solution.pose).translation.vector;
+    assert!((reached - position).norm() <= 0.005);
+  }
+
+  #[test]
+  fn reports_unreachable_targets() {
+    let solution = leg(None).inverse::<KBot>(
+      &target([0.0, 0.0, -1.0]),
+      &Pose::new(),
+      &IkConfig::default(),
+    );
+
+    assert!(!solution.converged);
+    assert!((solution.position_error - 0.4).abs() < 0.01, "{solution:?}");
+  }
+
+  #[test]
+  fn keeps_joints_within_their_limits() {
+    let chain = leg(Some([-10.0, 10.0]));
+    let goal = Pose::new().with(Joint::LeftKnee, Some(Axis::Pitch), -90.0);
+    let position = chain.forward(&goal).translation.vector;
+
+    let solution = chain.inverse::<KBot>(
+      &target(position.into()),
+      &Pose::new(),
+      &IkConfig::default(),
+    );
+
+    let knee = solution
+      .pose
+      .get(Joint::LeftKnee, Some(Axis::Pitch))
+      .unwrap();
+    assert!((-10.0..=10.0).contains(&knee), "{knee}");
+    assert!(!solution.converged);
+  }
+
+  #[test]
+  fn clamps_to_robot_limits_without_chain_limits() {
+    let ankle = joint(Joint::LeftAnkle, 0.0, None);
+
+    assert_eq!(clamp::<KBot>(&ankle, 90.0), 60.0);
+    assert_eq!(clamp::<KBot>(&ankle, -20.0), -20.0);
+  }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+  proto::{
+    common,
+    inference::{
+      get_models_info_request::Filter, ForwardRequest, GetModelsInfoRequest,
+      ModelInfo, ModelMetadata, ModelUids, Tensor, UploadModelRequest,
+    },
+  },
+  Client,
+};
+
+/// Serializable view of a model known to the robot's inference service.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelSummary {
+  pub uid: String,
+  pub name: Option<String>,
+  pub description: Option<String>,
+  pub version: Option<String>,
+  pub author: Option<String>,
+}
+
+impl From<ModelInfo> for ModelSummary {
+  fn from(info: ModelInfo) -> Self {
+    let metadata = info.metadata.unwrap_or_default();
+
+    Self {
+      uid: info.uid,
+      name: metadata.model_name,
+      description: metadata.model_description,
+      version: metadata.model_version,
+      author: metadata.model_author,
+    }
+  }
+}
+
+/// Converts the error field carried by KOS responses into an `eyre` error.
+pub(crate) fn check(error: Option<common::Error>) -> eyre::Result<()> {
+  match error {
+    Some(error) if !error.message.is_empty() || error.code != 0 => {
+      Err(eyre::eyre!("KOS error {}: {}", error.code, error.message))
+    }
+    _ => Ok(()),
+  }
+}
+
+impl Client {
+  /// Uploads a compiled model to the robot and returns its UID.
+  #[tracing::instrument(level = "debug", skip_all, fields(bytes = model.len()))]
+  pub async fn upload_model(
+    &self,
+    model: Vec<u8>,
+    metadata: Option<ModelMetadata>,
+  ) -> eyre::Result<String> {
+    let response = self
+      .measure("inference", "upload_model", async {
+        Ok(
+          self
+            .inference
+            .lock()
+            .await
+            .upload_model(UploadModelRequest { model, metadata })
+            .await?
+            .into_inner(),
+        )
+      })
+      .await?;
+
+    check(response.error)?;
+
+    Ok(response.model_uid)
+  }
+
+  #[tracing::instrument(level = "debug", skip(self))]
+  pub async fn list_models(&self) -> eyre::Result<Vec<ModelSummary>> {
+    let response = self
+      .measure("inference", "get_models_info", async {
+        Ok(
+          self
+            .inference
+            .lock()
+            .await
+            .get_models_info(GetModelsInfoRequest {
+              filter: Some(Filter::All(true)),
+            })
+            .await?
+            .into_inner(),
+        )
+      })
+      .await?;
+
+    check(response.error)?;
+
+    Ok(
+      response
+        .models
+        .into_iter()
+        .map(ModelSummary::from)
+        .collect(),
+    )
+  }
+
+  #[tracing::instrument(level = "debug", skip(self))]
+  pub async fn load_models(
+    &self,
+    uids: Vec<String>,
+  ) -> eyre::Result<Vec<ModelSummary>> {
+    let response = self
+      .measure("inference", "load_models", async {
+        Ok(
+          self
+            .inference
+            .lock()
+            .await
+            .load_models(ModelUids { uids })
+            .await?
+            .into_inner(),
+        )
+      })
+      .await?;
+
+    if let Some(result) = response.result {
+      check(result.error)?;
+    }
+
+    Ok(
+      response
+        .models
+        .into_iter()
+        .map(ModelSummary::from)
+        .collect(),
+    )
+  }
+
+  #[tracing::instrument(level = "debug", skip(self))]
+  pub async fn unload_models(&self, uids: Vec<String>) -> eyre::Result<()> {
+    let response = self
+      .measure("inference", "unload_models", async {
+        Ok(
+          self
+            .inference
+            .lock()
+            .await
+            .unload_models(ModelUids { uids })
+            .await?
+            .into_inner(),
+        )
+      })
+      .await?;
+
+    check(response.error)
+  }
+
+  /// Runs a loaded model once, returning its output tensors by name.
+  #[tracing::instrument(level = "debug", skip_all, fields(model_uid))]
+  pub async fn forward(
+    &self,
+    model_uid: impl Into<String>,
+    inputs: HashMap<String, Tensor>,
+  ) -> eyre::Result<HashMap<String, Tensor>> {
+    let model_uid = model_uid.into();
+    tracing::Span::current().record("model_uid", model_uid.as_str());
+
+    let response = self
+      .measure("inference", "forward", async {
+        Ok(
+          self
+            .inference
+            .lock()
+            .await
+            .forward(ForwardRequest { model_uid, inputs })
+            .await?
+            .into_inner(),
+        )
+      })
+      .await?;
+
+    check(response.error)?;
+
+    Ok(response.outputs)
+  }
+}
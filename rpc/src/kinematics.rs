@@ -0,0 +1,175 @@
+use nalgebra::{Isometry3, Translation3, Unit, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::pose::{JointAxis, JointGroup, Pose};
+
+/// A limb whose end effector is tracked by the kinematic model.
+#[derive(
+  Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Limb {
+  LeftArm,
+  RightArm,
+  LeftLeg,
+  RightLeg,
+}
+
+impl Limb {
+  pub const ALL: [Limb; 4] =
+    [Limb::LeftArm, Limb::RightArm, Limb::LeftLeg, Limb::RightLeg];
+
+  pub fn group(self) -> JointGroup {
+    match self {
+      Limb::LeftArm => JointGroup::LeftArm,
+      Limb::RightArm => JointGroup::RightArm,
+      Limb::LeftLeg => JointGroup::LeftLeg,
+      Limb::RightLeg => JointGroup::RightLeg,
+    }
+  }
+}
+
+/// A rigid transform in URDF terms: a translation in meters followed by
+/// fixed-axis roll, pitch and yaw in radians.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Frame {
+  pub xyz: [f64; 3],
+  #[serde(default)]
+  pub rpy: [f64; 3],
+}
+
+impl Frame {
+  pub fn translation(x: f64, y: f64, z: f64) -> Self {
+    Self {
+      xyz: [x, y, z],
+      rpy: [0.0; 3],
+    }
+  }
+
+  pub fn from_isometry(isometry: &Isometry3<f64>) -> Self {
+    let translation = isometry.translation.vector;
+    let (roll, pitch, yaw) = isometry.rotation.euler_angles();
+
+    Self {
+      xyz: [translation.x, translation.y, translation.z],
+      rpy: [roll, pitch, yaw],
+    }
+  }
+
+  pub fn isometry(&self) -> Isometry3<f64> {
+    let [x, y, z] = self.xyz;
+    let [roll, pitch, yaw] = self.rpy;
+
+    Isometry3::from_parts(
+      Translation3::new(x, y, z),
+      UnitQuaternion::from_euler_angles(roll, pitch, yaw),
+    )
+  }
+}
+
+/// A revolute joint in a limb.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct KinematicJoint {
+  pub joint: JointAxis,
+  /// Placement of the joint relative to the previous joint, or to the torso
+  /// for the first joint of a chain.
+  pub origin: Frame,
+  /// Rotation axis in the joint's own frame, for positive positions.
+  pub axis: [f64; 3],
+  /// Position limits in degrees, overriding [`crate::Robot::joint_limits`].
+  #[serde(default)]
+  pub limits: Option<[f64; 2]>,
+}
+
+/// The joints from the torso out to one end effector.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Chain {
+  pub limb: Limb,
+  pub joints: Vec<KinematicJoint>,
+  /// Placement of the hand or foot relative to the last joint.
+  pub tip: Frame,
+}
+
+impl Chain {
+  /// Pose of the end effector in the torso frame. Joints missing from
+  /// `pose` are taken to be at zero.
+  pub fn forward(&self, pose: &Pose) -> Isometry3<f64> {
+    self
+      .frames(pose)
+      .last()
+      .copied()
+      .unwrap_or_else(Isometry3::identity)
+  }
+
+  /// The frame of every joint in the torso frame, after it has rotated,
+  /// followed by the end effector.
+  pub fn frames(&self, pose: &Pose) -> Vec<Isometry3<f64>> {
+    let mut frame = Isometry3::identity();
+    let mut frames = Vec::with_capacity(self.joints.len() + 1);
+
+    for joint in &self.joints {
+      let angle = pose
+        .get(joint.joint.joint, joint.joint.axis)
+        .unwrap_or_default()
+        .to_radians();
+      let axis = Unit::new_normalize(Vector3::from(joint.axis));
+
+      frame = frame
+        * joint.origin.isometry()
+        * UnitQuaternion::from_axis_angle(&axis, angle);
+      frames.push(frame);
+    }
+
+    frames.push(frame * self.tip.isometry());
+    frames
+  }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EndEffector {
+  pub limb: Limb,
+  /// Position in meters in the torso frame.
+  pub position: [f64; 3],
+  /// Roll, pitch and yaw in degrees in the torso frame.
+  pub rpy: [f64; 3],
+}
+
+impl EndEffector {
+  fn new(limb: Limb, isometry: &Isometry3<f64>) -> Self {
+    let (roll, pitch, yaw) = isometry.rotation.euler_angles();
+    let position = isometry.translation.vector;
+
+    Self {
+      limb,
+      position: [position.x, position.y, position.z],
+      rpy: [roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees()],
+    }
+  }
+}
+
+/// Link lengths and joint axes for every tracked limb.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct KinematicModel {
+  pub chains: Vec<Chain>,
+}
+
+impl KinematicModel {
+  pub fn chain(&self, limb: Limb) -> Option<&Chain> {
+    self.chains.iter().find(|chain| chain.limb == limb)
+  }
+
+  /// End effector poses of every limb for the given joint positions.
+  pub fn forward(&self, pose: &Pose) -> Vec<EndEffector> {
+    self
+      .chains
+      .iter()
+      .map(|chain| EndEffector::new(chain.limb, &chain.forward(pose)))
+      .collect()
+  }
+
+  pub fn end_effector(&self, limb: Limb, pose: &Pose) -> Option<EndEffector> {
+    let chain = self.chain(limb)?;
+
+    Some(EndEffector::new(limb, &chain.forward(pose)))
+  }
+}
@@ -5,22 +5,75 @@ use kos::{
     inference_service_client::InferenceServiceClient,
     led_matrix_service_client::LedMatrixServiceClient,
     process_manager_service_client::ProcessManagerServiceClient,
-    sound_service_client::SoundServiceClient, CalibrateActuatorRequest,
-    ConfigureActuatorRequest, GetActuatorsStateRequest, WriteBufferRequest,
-    WriteColorBufferRequest,
+    sound_service_client::SoundServiceClient, ConfigureActuatorRequest,
   },
   kos_proto::system::system_service_client::SystemServiceClient,
 };
-use std::{fmt::Debug, future::Future, ops::Deref, sync::Arc, time::Duration};
-use tokio::sync::Mutex;
-use tonic::transport::Channel;
+use std::{
+  fmt::Debug, future::Future, ops::Deref, path::PathBuf, sync::Arc,
+  time::Duration,
+};
+use tokio::sync::{broadcast, Mutex};
+use tonic::transport::{Channel, Uri};
+
+use crate::{
+  arbiter::{Arbiter, Conflict, Lease, Priority},
+  calibration::Calibrator,
+  collision::{CollisionChecker, CollisionConfig},
+  description::RobotDescription,
+  events::RobotEvent,
+  fall::{FallConfig, FallDetector},
+  gains::{ActuatorGains, GainManager, GainProfiles},
+  hal::{check_results, Hal},
+  health::{HealthConfig, HealthMonitor},
+  ik::IkConfig,
+  kinematics::{EndEffector, KinematicModel},
+  metrics::Metrics,
+  pose::JointGroup,
+  processes::{ProcessManager, VideoStreamConfig},
+  profiler::{Profiler, ProfilerConfig},
+  recorder::{Recorder, RecorderConfig},
+  self_test::{SelfTestConfig, SelfTestReport},
+  sim::{SimConfig, Simulator},
+  telemetry::{Telemetry, TelemetryConfig},
+  velocity::{VelocityConfig, WalkCommand},
+  watchdog::{ControllerHandle, Watchdog, WatchdogConfig},
+};
+
+pub mod animation;
+pub mod arbiter;
+pub mod calibration;
+pub mod collision;
+pub mod description;
+pub mod events;
+pub mod export;
+pub mod fall;
+pub mod gains;
+pub mod hal;
+pub mod health;
+pub mod ik;
+pub mod inference;
+pub mod kinematics;
+pub mod mcap;
+pub mod metrics;
+pub mod policy;
+pub mod pose;
+pub mod processes;
+pub mod profiler;
+pub mod recorder;
+pub mod replay;
+pub mod self_test;
+pub mod sim;
+pub mod telemetry;
+pub mod velocity;
+pub mod watchdog;
 
 pub mod proto {
   pub use kos::google_proto as google;
   pub use kos::kos_proto::*;
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Joint {
   LeftShoulder,
   LeftElbow,
@@ -39,7 +92,42 @@ pub enum Joint {
   RightAnkle,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+impl Joint {
+  pub const ALL: [Joint; 12] = [
+    Joint::LeftShoulder,
+    Joint::LeftElbow,
+    Joint::LeftGripper,
+    Joint::RightShoulder,
+    Joint::RightElbow,
+    Joint::RightGripper,
+    Joint::LeftHip,
+    Joint::LeftKnee,
+    Joint::LeftAnkle,
+    Joint::RightHip,
+    Joint::RightKnee,
+    Joint::RightAnkle,
+  ];
+
+  /// The same joint on the other side of the body.
+  pub fn mirror(self) -> Joint {
+    match self {
+      Joint::LeftShoulder => Joint::RightShoulder,
+      Joint::LeftElbow => Joint::RightElbow,
+      Joint::LeftGripper => Joint::RightGripper,
+      Joint::RightShoulder => Joint::LeftShoulder,
+      Joint::RightElbow => Joint::LeftElbow,
+      Joint::RightGripper => Joint::LeftGripper,
+      Joint::LeftHip => Joint::RightHip,
+      Joint::LeftKnee => Joint::RightKnee,
+      Joint::LeftAnkle => Joint::RightAnkle,
+      Joint::RightHip => Joint::LeftHip,
+      Joint::RightKnee => Joint::LeftKnee,
+      Joint::RightAnkle => Joint::LeftAnkle,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Axis {
   Pitch,
   Yaw,
@@ -48,6 +136,7 @@ pub enum Axis {
 
 #[derive(Debug)]
 pub struct ClientInner {
+  pub uri: Uri,
   pub imu: Mutex<ImuServiceClient<Channel>>,
   pub actuator: Mutex<ActuatorServiceClient<Channel>>,
   pub sound: Mutex<SoundServiceClient<Channel>>,
@@ -60,18 +149,20 @@ pub struct ClientInner {
 #[derive(Debug, Clone)]
 pub struct Client {
   inner: Arc<ClientInner>,
+  /// Set by [`Client::measured`].
+  metrics: Option<Arc<Metrics>>,
 }
 
 pub use proto::actuator::{ActuatorCommand, CommandActuatorsRequest};
 
 impl Client {
   pub async fn connect(addr: impl Into<String>) -> eyre::Result<Self> {
-    let conn = tonic::transport::Endpoint::new(addr.into())?
-      .connect()
-      .await?;
+    let endpoint = tonic::transport::Endpoint::new(addr.into())?;
+    let conn = endpoint.connect().await?;
 
     Ok(Self {
       inner: Arc::new(ClientInner {
+        uri: endpoint.uri().clone(),
         imu: Mutex::new(ImuServiceClient::new(conn.clone())),
         actuator: Mutex::new(ActuatorServiceClient::new(conn.clone())),
         sound: Mutex::new(SoundServiceClient::new(conn.clone())),
@@ -80,6 +171,7 @@ impl Client {
         inference: Mutex::new(InferenceServiceClient::new(conn.clone())),
         system: Mutex::new(SystemServiceClient::new(conn)),
       }),
+      metrics: None,
     })
   }
 }
@@ -95,11 +187,58 @@ impl Deref for Client {
 pub struct Config {
   pub server_url: String,
   pub imu_poll_interval_ms: u64,
+  /// Where actuator calibration results are persisted.
+  pub calibration_path: PathBuf,
+  /// JSON file of per-joint gain profiles, see [`GainProfiles`].
+  pub gain_profiles_path: PathBuf,
+  /// URDF or MJCF file to take joint limits, mirror signs and link geometry
+  /// from. Kinematics, reaching and self-collision checks need one.
+  pub robot_description_path: Option<PathBuf>,
+  pub health: HealthConfig,
+  /// Run the actuator self-test right after connecting.
+  pub self_test: Option<SelfTestConfig>,
+  pub fall: FallConfig,
+  pub watchdog: WatchdogConfig,
+  pub telemetry: TelemetryConfig,
+  pub ik: IkConfig,
+  pub collision: CollisionConfig,
+  /// Run against a simulated robot instead of connecting to KOS.
+  pub simulator: Option<SimConfig>,
+  pub recorder: RecorderConfig,
+  pub profiler: ProfilerConfig,
+  pub velocity: VelocityConfig,
+  pub video: VideoStreamConfig,
 }
 
 pub struct KBot {
-  pub client: Client,
+  /// The KOS gRPC client. `None` when running against a [`Hal`] alone, such
+  /// as the simulator, where only the HAL services are available.
+  pub client: Option<Client>,
+  pub hal: Hal,
+  /// [`KBot::hal`] without the recorder, for reads that only feed live views
+  /// and should not end up in recordings.
+  pub unrecorded_hal: Hal,
   pub config: Arc<Config>,
+  pub process_manager: ProcessManager,
+  pub calibrator: Calibrator,
+  pub gains: Arc<GainManager>,
+  pub health: Arc<HealthMonitor>,
+  pub last_self_test: Mutex<Option<SelfTestReport>>,
+  pub fall_detector: Arc<FallDetector>,
+  pub watchdog: Arc<Watchdog>,
+  pub arbiter: Arc<Arbiter>,
+  pub description: Option<RobotDescription>,
+  /// Link lengths and joint axes from the robot description, if one is
+  /// loaded.
+  pub kinematics: Option<Arc<KinematicModel>>,
+  /// Self-collision checks, which also need the robot description.
+  pub collisions: Option<CollisionChecker>,
+  pub telemetry: Arc<Telemetry>,
+  pub recorder: Arc<Recorder>,
+  pub metrics: Arc<Metrics>,
+  pub profiler: Arc<Profiler>,
+  pub walk_command: WalkCommand,
+  pub events: broadcast::Sender<RobotEvent>,
 }
 
 pub trait Robot: Sized {
@@ -107,8 +246,20 @@ pub trait Robot: Sized {
 
   fn get_actuator_id(joint: Joint, axis: Option<Axis>) -> Option<u32>;
 
+  /// Allowed position range of a joint axis in degrees, if known.
+  fn joint_limits(_joint: Joint, _axis: Option<Axis>) -> Option<(f64, f64)> {
+    None
+  }
+
+  /// Factor that maps a position on `joint` to the same motion on
+  /// [`Joint::mirror`], i.e. `-1.0` when the two sides count in opposite
+  /// directions. A loaded robot description takes precedence, see
+  /// [`pose::mirror_sign`].
+  fn mirror_sign(joint: Joint, axis: Option<Axis>) -> f64;
+
   fn initialize(
-    client: Client,
+    client: Option<Client>,
+    hal: Hal,
     config: Config,
   ) -> impl Future<Output = eyre::Result<Self>>;
 }
@@ -124,17 +275,17 @@ impl Robot for KBot {
   }
 
   fn get_actuator_id(joint: Joint, axis: Option<Axis>) -> Option<u32> {
+    // Only actuators from `list_actuator_ids`, so that joint groups, poses
+    // and the simulator all agree on which joints exist.
     Some(match (joint, axis) {
       (Joint::LeftShoulder, Some(Axis::Yaw)) => 11,
       (Joint::LeftShoulder, Some(Axis::Pitch)) => 12,
-      (Joint::LeftElbow, Some(Axis::Yaw)) => 13,
-      (Joint::LeftGripper, None) => 14,
-
+      // (Joint::LeftElbow, Some(Axis::Yaw)) => 13,
+      // (Joint::LeftGripper, None) => 14,
       (Joint::RightShoulder, Some(Axis::Yaw)) => 21,
       (Joint::RightShoulder, Some(Axis::Pitch)) => 22,
       (Joint::RightElbow, Some(Axis::Yaw)) => 23,
-      (Joint::RightGripper, None) => 24,
-
+      // (Joint::RightGripper, None) => 24,
       (Joint::LeftHip, Some(Axis::Yaw)) => 31,
       (Joint::LeftHip, Some(Axis::Roll)) => 32,
       (Joint::LeftHip, Some(Axis::Pitch)) => 33,
@@ -151,16 +302,80 @@ impl Robot for KBot {
     })
   }
 
-  async fn initialize(client: Client, config: Config) -> eyre::Result<Self> {
+  fn joint_limits(joint: Joint, axis: Option<Axis>) -> Option<(f64, f64)> {
+    // Conservative software limits, wide enough for the built-in gestures
+    // and symmetric so that mirrored poses stay within them. Limits from a
+    // loaded robot description take precedence.
+    Some(match (joint, axis) {
+      (Joint::LeftShoulder | Joint::RightShoulder, Some(Axis::Pitch)) => {
+        (-120.0, 120.0)
+      }
+      (Joint::LeftShoulder | Joint::RightShoulder, Some(Axis::Yaw)) => {
+        (-100.0, 100.0)
+      }
+      (Joint::LeftElbow | Joint::RightElbow, Some(Axis::Yaw)) => {
+        (-180.0, 180.0)
+      }
+
+      (Joint::LeftHip | Joint::RightHip, Some(Axis::Yaw)) => (-45.0, 45.0),
+      (Joint::LeftHip | Joint::RightHip, Some(Axis::Roll)) => (-45.0, 45.0),
+      (Joint::LeftHip | Joint::RightHip, Some(Axis::Pitch)) => (-120.0, 120.0),
+      (Joint::LeftKnee | Joint::RightKnee, Some(Axis::Pitch)) => {
+        (-150.0, 150.0)
+      }
+      (Joint::LeftAnkle | Joint::RightAnkle, Some(Axis::Pitch)) => {
+        (-60.0, 60.0)
+      }
+
+      _ => return None,
+    })
+  }
+
+  fn mirror_sign(joint: Joint, axis: Option<Axis>) -> f64 {
+    // Used when no robot description is loaded. The left and right
+    // actuators are mounted mirror-image, so each rotary axis reads with the
+    // opposite sign on the other side; grippers open and close the same way
+    // on both hands. Listed per joint so a remounted actuator can be flipped
+    // on its own.
+    match (joint, axis) {
+      (Joint::LeftShoulder | Joint::RightShoulder, Some(Axis::Pitch)) => -1.0,
+      (Joint::LeftShoulder | Joint::RightShoulder, Some(Axis::Yaw)) => -1.0,
+      (Joint::LeftElbow | Joint::RightElbow, Some(Axis::Yaw)) => -1.0,
+      (Joint::LeftGripper | Joint::RightGripper, None) => 1.0,
+
+      (Joint::LeftHip | Joint::RightHip, Some(Axis::Yaw)) => -1.0,
+      (Joint::LeftHip | Joint::RightHip, Some(Axis::Roll)) => -1.0,
+      (Joint::LeftHip | Joint::RightHip, Some(Axis::Pitch)) => -1.0,
+      (Joint::LeftKnee | Joint::RightKnee, Some(Axis::Pitch)) => -1.0,
+      (Joint::LeftAnkle | Joint::RightAnkle, Some(Axis::Pitch)) => -1.0,
+
+      _ => 1.0,
+    }
+  }
+
+  async fn initialize(
+    client: Option<Client>,
+    hal: Hal,
+    config: Config,
+  ) -> eyre::Result<Self> {
+    let recorder = Arc::new(Recorder::new(config.recorder.clone()));
+    let metrics = Arc::new(Metrics::new()?);
+    let unrecorded_hal = hal.measured(metrics.clone());
+    let hal = unrecorded_hal.clone().recorded(recorder.clone());
+    let client = client.map(|client| client.measured(metrics.clone()));
+    let profiler =
+      Arc::new(Profiler::new(config.profiler.clone(), metrics.clone()));
+
+    let profiles = GainProfiles::load(&config.gain_profiles_path).await?;
+    let initial = profiles.initial.clone();
+    let gains = Arc::new(GainManager::new::<Self>(
+      hal.clone(),
+      Self::list_actuator_ids(),
+      profiles,
+    )?);
+
     for actuator_id in Self::list_actuator_ids() {
-      println!("Initializing Actuator {}", actuator_id);
-      // client.actuator.lock().await.calibrate_actuator(
-      //   CalibrateActuatorRequest {
-      //     actuator_id,
-      //     calibration_speed: None,
-      //     threshold_current: None
-      //   }
-      // ).await.unwrap()
+      tracing::debug!(actuator_id, "Initializing actuator");
 
       // let position = client
       //   .actuator
@@ -173,28 +388,100 @@ impl Robot for KBot {
       //   .unwrap()
       //   .into_inner();
 
-      client
-        .actuator
-        .lock()
-        .await
+      hal
+        .actuators
         .configure_actuator(ConfigureActuatorRequest {
-          actuator_id,
-          kp: None,
-          kd: None,
-          ki: None,
-          max_torque: None,
-          protective_torque: None,
-          protection_time: None,
           torque_enabled: Some(true),
-          new_actuator_id: None,
-          zero_position: None,
-          //zero_position: Some(true), // turn on for calibration
+          ..match &initial {
+            Some(name) => gains.gains(name, actuator_id)?,
+            None => ActuatorGains::default(),
+          }
+          .to_request(actuator_id)
         })
         .await?;
     }
 
+    let health = Arc::new(HealthMonitor::new(
+      hal.clone(),
+      config.health.clone(),
+      Self::list_actuator_ids(),
+      gains.clone(),
+      profiler.clone(),
+    ));
+    let (events, _) = broadcast::channel(64);
+    let description = match &config.robot_description_path {
+      Some(path) => {
+        let description = RobotDescription::load(path).await?;
+        for mismatch in description.mismatches::<Self>() {
+          tracing::warn!(
+            path = %path.display(),
+            "Robot description: {mismatch}"
+          );
+        }
+        Some(description)
+      }
+      None => None,
+    };
+    let kinematics = match &description {
+      Some(description) => Some(Arc::new(description.kinematics()?)),
+      None => {
+        tracing::warn!(
+          "No robot description loaded; kinematics, reaching and \
+           self-collision checks are unavailable"
+        );
+        None
+      }
+    };
+
+    gains.set_active(initial).await;
+
+    let fall_detector = Arc::new(FallDetector::new(
+      hal.clone(),
+      config.fall.clone(),
+      Self::list_actuator_ids(),
+      health.clone(),
+      events.clone(),
+      profiler.clone(),
+    ));
+
     Ok(Self {
+      process_manager: ProcessManager::new(
+        client.clone(),
+        config.video.clone(),
+      ),
+      calibrator: Calibrator::new(hal.clone(), config.calibration_path.clone()),
+      gains,
+      watchdog: Arc::new(Watchdog::new(
+        hal.clone(),
+        config.watchdog.clone(),
+        health.clone(),
+        fall_detector.clone(),
+        events.clone(),
+        profiler.clone(),
+      )),
+      fall_detector,
+      arbiter: Arc::new(Arbiter::new(events.clone())),
+      description,
+      kinematics: kinematics.clone(),
+      collisions: kinematics.clone().map(|kinematics| {
+        CollisionChecker::new(config.collision.clone(), kinematics)
+      }),
+      telemetry: Arc::new(Telemetry::new(
+        hal.clone(),
+        config.telemetry.clone(),
+        kinematics,
+        profiler.clone(),
+      )),
+      recorder,
+      profiler,
+      metrics,
+      walk_command: WalkCommand::new(config.velocity.clone()),
+      health,
+      events,
+      last_self_test: Mutex::new(None),
       client,
+      hal,
+      unrecorded_hal,
       config: Arc::new(config),
     })
   }
@@ -207,75 +494,10 @@ pub struct JointCommand {
   pub torque: Option<f64>,
 }
 
-const EMPTY_SCREEN: [[u8; 8]; 8] = [
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-];
-
-const FACE_WINK: [[u8; 8]; 8] = [
-  [
-    0b01111110, 0b00000000, 0b00000000, 0b01111110, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b01111000, 0b00000000, 0b00000000, 0b00011110, 0b00110000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b11111111, 0b11111111, 0b00000000, 0b00000000, 0b00100000,
-    0b00000100, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00011111, 0b11111000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-  [
-    0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
-    0b00000000, 0b00000000,
-  ],
-];
+/// IDs of the actuators `commands` go to, for span fields.
+pub(crate) fn actuator_ids(commands: &[ActuatorCommand]) -> Vec<u32> {
+  commands.iter().map(|command| command.actuator_id).collect()
+}
 
 const FACE_BLINK: [[u8; 8]; 8] = [
   [
@@ -348,24 +570,49 @@ const FACE_EYES_OPEN: [[u8; 8]; 8] = [
 ];
 
 impl KBot {
+  #[tracing::instrument(skip(config))]
   pub async fn connect(addr: String, config: Config) -> eyre::Result<Self> {
-    let client = Client::connect(addr).await?;
+    let (client, hal) = match &config.simulator {
+      Some(sim) => {
+        let description = match &config.robot_description_path {
+          Some(path) => Some(RobotDescription::load(path).await?),
+          None => None,
+        };
+        let simulator =
+          Simulator::new::<Self>(sim.clone(), description.as_ref());
+        simulator.spawn();
+        if let Some(addr) = sim.addr {
+          let addr = simulator.serve(addr).await?;
+          tracing::info!(%addr, "Simulator listening");
+        }
+        (None, simulator.hal())
+      }
+      None => {
+        let client = Client::connect(addr).await?;
+        tracing::info!("gRPC connected");
+        let hal = Hal::grpc(&client);
+        (Some(client), hal)
+      }
+    };
+
+    let bot = Self::initialize(client, hal.clone(), config).await?;
 
-    println!("GRPC Connected");
+    if let Some(config) = &bot.config.self_test {
+      let report = bot.self_test(config).await?;
+      if !report.passed {
+        tracing::error!(?report, "Actuator self-test failed");
+      }
+    }
 
-    let bot = Self::initialize(client.clone(), config).await?;
+    bot.health.spawn();
+    bot.fall_detector.spawn();
+    bot.watchdog.spawn();
+    bot.telemetry.spawn();
 
     let buffer: Vec<u8> = FACE_EYES_OPEN.into_iter().flatten().collect();
+    // let buffer = std::iter::repeat(0x00).take(64).collect();
 
-    client
-      .led_matrix
-      .lock()
-      .await
-      .write_buffer(WriteBufferRequest {
-        buffer,
-        // buffer: std::iter::repeat(0x00).take(64).collect()
-      })
-      .await?;
+    hal.led_matrix.write_buffer(buffer).await?;
 
     tokio::spawn(async move {
       let mut interval = tokio::time::interval(Duration::from_millis(5000));
@@ -373,25 +620,17 @@ impl KBot {
       loop {
         interval.tick().await;
 
-        client
+        hal
           .led_matrix
-          .lock()
-          .await
-          .write_buffer(WriteBufferRequest {
-            buffer: FACE_BLINK.into_iter().flatten().collect(),
-          })
+          .write_buffer(FACE_BLINK.into_iter().flatten().collect())
           .await
           .ok();
 
         tokio::time::sleep(Duration::from_millis(50)).await;
 
-        client
+        hal
           .led_matrix
-          .lock()
-          .await
-          .write_buffer(WriteBufferRequest {
-            buffer: FACE_EYES_OPEN.into_iter().flatten().collect(),
-          })
+          .write_buffer(FACE_EYES_OPEN.into_iter().flatten().collect())
           .await
           .ok();
       }
@@ -423,7 +662,6 @@ impl KBot {
     //         .send()
     //         .await
     //       {
-    //         eprintln!("Failed to send IMU data: {}", e);
     //         tracing::error!("{e}");
     //       }
     //     }
@@ -433,39 +671,218 @@ impl KBot {
     Ok(bot)
   }
 
+  pub fn subscribe(&self) -> broadcast::Receiver<RobotEvent> {
+    self.events.subscribe()
+  }
+
+  /// Where the hands and feet currently are, from the measured joint
+  /// positions.
+  pub async fn end_effectors(&self) -> eyre::Result<Vec<EndEffector>> {
+    let pose = self.read_pose(JointGroup::WholeBody).await?;
+
+    Ok(self.kinematic_model()?.forward(&pose))
+  }
+
+  /// The KOS gRPC client, or an error when running without one.
+  pub fn client(&self) -> eyre::Result<&Client> {
+    self
+      .client
+      .as_ref()
+      .ok_or_else(|| eyre::eyre!("Not connected to KOS"))
+  }
+
+  /// The kinematic model, or an error if there is no robot description to
+  /// take the link lengths from.
+  pub fn kinematic_model(&self) -> eyre::Result<&KinematicModel> {
+    self
+      .kinematics
+      .as_deref()
+      .ok_or_else(|| eyre::eyre!("No robot description is loaded"))
+  }
+
+  /// The self-collision checker, or an error if there is no robot
+  /// description to build it from.
+  pub fn collision_checker(&self) -> eyre::Result<&CollisionChecker> {
+    self
+      .collisions
+      .as_ref()
+      .ok_or_else(|| eyre::eyre!("No robot description is loaded"))
+  }
+
+  /// Claims `actuators` for a long-running controller and registers it with
+  /// the watchdog.
+  pub fn acquire_controller(
+    &self,
+    name: impl Into<String>,
+    priority: Priority,
+    actuators: impl IntoIterator<Item = u32>,
+  ) -> Result<ControllerHandle, Conflict> {
+    let lease = self.arbiter.acquire(name, priority, actuators)?;
+
+    Ok(self.watchdog.register(lease))
+  }
+
+  #[tracing::instrument(level = "debug", skip(self, command))]
   pub async fn command_joint(
     &self,
+    lease: &Lease,
     joint: Joint,
     axis: Option<Axis>,
     command: JointCommand,
   ) -> eyre::Result<()> {
-    let Some(actuator_id) = Self::get_actuator_id(joint, axis) else {
-      return Err(eyre::eyre!("Invalid actuator {joint:?} {axis:?}"));
-    };
-
     self
-      .client
-      .actuator
-      .lock()
+      .command_joints(lease, vec![(joint, axis, command)])
       .await
-      .command_actuators(CommandActuatorsRequest {
-        commands: vec![ActuatorCommand {
+  }
+
+  /// Sends several joint commands in a single `CommandActuators` request.
+  pub async fn command_joints(
+    &self,
+    lease: &Lease,
+    commands: Vec<(Joint, Option<Axis>, JointCommand)>,
+  ) -> eyre::Result<()> {
+    let commands = commands
+      .into_iter()
+      .map(|(joint, axis, command)| {
+        let Some(actuator_id) = Self::get_actuator_id(joint, axis) else {
+          return Err(eyre::eyre!("Invalid actuator {joint:?} {axis:?}"));
+        };
+
+        Ok(ActuatorCommand {
           actuator_id,
           position: command.position,
           velocity: command.velocity,
           torque: command.torque,
-        }],
+        })
       })
-      .await?;
+      .collect::<eyre::Result<Vec<_>>>()?;
+
+    self.command_actuators(lease, commands).await
+  }
+
+  /// Sends raw actuator commands, bypassing the joint mapping. Fails without
+  /// sending anything unless `lease` owns every actuator commanded, or after
+  /// a fall until the fall detector is reset, and fails if any actuator
+  /// rejects its command.
+  #[tracing::instrument(
+    level = "debug",
+    skip_all,
+    fields(actuator_ids = ?actuator_ids(&commands)),
+  )]
+  pub async fn command_actuators(
+    &self,
+    lease: &Lease,
+    commands: Vec<ActuatorCommand>,
+  ) -> eyre::Result<()> {
+    self.fall_detector.check()?;
+    lease.check(&actuator_ids(&commands))?;
 
-    Ok(())
+    self.health.record_commands(&commands).await;
+
+    check_results(&self.hal.actuators.command_actuators(commands).await?)
   }
 }
 
-impl Deref for KBot {
-  type Target = Client;
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    arbiter::Priority,
+    sim::{SimConfig, Simulator},
+  };
+
+  /// Configuration for a robot without KOS or files on disk.
+  pub(crate) fn config() -> Config {
+    Config {
+      server_url: String::new(),
+      imu_poll_interval_ms: 1000,
+      calibration_path: "/nonexistent/calibration.json".into(),
+      gain_profiles_path: "/nonexistent/gains.json".into(),
+      robot_description_path: None,
+      health: HealthConfig::default(),
+      self_test: None,
+      fall: FallConfig::default(),
+      watchdog: WatchdogConfig::default(),
+      telemetry: TelemetryConfig::default(),
+      ik: IkConfig::default(),
+      collision: CollisionConfig::default(),
+      simulator: None,
+      recorder: RecorderConfig::default(),
+      profiler: ProfilerConfig::default(),
+      velocity: VelocityConfig::default(),
+      video: VideoStreamConfig::default(),
+    }
+  }
+
+  /// A robot on a running simulator, which is returned as well so faults
+  /// can be injected.
+  pub(crate) async fn simulated_with(config: Config) -> (KBot, Simulator) {
+    let simulator = Simulator::new::<KBot>(SimConfig::default(), None);
+    simulator.spawn();
 
-  fn deref(&self) -> &Self::Target {
-    &self.client
+    let kbot = KBot::initialize(None, simulator.hal(), config)
+      .await
+      .unwrap();
+
+    (kbot, simulator)
+  }
+
+  async fn simulated() -> KBot {
+    simulated_with(config()).await.0
+  }
+
+  #[tokio::test]
+  async fn runs_on_a_hal_without_a_kos_client() {
+    let kbot = simulated().await;
+    assert!(kbot.client().is_err());
+
+    let lease = kbot.arbiter.acquire("test", Priority::Pose, [34]).unwrap();
+    kbot
+      .command_joint(
+        &lease,
+        Joint::LeftKnee,
+        Some(Axis::Pitch),
+        JointCommand {
+          position: Some(20.0),
+          velocity: None,
+          torque: None,
+        },
+      )
+      .await
+      .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let pose = kbot.read_pose(JointGroup::LeftLeg).await.unwrap();
+    let knee = pose.get(Joint::LeftKnee, Some(Axis::Pitch)).unwrap();
+    assert!(knee > 1.0, "knee at {knee}°");
+  }
+
+  #[tokio::test]
+  async fn rejected_commands_are_errors() {
+    let kbot = simulated().await;
+    let lease = kbot.arbiter.acquire("test", Priority::Pose, [99]).unwrap();
+
+    let error = kbot
+      .command_actuators(
+        &lease,
+        vec![ActuatorCommand {
+          actuator_id: 99,
+          position: Some(0.0),
+          velocity: None,
+          torque: None,
+        }],
+      )
+      .await
+      .unwrap_err();
+
+    assert!(error.to_string().contains("99"), "{error}");
+  }
+
+  #[test]
+  fn joint_groups_only_contain_listed_actuators() {
+    let mut actuators = JointGroup::WholeBody.actuators::<KBot>();
+    actuators.sort();
+
+    assert_eq!(actuators, KBot::list_actuator_ids());
   }
 }
@@ -0,0 +1,311 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  io::{self, Write},
+};
+
+const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_STATISTICS: u8 = 0x0B;
+const OP_SUMMARY_OFFSET: u8 = 0x0E;
+const OP_DATA_END: u8 = 0x0F;
+
+/// Writes just enough of the MCAP format (<https://mcap.dev/spec>) for
+/// Foxglove to open the file: unchunked records straight to `out`, followed
+/// on [`McapWriter::finish`] by a summary of every schema and channel and
+/// message statistics.
+pub struct McapWriter<W: Write> {
+  out: W,
+  position: u64,
+  schemas: Vec<Vec<u8>>,
+  channels: Vec<Vec<u8>>,
+  sequences: BTreeMap<u16, u32>,
+  message_count: u64,
+  /// Log times of the first and last message, in nanoseconds.
+  time_range: Option<(u64, u64)>,
+}
+
+impl<W: Write> McapWriter<W> {
+  pub fn new(out: W, library: &str) -> io::Result<Self> {
+    let mut writer = Self {
+      out,
+      position: 0,
+      schemas: Vec::new(),
+      channels: Vec::new(),
+      sequences: BTreeMap::new(),
+      message_count: 0,
+      time_range: None,
+    };
+
+    writer.write_bytes(MAGIC)?;
+
+    let mut header = Vec::new();
+    put_str(&mut header, "");
+    put_str(&mut header, library);
+    writer.write_record(OP_HEADER, &header)?;
+
+    Ok(writer)
+  }
+
+  /// Registers a schema, e.g. a JSON schema with `encoding` "jsonschema".
+  pub fn add_schema(
+    &mut self,
+    name: &str,
+    encoding: &str,
+    data: &[u8],
+  ) -> io::Result<u16> {
+    let id = self.schemas.len() as u16 + 1;
+
+    let mut schema = Vec::new();
+    schema.extend(id.to_le_bytes());
+    put_str(&mut schema, name);
+    put_str(&mut schema, encoding);
+    put_bytes(&mut schema, data);
+
+    self.write_record(OP_SCHEMA, &schema)?;
+    self.schemas.push(schema);
+
+    Ok(id)
+  }
+
+  pub fn add_channel(
+    &mut self,
+    schema_id: u16,
+    topic: &str,
+    message_encoding: &str,
+  ) -> io::Result<u16> {
+    let id = self.channels.len() as u16;
+
+    let mut channel = Vec::new();
+    channel.extend(id.to_le_bytes());
+    channel.extend(schema_id.to_le_bytes());
+    put_str(&mut channel, topic);
+    put_str(&mut channel, message_encoding);
+    // No metadata.
+    channel.extend(0u32.to_le_bytes());
+
+    self.write_record(OP_CHANNEL, &channel)?;
+    self.channels.push(channel);
+    self.sequences.insert(id, 0);
+
+    Ok(id)
+  }
+
+  /// Writes a message on `channel_id` logged at `log_time` nanoseconds.
+  pub fn write_message(
+    &mut self,
+    channel_id: u16,
+    log_time: u64,
+    data: &[u8],
+  ) -> io::Result<()> {
+    let sequence = self.sequences.entry(channel_id).or_default();
+    *sequence += 1;
+
+    let mut message = Vec::with_capacity(22 + data.len());
+    message.extend(channel_id.to_le_bytes());
+    message.extend(sequence.to_le_bytes());
+    message.extend(log_time.to_le_bytes());
+    message.extend(log_time.to_le_bytes());
+    message.extend(data);
+
+    self.write_record(OP_MESSAGE, &message)?;
+
+    self.message_count += 1;
+    self.time_range = Some(match self.time_range {
+      Some((start, end)) => (start.min(log_time), end.max(log_time)),
+      None => (log_time, log_time),
+    });
+
+    Ok(())
+  }
+
+  /// Writes the summary and footer and hands back the underlying writer.
+  pub fn finish(mut self) -> io::Result<W> {
+    // A zero CRC means "not computed".
+    self.write_record(OP_DATA_END, &0u32.to_le_bytes())?;
+
+    let summary_start = self.position;
+    let mut offsets = Vec::new();
+
+    let schemas = std::mem::take(&mut self.schemas);
+    offsets.push(self.write_group(OP_SCHEMA, &schemas)?);
+
+    let channels = std::mem::take(&mut self.channels);
+    offsets.push(self.write_group(OP_CHANNEL, &channels)?);
+
+    let (start, end) = self.time_range.unwrap_or_default();
+    let mut statistics = Vec::new();
+    statistics.extend(self.message_count.to_le_bytes());
+    statistics.extend((schemas.len() as u16).to_le_bytes());
+    statistics.extend((channels.len() as u32).to_le_bytes());
+    // Attachments, metadata and chunks.
+    statistics.extend([0u8; 12]);
+    statistics.extend(start.to_le_bytes());
+    statistics.extend(end.to_le_bytes());
+    let mut counts = Vec::new();
+    for (channel_id, count) in &self.sequences {
+      counts.extend(channel_id.to_le_bytes());
+      counts.extend((*count as u64).to_le_bytes());
+    }
+    put_bytes(&mut statistics, &counts);
+    let statistics = vec![statistics];
+    offsets.push(self.write_group(OP_STATISTICS, &statistics)?);
+
+    let summary_offset_start = self.position;
+    for (opcode, start, length) in offsets {
+      let mut offset = vec![opcode];
+      offset.extend(start.to_le_bytes());
+      offset.extend(length.to_le_bytes());
+      self.write_record(OP_SUMMARY_OFFSET, &offset)?;
+    }
+
+    let mut footer = Vec::new();
+    footer.extend(summary_start.to_le_bytes());
+    footer.extend(summary_offset_start.to_le_bytes());
+    footer.extend(0u32.to_le_bytes());
+    self.write_record(OP_FOOTER, &footer)?;
+
+    self.write_bytes(MAGIC)?;
+    self.out.flush()?;
+
+    Ok(self.out)
+  }
+
+  /// Writes records of one kind back to back and returns where they are.
+  fn write_group(
+    &mut self,
+    opcode: u8,
+    records: &[Vec<u8>],
+  ) -> io::Result<(u8, u64, u64)> {
+    let start = self.position;
+    for record in records {
+      self.write_record(opcode, record)?;
+    }
+
+    Ok((opcode, start, self.position - start))
+  }
+
+  fn write_record(&mut self, opcode: u8, content: &[u8]) -> io::Result<()> {
+    self.write_bytes(&[opcode])?;
+    self.write_bytes(&(content.len() as u64).to_le_bytes())?;
+    self.write_bytes(content)
+  }
+
+  fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+    self.out.write_all(bytes)?;
+    self.position += bytes.len() as u64;
+
+    Ok(())
+  }
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+  put_bytes(out, value.as_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, value: &[u8]) {
+  out.extend((value.len() as u32).to_le_bytes());
+  out.extend(value);
+}
+
+/// A message read back from an MCAP file.
+#[derive(Debug, Clone)]
+pub struct McapMessage {
+  pub topic: String,
+  /// Nanoseconds.
+  pub log_time: u64,
+  pub data: Vec<u8>,
+}
+
+/// Reads every message of an MCAP file in file order. Only understands the
+/// unchunked layout written by [`McapWriter`].
+pub fn read_messages(bytes: &[u8]) -> io::Result<Vec<McapMessage>> {
+  let Some(mut rest) = bytes.strip_prefix(MAGIC) else {
+    return Err(invalid("not an MCAP file"));
+  };
+
+  let mut topics = HashMap::new();
+  let mut messages = Vec::new();
+  while let Some((&opcode, after)) = rest.split_first() {
+    let mut record = Reader(after);
+    let length = record.u64()? as usize;
+    let (mut content, after) = record.split(length)?;
+    rest = after;
+
+    match opcode {
+      OP_CHANNEL => {
+        let id = content.u16()?;
+        let _schema_id = content.u16()?;
+        topics.insert(id, content.str()?);
+      }
+      OP_MESSAGE => {
+        let channel_id = content.u16()?;
+        let _sequence = content.u32()?;
+        let log_time = content.u64()?;
+        let _publish_time = content.u64()?;
+        let Some(topic) = topics.get(&channel_id) else {
+          return Err(invalid("message on an unknown channel"));
+        };
+
+        messages.push(McapMessage {
+          topic: topic.clone(),
+          log_time,
+          data: content.0.to_vec(),
+        });
+      }
+      // Everything after the data section repeats what was already read.
+      OP_DATA_END | OP_FOOTER => break,
+      _ => {}
+    }
+  }
+
+  Ok(messages)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+  fn split(&mut self, length: usize) -> io::Result<(Reader<'a>, &'a [u8])> {
+    if self.0.len() < length {
+      return Err(invalid("truncated record"));
+    }
+    let (head, tail) = self.0.split_at(length);
+
+    Ok((Reader(head), tail))
+  }
+
+  fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+    let (head, tail) = self.split(N)?;
+    self.0 = tail;
+
+    Ok(head.0.try_into().unwrap())
+  }
+
+  fn u16(&mut self) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(self.take()?))
+  }
+
+  fn u32(&mut self) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(self.take()?))
+  }
+
+  fn u64(&mut self) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(self.take()?))
+  }
+
+  fn str(&mut self) -> io::Result<String> {
+    let length = self.u32()? as usize;
+    let (head, tail) = self.split(length)?;
+    self.0 = tail;
+
+    String::from_utf8(head.0.to_vec()).map_err(|_| invalid("invalid string"))
+  }
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
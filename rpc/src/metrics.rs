@@ -0,0 +1,467 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use prometheus::{
+  exponential_buckets, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts,
+  HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::{
+  hal::{ActuatorBackend, Hal, ImuBackend, LedMatrixBackend, SoundBackend},
+  proto::{
+    actuator::{
+      ActuatorCommand, ActuatorStateResponse, CalibrateActuatorRequest,
+      ConfigureActuatorRequest,
+    },
+    common::{ActionResponse, ActionResult},
+    imu::{EulerAnglesResponse, ImuValuesResponse, QuaternionResponse},
+    sound::AudioConfig,
+  },
+  Client,
+};
+
+/// Prometheus metrics for watching long runs.
+#[derive(Debug)]
+pub struct Metrics {
+  registry: Registry,
+  hal_latency: HistogramVec,
+  hal_errors: IntCounterVec,
+  commands: IntCounterVec,
+  temperature: GaugeVec,
+  loop_period: Histogram,
+  loop_jitter: Histogram,
+  loop_frequency: Gauge,
+  inference_latency: Histogram,
+  loop_stages: HistogramVec,
+  deadline_misses: IntCounterVec,
+  errors: IntCounterVec,
+  fallen: IntGauge,
+  watchdog_tripped: IntGauge,
+  safety_stop: IntGauge,
+}
+
+impl Metrics {
+  pub fn new() -> eyre::Result<Self> {
+    let registry = Registry::new_custom(Some("kbot".to_string()), None)?;
+    // 0.1 ms to about 3 s.
+    let seconds = exponential_buckets(0.0001, 2.0, 16)?;
+
+    let metrics = Self {
+      hal_latency: HistogramVec::new(
+        HistogramOpts::new(
+          "hal_request_duration_seconds",
+          "Latency of calls to the robot's services",
+        )
+        .buckets(seconds.clone()),
+        &["service", "method"],
+      )?,
+      hal_errors: IntCounterVec::new(
+        Opts::new("hal_errors_total", "Failed calls to the robot's services"),
+        &["service", "method"],
+      )?,
+      commands: IntCounterVec::new(
+        Opts::new("actuator_commands_total", "Commands sent per actuator"),
+        &["actuator"],
+      )?,
+      temperature: GaugeVec::new(
+        Opts::new(
+          "actuator_temperature_celsius",
+          "Last reported actuator temperature",
+        ),
+        &["actuator"],
+      )?,
+      loop_period: Histogram::with_opts(
+        HistogramOpts::new(
+          "walk_loop_period_seconds",
+          "Time between walk loop ticks",
+        )
+        .buckets(seconds.clone()),
+      )?,
+      loop_jitter: Histogram::with_opts(
+        HistogramOpts::new(
+          "walk_loop_jitter_seconds",
+          "Change in period between consecutive walk loop ticks",
+        )
+        .buckets(seconds.clone()),
+      )?,
+      loop_frequency: Gauge::new(
+        "walk_loop_frequency_hertz",
+        "Walk loop rate measured over the last tick",
+      )?,
+      inference_latency: Histogram::with_opts(
+        HistogramOpts::new(
+          "policy_inference_duration_seconds",
+          "Time spent waiting for the walk policy",
+        )
+        .buckets(seconds.clone()),
+      )?,
+      loop_stages: HistogramVec::new(
+        HistogramOpts::new(
+          "loop_stage_duration_seconds",
+          "Time spent in each stage of control loop ticks",
+        )
+        .buckets(seconds),
+        &["loop", "stage"],
+      )?,
+      deadline_misses: IntCounterVec::new(
+        Opts::new(
+          "loop_deadline_misses_total",
+          "Control loop ticks that overran their deadline",
+        ),
+        &["loop"],
+      )?,
+      errors: IntCounterVec::new(
+        Opts::new("errors_total", "Errors in control loops"),
+        &["source"],
+      )?,
+      fallen: IntGauge::new("fallen", "1 while a detected fall is latched")?,
+      watchdog_tripped: IntGauge::new(
+        "watchdog_tripped_controllers",
+        "Controllers the watchdog has put in a safe state",
+      )?,
+      safety_stop: IntGauge::new(
+        "safety_stop",
+        "1 while a fall is latched or the watchdog has tripped a controller; \
+         not a hardware emergency stop",
+      )?,
+      registry,
+    };
+
+    metrics
+      .registry
+      .register(Box::new(metrics.hal_latency.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.hal_errors.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.commands.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.temperature.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.loop_period.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.loop_jitter.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.loop_frequency.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.inference_latency.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.loop_stages.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.deadline_misses.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.errors.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.fallen.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.watchdog_tripped.clone()))?;
+    metrics
+      .registry
+      .register(Box::new(metrics.safety_stop.clone()))?;
+
+    Ok(metrics)
+  }
+
+  /// Records one walk loop tick. `previous` is the period of the tick
+  /// before, if there was one.
+  pub fn walk_tick(
+    &self,
+    period: Duration,
+    previous: Option<Duration>,
+    inference: Duration,
+  ) {
+    self.loop_period.observe(period.as_secs_f64());
+    if period > Duration::ZERO {
+      self.loop_frequency.set(1.0 / period.as_secs_f64());
+    }
+    if let Some(previous) = previous {
+      self
+        .loop_jitter
+        .observe(period.abs_diff(previous).as_secs_f64());
+    }
+    self.inference_latency.observe(inference.as_secs_f64());
+  }
+
+  /// Records a `stage` of a tick of `loop_name`, see
+  /// [`crate::profiler::Profiler`].
+  pub fn loop_stage(&self, loop_name: &str, stage: &str, duration: Duration) {
+    self
+      .loop_stages
+      .with_label_values(&[loop_name, stage])
+      .observe(duration.as_secs_f64());
+  }
+
+  pub fn deadline_miss(&self, loop_name: &str) {
+    self.deadline_misses.with_label_values(&[loop_name]).inc();
+  }
+
+  /// Counts an error from `source`, e.g. "policy".
+  pub fn error(&self, source: &str) {
+    self.errors.with_label_values(&[source]).inc();
+  }
+
+  pub fn set_safety(&self, fallen: bool, watchdog_tripped: usize) {
+    self.fallen.set(fallen as i64);
+    self.watchdog_tripped.set(watchdog_tripped as i64);
+    self
+      .safety_stop
+      .set((fallen || watchdog_tripped > 0) as i64);
+  }
+
+  /// Everything in the Prometheus text format.
+  pub fn encode(&self) -> eyre::Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+    Ok(String::from_utf8(buffer)?)
+  }
+
+  async fn measure<T>(
+    &self,
+    service: &str,
+    method: &str,
+    call: impl Future<Output = eyre::Result<T>>,
+  ) -> eyre::Result<T> {
+    let timer = self
+      .hal_latency
+      .with_label_values(&[service, method])
+      .start_timer();
+    let result = call.await;
+    timer.observe_duration();
+
+    if result.is_err() {
+      self.hal_errors.with_label_values(&[service, method]).inc();
+    }
+
+    result
+  }
+}
+
+impl Client {
+  /// Times every call this client makes outside of a [`Hal`], such as model
+  /// inference and KClip recordings, in `metrics`.
+  pub fn measured(mut self, metrics: Arc<Metrics>) -> Self {
+    self.metrics = Some(metrics);
+    self
+  }
+
+  /// Runs `call` to `service`, timing it if the client is measured.
+  pub(crate) async fn measure<T>(
+    &self,
+    service: &str,
+    method: &str,
+    call: impl Future<Output = eyre::Result<T>>,
+  ) -> eyre::Result<T> {
+    match &self.metrics {
+      Some(metrics) => metrics.measure(service, method, call).await,
+      None => call.await,
+    }
+  }
+}
+
+impl Hal {
+  /// Times every call through this HAL, and counts commands and tracks
+  /// actuator temperatures in `metrics`.
+  pub fn measured(self, metrics: Arc<Metrics>) -> Hal {
+    Hal {
+      actuators: Arc::new(Measured {
+        inner: self.actuators,
+        metrics: metrics.clone(),
+      }),
+      imu: Arc::new(Measured {
+        inner: self.imu,
+        metrics: metrics.clone(),
+      }),
+      led_matrix: Arc::new(Measured {
+        inner: self.led_matrix,
+        metrics: metrics.clone(),
+      }),
+      sound: Arc::new(Measured {
+        inner: self.sound,
+        metrics,
+      }),
+    }
+  }
+}
+
+#[derive(Debug)]
+struct Measured<T: ?Sized> {
+  inner: Arc<T>,
+  metrics: Arc<Metrics>,
+}
+
+#[async_trait]
+impl ActuatorBackend for Measured<dyn ActuatorBackend> {
+  async fn command_actuators(
+    &self,
+    commands: Vec<ActuatorCommand>,
+  ) -> eyre::Result<Vec<ActionResult>> {
+    for command in &commands {
+      self
+        .metrics
+        .commands
+        .with_label_values(&[&command.actuator_id.to_string()])
+        .inc();
+    }
+
+    self
+      .metrics
+      .measure(
+        "actuator",
+        "command_actuators",
+        self.inner.command_actuators(commands),
+      )
+      .await
+  }
+
+  async fn configure_actuator(
+    &self,
+    request: ConfigureActuatorRequest,
+  ) -> eyre::Result<ActionResponse> {
+    self
+      .metrics
+      .measure(
+        "actuator",
+        "configure_actuator",
+        self.inner.configure_actuator(request),
+      )
+      .await
+  }
+
+  async fn calibrate_actuator(
+    &self,
+    request: CalibrateActuatorRequest,
+  ) -> eyre::Result<()> {
+    self
+      .metrics
+      .measure(
+        "actuator",
+        "calibrate_actuator",
+        self.inner.calibrate_actuator(request),
+      )
+      .await
+  }
+
+  async fn get_actuators_state(
+    &self,
+    actuator_ids: Vec<u32>,
+  ) -> eyre::Result<Vec<ActuatorStateResponse>> {
+    let states = self
+      .metrics
+      .measure(
+        "actuator",
+        "get_actuators_state",
+        self.inner.get_actuators_state(actuator_ids),
+      )
+      .await?;
+
+    for state in &states {
+      if let Some(temperature) = state.temperature {
+        self
+          .metrics
+          .temperature
+          .with_label_values(&[&state.actuator_id.to_string()])
+          .set(temperature);
+      }
+    }
+
+    Ok(states)
+  }
+}
+
+#[async_trait]
+impl ImuBackend for Measured<dyn ImuBackend> {
+  async fn get_values(&self) -> eyre::Result<ImuValuesResponse> {
+    self
+      .metrics
+      .measure("imu", "get_values", self.inner.get_values())
+      .await
+  }
+
+  async fn get_euler(&self) -> eyre::Result<EulerAnglesResponse> {
+    self
+      .metrics
+      .measure("imu", "get_euler", self.inner.get_euler())
+      .await
+  }
+
+  async fn get_quaternion(&self) -> eyre::Result<QuaternionResponse> {
+    self
+      .metrics
+      .measure("imu", "get_quaternion", self.inner.get_quaternion())
+      .await
+  }
+}
+
+#[async_trait]
+impl LedMatrixBackend for Measured<dyn LedMatrixBackend> {
+  async fn write_buffer(
+    &self,
+    buffer: Vec<u8>,
+  ) -> eyre::Result<ActionResponse> {
+    self
+      .metrics
+      .measure(
+        "led_matrix",
+        "write_buffer",
+        self.inner.write_buffer(buffer),
+      )
+      .await
+  }
+}
+
+#[async_trait]
+impl SoundBackend for Measured<dyn SoundBackend> {
+  async fn play_audio(
+    &self,
+    config: AudioConfig,
+    audio: Vec<u8>,
+  ) -> eyre::Result<ActionResponse> {
+    self
+      .metrics
+      .measure("sound", "play_audio", self.inner.play_audio(config, audio))
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn calls_and_safety_stops_are_exported() {
+    let metrics = Metrics::new().unwrap();
+
+    metrics
+      .measure("inference", "forward", async { Ok(()) })
+      .await
+      .unwrap();
+    let failed = metrics
+      .measure("inference", "forward", async {
+        Err::<(), _>(eyre::eyre!("unavailable"))
+      })
+      .await;
+    assert!(failed.is_err());
+    metrics.set_safety(false, 1);
+
+    let text = metrics.encode().unwrap();
+    let forward = "{method=\"forward\",service=\"inference\"}";
+    assert!(text.contains(&format!(
+      "kbot_hal_request_duration_seconds_count{forward} 2"
+    )));
+    assert!(text.contains(&format!("kbot_hal_errors_total{forward} 1")));
+    assert!(text.contains("kbot_safety_stop 1"));
+    assert!(text.contains("kbot_fallen 0"));
+  }
+}
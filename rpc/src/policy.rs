@@ -0,0 +1,472 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  hal::{ActuatorBackend, ImuBackend},
+  proto::{
+    actuator::ActuatorStateResponse,
+    imu::ImuValuesResponse,
+    inference::{tensor::Dimension, Tensor},
+  },
+  Axis, Client, Joint, JointCommand, Robot,
+};
+
+/// Joint order of the walking policy's `dof_pos`, `dof_vel` and actions.
+pub const POLICY_JOINTS: [&str; 10] = [
+  "R_Hip_Pitch",
+  "L_Hip_Pitch",
+  "R_Hip_Yaw",
+  "L_Hip_Yaw",
+  "R_Hip_Roll",
+  "L_Hip_Roll",
+  "R_Knee_Pitch",
+  "L_Knee_Pitch",
+  "R_Ankle_Pitch",
+  "L_Ankle_Pitch",
+];
+
+/// `(Joint, Axis)` for each entry of [`POLICY_JOINTS`].
+pub const POLICY_ACTUATORS: [(Joint, Axis); 10] = [
+  (Joint::RightHip, Axis::Pitch),
+  (Joint::LeftHip, Axis::Pitch),
+  (Joint::RightHip, Axis::Yaw),
+  (Joint::LeftHip, Axis::Yaw),
+  (Joint::RightHip, Axis::Roll),
+  (Joint::LeftHip, Axis::Roll),
+  (Joint::RightKnee, Axis::Pitch),
+  (Joint::LeftKnee, Axis::Pitch),
+  (Joint::RightAnkle, Axis::Pitch),
+  (Joint::LeftAnkle, Axis::Pitch),
+];
+
+/// How long a policy may take to answer before the tick fails. A few walk
+/// loop ticks, so a stuck inference server stops the walk instead of hanging
+/// it.
+pub const INFERENCE_TIMEOUT: Duration = Duration::from_millis(50);
+
+// Mirrors the constants in `ml/inference/inference_server.py`.
+const ANG_VEL_SCALE: f64 = 0.25;
+const COMMAND_SCALE: f64 = 1.0;
+const DOF_POS_SCALE: f64 = 1.0;
+const DOF_VEL_SCALE: f64 = 0.05;
+const ACTION_SCALE: f64 = 0.25;
+
+/// Raw sensor readings for one tick of the walk loop.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Observation {
+  pub base_ang_vel: [f64; 3],
+  pub accel: [f64; 3],
+  pub commands: [f64; 3],
+  pub dof_pos: Vec<f64>,
+  pub dof_vel: Vec<f64>,
+  pub actions: Vec<f64>,
+}
+
+/// Actuator ids of the [`POLICY_ACTUATORS`], in the same order.
+pub fn policy_actuator_ids<R: Robot>() -> eyre::Result<Vec<u32>> {
+  POLICY_ACTUATORS
+    .iter()
+    .map(|(joint, axis)| {
+      R::get_actuator_id(*joint, Some(*axis))
+        .ok_or_else(|| eyre::eyre!("No actuator for {joint:?} {axis:?}"))
+    })
+    .collect()
+}
+
+impl Observation {
+  /// Reads the IMU and the [`POLICY_ACTUATORS`] and builds the observation
+  /// for walking at velocity `commands`.
+  pub async fn read<R: Robot>(
+    imu: &dyn ImuBackend,
+    actuators: &dyn ActuatorBackend,
+    commands: [f64; 3],
+  ) -> eyre::Result<Self> {
+    let data = imu.get_values().await?;
+    let states = actuators
+      .get_actuators_state(policy_actuator_ids::<R>()?)
+      .await?;
+
+    Self::from_readings::<R>(&data, &states, commands)
+  }
+
+  /// Builds the observation from an IMU sample and the states of the
+  /// [`POLICY_ACTUATORS`]. Fails if any of them did not report a position and
+  /// velocity, rather than feeding the policy made-up zeros.
+  pub fn from_readings<R: Robot>(
+    data: &ImuValuesResponse,
+    states: &[ActuatorStateResponse],
+    commands: [f64; 3],
+  ) -> eyre::Result<Self> {
+    let actuator_ids = policy_actuator_ids::<R>()?;
+    let mut dof_pos = Vec::with_capacity(actuator_ids.len());
+    let mut dof_vel = Vec::with_capacity(actuator_ids.len());
+
+    // Keep the policy's joint order whatever order the states come back in.
+    for id in actuator_ids {
+      let state = states
+        .iter()
+        .find(|state| state.actuator_id == id)
+        .ok_or_else(|| eyre::eyre!("Actuator {id} reported no state"))?;
+      let (Some(position), Some(velocity)) = (state.position, state.velocity)
+      else {
+        return Err(eyre::eyre!(
+          "Actuator {id} reported no position or velocity"
+        ));
+      };
+
+      dof_pos.push(position);
+      dof_vel.push(velocity);
+    }
+
+    Ok(Self {
+      base_ang_vel: [data.gyro_x, data.gyro_y, data.gyro_z],
+      accel: [data.accel_x, data.accel_y, data.accel_z],
+      commands,
+      dof_pos,
+      dof_vel,
+      actions: vec![0.0; POLICY_ACTUATORS.len()],
+    })
+  }
+
+  /// Flattens the observation into the 39-element policy input, applying the
+  /// same scaling as the Python inference server.
+  pub fn to_input(&self, last_actions: &[f64]) -> Vec<f32> {
+    let [ax, ay, az] = self.accel;
+    let norm = (ax * ax + ay * ay + az * az).sqrt().max(f64::EPSILON);
+
+    self
+      .base_ang_vel
+      .iter()
+      .map(|v| v * ANG_VEL_SCALE)
+      .chain([ax / norm, ay / norm, az / norm])
+      .chain(self.commands.iter().map(|v| v * COMMAND_SCALE))
+      .chain(self.dof_pos.iter().map(|v| v * DOF_POS_SCALE))
+      .chain(self.dof_vel.iter().map(|v| v * DOF_VEL_SCALE))
+      .chain(last_actions.iter().copied())
+      .map(|v| v as f32)
+      .collect()
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JointAngles {
+  #[serde(rename = "L_Ankle_Pitch")]
+  pub l_ankle_pitch: f64,
+  #[serde(rename = "L_Hip_Pitch")]
+  pub l_hip_pitch: f64,
+  #[serde(rename = "L_Hip_Yaw")] // SWAPPED WITH ROLL
+  pub l_hip_roll: f64,
+  #[serde(rename = "L_Hip_Roll")]
+  pub l_hip_yaw: f64,
+  #[serde(rename = "L_Knee_Pitch")]
+  pub l_knee_pitch: f64,
+  #[serde(rename = "R_Ankle_Pitch")]
+  pub r_ankle_pitch: f64,
+  #[serde(rename = "R_Hip_Pitch")]
+  pub r_hip_pitch: f64,
+  #[serde(rename = "R_Hip_Yaw")] // SWAPPED WITH ROLL
+  pub r_hip_roll: f64,
+  #[serde(rename = "R_Hip_Roll")]
+  pub r_hip_yaw: f64,
+  #[serde(rename = "R_Knee_Pitch")]
+  pub r_knee_pitch: f64,
+}
+
+impl JointAngles {
+  /// Builds joint angles from values ordered like [`POLICY_JOINTS`].
+  pub fn from_ordered(values: &[f64]) -> eyre::Result<Self> {
+    if values.len() != POLICY_JOINTS.len() {
+      return Err(eyre::eyre!(
+        "Expected {} policy outputs, got {}",
+        POLICY_JOINTS.len(),
+        values.len()
+      ));
+    }
+
+    let named = POLICY_JOINTS
+      .iter()
+      .zip(values)
+      .map(|(name, value)| (name.to_string(), serde_json::json!(value)))
+      .collect::<serde_json::Map<_, _>>();
+
+    Ok(serde_json::from_value(serde_json::Value::Object(named))?)
+  }
+
+  /// Values ordered like [`POLICY_JOINTS`], the inverse of
+  /// [`JointAngles::from_ordered`].
+  pub fn to_ordered(&self) -> eyre::Result<Vec<f64>> {
+    let named = serde_json::to_value(self)?;
+    POLICY_JOINTS
+      .iter()
+      .map(|name| {
+        named[*name]
+          .as_f64()
+          .ok_or_else(|| eyre::eyre!("No joint angle named {name}"))
+      })
+      .collect()
+  }
+
+  pub fn into_commands(self) -> Vec<(Joint, Option<Axis>, JointCommand)> {
+    [
+      (Joint::LeftAnkle, Axis::Pitch, self.l_ankle_pitch),
+      (Joint::LeftHip, Axis::Pitch, self.l_hip_pitch),
+      (Joint::LeftHip, Axis::Roll, self.l_hip_roll),
+      (Joint::LeftHip, Axis::Yaw, self.l_hip_yaw),
+      (Joint::LeftKnee, Axis::Pitch, self.l_knee_pitch),
+      (Joint::RightAnkle, Axis::Pitch, self.r_ankle_pitch),
+      (Joint::RightHip, Axis::Pitch, self.r_hip_pitch),
+      (Joint::RightHip, Axis::Roll, self.r_hip_roll),
+      (Joint::RightHip, Axis::Yaw, self.r_hip_yaw),
+      (Joint::RightKnee, Axis::Pitch, self.r_knee_pitch),
+    ]
+    .into_iter()
+    .map(|(joint, axis, position)| {
+      (
+        joint,
+        Some(axis),
+        JointCommand {
+          position: Some(position),
+          velocity: None,
+          torque: None,
+        },
+      )
+    })
+    .collect()
+  }
+}
+
+/// What the walk policy answered for one observation, as recorded on
+/// [`crate::recorder::Topic::PolicyActions`].
+///
+/// The policy's outputs are not keyed like its inputs: the output it calls
+/// `L_Hip_Yaw` drives the left hip roll actuator and the other way round (see
+/// [`JointAngles`]), so both the raw outputs and the per-actuator targets are
+/// kept.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PolicyActions {
+  /// Unscaled policy outputs ordered like [`POLICY_JOINTS`], as fed back into
+  /// the next observation.
+  pub actions: Vec<f64>,
+  /// Commanded position of each actuator, by actuator id.
+  pub targets: BTreeMap<u32, f64>,
+}
+
+impl PolicyActions {
+  pub fn new<R: Robot>(joints: &JointAngles) -> eyre::Result<Self> {
+    let actions = joints
+      .to_ordered()?
+      .into_iter()
+      .map(|value| value / ACTION_SCALE)
+      .collect();
+    let targets = joints
+      .clone()
+      .into_commands()
+      .into_iter()
+      .filter_map(|(joint, axis, command)| {
+        Some((R::get_actuator_id(joint, axis)?, command.position?))
+      })
+      .collect();
+
+    Ok(Self { actions, targets })
+  }
+}
+
+/// Policy served by `ml/inference/inference_server.py` over HTTP.
+pub struct HttpPolicy {
+  url: String,
+  http: reqwest::Client,
+  timeout: Duration,
+}
+
+impl HttpPolicy {
+  pub fn new(url: impl Into<String>) -> Self {
+    Self {
+      url: url.into(),
+      http: reqwest::Client::new(),
+      timeout: INFERENCE_TIMEOUT,
+    }
+  }
+
+  /// Overrides [`INFERENCE_TIMEOUT`].
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  pub async fn infer(&self, obs: &Observation) -> eyre::Result<JointAngles> {
+    let response = self
+      .http
+      .post(&self.url)
+      .timeout(self.timeout)
+      .json(obs)
+      .send()
+      .await?;
+
+    Ok(response.json().await?)
+  }
+}
+
+/// Policy running on the robot's own KOS inference service.
+pub struct KosPolicy {
+  client: Client,
+  model_uid: String,
+  input_name: String,
+  output_name: String,
+  last_actions: Vec<f64>,
+}
+
+impl KosPolicy {
+  pub fn new(client: Client, model_uid: impl Into<String>) -> Self {
+    Self {
+      client,
+      model_uid: model_uid.into(),
+      input_name: "obs".to_string(),
+      output_name: "actions".to_string(),
+      last_actions: vec![0.0; POLICY_JOINTS.len()],
+    }
+  }
+
+  /// Overrides the model's input and output tensor names.
+  pub fn with_tensor_names(
+    mut self,
+    input_name: impl Into<String>,
+    output_name: impl Into<String>,
+  ) -> Self {
+    self.input_name = input_name.into();
+    self.output_name = output_name.into();
+    self
+  }
+
+  pub async fn infer(
+    &mut self,
+    obs: &Observation,
+  ) -> eyre::Result<JointAngles> {
+    let values = obs.to_input(&self.last_actions);
+    let input = Tensor {
+      shape: vec![
+        Dimension {
+          size: 1,
+          name: "batch".to_string(),
+          dynamic: false,
+        },
+        Dimension {
+          size: values.len() as u32,
+          name: "obs".to_string(),
+          dynamic: false,
+        },
+      ],
+      values,
+    };
+
+    let forward = self.client.forward(
+      self.model_uid.clone(),
+      HashMap::from([(self.input_name.clone(), input)]),
+    );
+    let mut outputs = tokio::time::timeout(INFERENCE_TIMEOUT, forward)
+      .await
+      .map_err(|_| {
+      eyre::eyre!("Model {} did not answer in time", self.model_uid)
+    })??;
+
+    let Some(output) = outputs.remove(&self.output_name) else {
+      return Err(eyre::eyre!(
+        "Model {} has no output named {}",
+        self.model_uid,
+        self.output_name
+      ));
+    };
+
+    // The raw (unscaled) actions are fed back in as the next observation.
+    self.last_actions = output.values.iter().map(|v| *v as f64).collect();
+
+    let scaled = self
+      .last_actions
+      .iter()
+      .map(|v| v * ACTION_SCALE)
+      .collect::<Vec<_>>();
+
+    JointAngles::from_ordered(&scaled)
+  }
+}
+
+/// Backend used by the walk loop to turn observations into joint targets.
+pub enum WalkPolicy {
+  Http(HttpPolicy),
+  Kos(KosPolicy),
+}
+
+impl WalkPolicy {
+  pub async fn infer(
+    &mut self,
+    obs: &Observation,
+  ) -> eyre::Result<JointAngles> {
+    match self {
+      WalkPolicy::Http(policy) => policy.infer(obs).await,
+      WalkPolicy::Kos(policy) => policy.infer(obs).await,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::KBot;
+
+  fn state(actuator_id: u32, position: f64) -> ActuatorStateResponse {
+    ActuatorStateResponse {
+      actuator_id,
+      online: true,
+      position: Some(position),
+      velocity: Some(-position),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn observations_keep_the_policy_joint_order() {
+    let ids = policy_actuator_ids::<KBot>().unwrap();
+    let states = ids
+      .iter()
+      .rev()
+      .map(|&id| state(id, id as f64))
+      .collect::<Vec<_>>();
+
+    let observation = Observation::from_readings::<KBot>(
+      &ImuValuesResponse::default(),
+      &states,
+      [0.0; 3],
+    )
+    .unwrap();
+
+    let positions = ids.iter().map(|&id| id as f64).collect::<Vec<_>>();
+    assert_eq!(observation.dof_pos, positions);
+    assert_eq!(
+      observation.dof_vel,
+      positions
+        .iter()
+        .map(|position| -position)
+        .collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn observations_need_every_policy_actuator() {
+    let ids = policy_actuator_ids::<KBot>().unwrap();
+    let mut states = ids.iter().map(|&id| state(id, 0.0)).collect::<Vec<_>>();
+    let imu = ImuValuesResponse::default();
+
+    states[3].velocity = None;
+    assert!(
+      Observation::from_readings::<KBot>(&imu, &states, [0.0; 3]).is_err()
+    );
+
+    states.remove(3);
+    let error =
+      Observation::from_readings::<KBot>(&imu, &states, [0.0; 3]).unwrap_err();
+    assert!(error.to_string().contains(&ids[3].to_string()), "{error}");
+  }
+}
@@ -0,0 +1,488 @@
+use std::{collections::BTreeMap, fmt, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  arbiter::Lease, collision::CollisionAction, description::RobotDescription,
+  proto::actuator::ActuatorStateResponse, Axis, Joint, JointCommand, KBot,
+  Robot,
+};
+
+/// Every axis a joint may be addressed by; `None` is a single-axis joint
+/// such as a gripper.
+const AXES: [Option<Axis>; 4] =
+  [None, Some(Axis::Pitch), Some(Axis::Yaw), Some(Axis::Roll)];
+
+/// Positions closer than this, in degrees, are considered equal by
+/// [`Pose::diff`].
+const POSITION_EPSILON: f64 = 1e-6;
+
+/// Allowed position range of `joint` in degrees, taken from `description`
+/// when it has one and from [`Robot::joint_limits`] otherwise.
+pub fn joint_limits<R: Robot>(
+  joint: JointAxis,
+  description: Option<&RobotDescription>,
+) -> Option<[f64; 2]> {
+  description
+    .and_then(|description| description.limits(joint))
+    .or_else(|| {
+      R::joint_limits(joint.joint, joint.axis).map(|(min, max)| [min, max])
+    })
+}
+
+/// Factor that maps a position on `joint` to the same motion on
+/// [`JointAxis::mirror`], taken from the joint axes in `description` when it
+/// has both sides and from [`Robot::mirror_sign`] otherwise.
+pub fn mirror_sign<R: Robot>(
+  joint: JointAxis,
+  description: Option<&RobotDescription>,
+) -> f64 {
+  description
+    .and_then(|description| description.mirror_sign(joint))
+    .unwrap_or_else(|| R::mirror_sign(joint.joint, joint.axis))
+}
+
+/// A named set of joints that can be addressed together.
+#[derive(
+  Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum JointGroup {
+  LeftArm,
+  RightArm,
+  LeftLeg,
+  RightLeg,
+  Arms,
+  Legs,
+  WholeBody,
+}
+
+impl JointGroup {
+  pub fn joints(self) -> &'static [Joint] {
+    use Joint::*;
+
+    match self {
+      JointGroup::LeftArm => &[LeftShoulder, LeftElbow, LeftGripper],
+      JointGroup::RightArm => &[RightShoulder, RightElbow, RightGripper],
+      JointGroup::LeftLeg => &[LeftHip, LeftKnee, LeftAnkle],
+      JointGroup::RightLeg => &[RightHip, RightKnee, RightAnkle],
+      JointGroup::Arms => &[
+        LeftShoulder,
+        LeftElbow,
+        LeftGripper,
+        RightShoulder,
+        RightElbow,
+        RightGripper,
+      ],
+      JointGroup::Legs => &[
+        LeftHip, LeftKnee, LeftAnkle, RightHip, RightKnee, RightAnkle,
+      ],
+      JointGroup::WholeBody => &Joint::ALL,
+    }
+  }
+
+  pub fn contains(self, joint: Joint) -> bool {
+    self.joints().contains(&joint)
+  }
+
+  /// Every joint axis in the group that `R` has an actuator for.
+  pub fn axes<R: Robot>(self) -> Vec<JointAxis> {
+    self
+      .joints()
+      .iter()
+      .flat_map(|&joint| AXES.map(|axis| JointAxis::new(joint, axis)))
+      .filter(|joint| joint.actuator_id::<R>().is_some())
+      .collect()
+  }
+
+  /// The same group on the other side of the body.
+  pub fn mirror(self) -> JointGroup {
+    match self {
+      JointGroup::LeftArm => JointGroup::RightArm,
+      JointGroup::RightArm => JointGroup::LeftArm,
+      JointGroup::LeftLeg => JointGroup::RightLeg,
+      JointGroup::RightLeg => JointGroup::LeftLeg,
+      group => group,
+    }
+  }
+
+  pub fn actuators<R: Robot>(self) -> Vec<u32> {
+    self
+      .axes::<R>()
+      .into_iter()
+      .filter_map(|joint| joint.actuator_id::<R>())
+      .collect()
+  }
+}
+
+/// One axis of a joint, written as e.g. `left_shoulder_pitch` or
+/// `left_gripper`.
+#[derive(
+  Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct JointAxis {
+  pub joint: Joint,
+  pub axis: Option<Axis>,
+}
+
+impl JointAxis {
+  pub fn new(joint: Joint, axis: Option<Axis>) -> Self {
+    Self { joint, axis }
+  }
+
+  pub fn actuator_id<R: Robot>(self) -> Option<u32> {
+    R::get_actuator_id(self.joint, self.axis)
+  }
+
+  /// The same axis on the other side of the body.
+  pub fn mirror(self) -> JointAxis {
+    JointAxis::new(self.joint.mirror(), self.axis)
+  }
+
+  pub fn from_actuator_id<R: Robot>(actuator_id: u32) -> Option<Self> {
+    Joint::ALL
+      .iter()
+      .flat_map(|&joint| AXES.map(|axis| JointAxis::new(joint, axis)))
+      .find(|joint| joint.actuator_id::<R>() == Some(actuator_id))
+  }
+}
+
+impl fmt::Display for JointAxis {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let joint = match self.joint {
+      Joint::LeftShoulder => "left_shoulder",
+      Joint::LeftElbow => "left_elbow",
+      Joint::LeftGripper => "left_gripper",
+      Joint::RightShoulder => "right_shoulder",
+      Joint::RightElbow => "right_elbow",
+      Joint::RightGripper => "right_gripper",
+      Joint::LeftHip => "left_hip",
+      Joint::LeftKnee => "left_knee",
+      Joint::LeftAnkle => "left_ankle",
+      Joint::RightHip => "right_hip",
+      Joint::RightKnee => "right_knee",
+      Joint::RightAnkle => "right_ankle",
+    };
+
+    match self.axis {
+      None => write!(f, "{joint}"),
+      Some(Axis::Pitch) => write!(f, "{joint}_pitch"),
+      Some(Axis::Yaw) => write!(f, "{joint}_yaw"),
+      Some(Axis::Roll) => write!(f, "{joint}_roll"),
+    }
+  }
+}
+
+impl FromStr for JointAxis {
+  type Err = eyre::Report;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Joint::ALL
+      .iter()
+      .flat_map(|&joint| AXES.map(|axis| JointAxis::new(joint, axis)))
+      .find(|joint| joint.to_string() == s)
+      .ok_or_else(|| eyre::eyre!("Unknown joint {s}"))
+  }
+}
+
+impl TryFrom<String> for JointAxis {
+  type Error = eyre::Report;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    s.parse()
+  }
+}
+
+impl From<JointAxis> for String {
+  fn from(joint: JointAxis) -> Self {
+    joint.to_string()
+  }
+}
+
+/// Target positions, in degrees, for some or all of the robot's joints.
+/// Joints that are not set are left alone.
+///
+/// ```json
+/// { "left_shoulder_pitch": 90.0, "left_elbow_yaw": -45.0 }
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Pose(BTreeMap<JointAxis, f64>);
+
+impl Pose {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with(
+    mut self,
+    joint: Joint,
+    axis: Option<Axis>,
+    position: f64,
+  ) -> Self {
+    self.set(joint, axis, position);
+    self
+  }
+
+  pub fn set(&mut self, joint: Joint, axis: Option<Axis>, position: f64) {
+    self.0.insert(JointAxis::new(joint, axis), position);
+  }
+
+  pub fn get(&self, joint: Joint, axis: Option<Axis>) -> Option<f64> {
+    self.0.get(&JointAxis::new(joint, axis)).copied()
+  }
+
+  pub fn contains(&self, joint: JointAxis) -> bool {
+    self.0.contains_key(&joint)
+  }
+
+  pub fn remove(&mut self, joint: Joint, axis: Option<Axis>) -> Option<f64> {
+    self.0.remove(&JointAxis::new(joint, axis))
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (JointAxis, f64)> + '_ {
+    self.0.iter().map(|(&joint, &position)| (joint, position))
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Layers `other` on top of `self`; joints set in both take `other`'s
+  /// position.
+  pub fn merge(&self, other: &Pose) -> Pose {
+    let mut merged = self.clone();
+    merged.0.extend(other.iter());
+    merged
+  }
+
+  /// The joints of `other` that differ from `self`, with their positions in
+  /// `other`. Joints missing from `self` always count as different.
+  pub fn diff(&self, other: &Pose) -> Pose {
+    Pose(
+      other
+        .iter()
+        .filter(|(joint, position)| {
+          self
+            .0
+            .get(joint)
+            .is_none_or(|current| (current - position).abs() > POSITION_EPSILON)
+        })
+        .collect(),
+    )
+  }
+
+  /// The part of the pose that falls within `group`.
+  pub fn only(&self, group: JointGroup) -> Pose {
+    Pose(
+      self
+        .iter()
+        .filter(|(joint, _)| group.contains(joint.joint))
+        .collect(),
+    )
+  }
+
+  /// Swaps left and right, flipping each position by [`mirror_sign`].
+  /// Joints without an actuator on the other side are left out.
+  pub fn mirror<R: Robot>(
+    &self,
+    description: Option<&RobotDescription>,
+  ) -> Pose {
+    self
+      .iter()
+      .filter(|(joint, _)| joint.mirror().actuator_id::<R>().is_some())
+      .map(|(joint, position)| {
+        (
+          joint.mirror(),
+          position * mirror_sign::<R>(joint, description),
+        )
+      })
+      .collect()
+  }
+
+  /// Fills in the other side of a one-sided pose by mirroring it. Joints
+  /// already set on both sides are kept as they are.
+  pub fn symmetric<R: Robot>(
+    &self,
+    description: Option<&RobotDescription>,
+  ) -> Pose {
+    self.mirror::<R>(description).merge(self)
+  }
+
+  /// Checks that every joint exists on `R` and is within its limits, see
+  /// [`joint_limits`].
+  pub fn validate<R: Robot>(
+    &self,
+    description: Option<&RobotDescription>,
+  ) -> eyre::Result<()> {
+    for (joint, position) in self.iter() {
+      if joint.actuator_id::<R>().is_none() {
+        return Err(eyre::eyre!("{joint} has no actuator"));
+      }
+
+      if !position.is_finite() {
+        return Err(eyre::eyre!("{joint} target {position} is not finite"));
+      }
+
+      if let Some([min, max]) = joint_limits::<R>(joint, description) {
+        if !(min..=max).contains(&position) {
+          return Err(eyre::eyre!(
+            "{joint} target {position}° is outside [{min}°, {max}°]"
+          ));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn actuators<R: Robot>(&self) -> Vec<u32> {
+    self
+      .iter()
+      .filter_map(|(joint, _)| joint.actuator_id::<R>())
+      .collect()
+  }
+
+  /// The positions reported in `states`, skipping actuators that did not
+  /// report one.
+  pub fn from_states<R: Robot>(states: &[ActuatorStateResponse]) -> Pose {
+    states
+      .iter()
+      .filter_map(|state| {
+        Some((
+          JointAxis::from_actuator_id::<R>(state.actuator_id)?,
+          state.position?,
+        ))
+      })
+      .collect()
+  }
+
+  pub fn into_commands(self) -> Vec<(Joint, Option<Axis>, JointCommand)> {
+    self
+      .iter()
+      .map(|(joint, position)| {
+        (
+          joint.joint,
+          joint.axis,
+          JointCommand {
+            position: Some(position),
+            velocity: None,
+            torque: None,
+          },
+        )
+      })
+      .collect()
+  }
+}
+
+impl FromIterator<(JointAxis, f64)> for Pose {
+  fn from_iter<T: IntoIterator<Item = (JointAxis, f64)>>(iter: T) -> Self {
+    Self(iter.into_iter().collect())
+  }
+}
+
+impl KBot {
+  /// Reads the current positions of the joints in `group`.
+  pub async fn read_pose(&self, group: JointGroup) -> eyre::Result<Pose> {
+    let states = self
+      .hal
+      .actuators
+      .get_actuators_state(group.actuators::<Self>())
+      .await?;
+
+    Ok(Pose::from_states::<Self>(&states))
+  }
+
+  /// Validates `pose`, checks the straight-line motion there for
+  /// self-collisions and commands every joint in it on behalf of `lease`.
+  /// The motion is sent as the checked waypoints, one
+  /// [`step_ms`](crate::collision::CollisionConfig::step_ms) apart, so the
+  /// joints follow the path that was checked. Without a robot description
+  /// there is no collision model, so the pose is commanded directly and
+  /// unchecked.
+  pub async fn move_to(&self, lease: &Lease, pose: Pose) -> eyre::Result<()> {
+    pose.validate::<Self>(self.description.as_ref())?;
+
+    let Some(collisions) = &self.collisions else {
+      return self.command_joints(lease, pose.into_commands()).await;
+    };
+
+    let current = self.read_pose(JointGroup::WholeBody).await?;
+    let motion = collisions.check_motion(&current, &current.merge(&pose));
+
+    if let Some(collision) = &motion.collision {
+      let progress = motion.safe_fraction * 100.0;
+      if collisions.config().action == CollisionAction::Reject
+        || motion.safe_fraction <= 0.0
+      {
+        return Err(eyre::eyre!(
+          "{:?} would hit {:?} {progress:.0}% of the way there",
+          collision.a,
+          collision.b
+        ));
+      }
+
+      tracing::warn!(
+        "Stopping {progress:.0}% of the way to avoid {:?} hitting {:?}",
+        collision.a,
+        collision.b
+      );
+    }
+
+    let mut interval =
+      tokio::time::interval(Duration::from_millis(collisions.config().step_ms));
+    // The first waypoint is where the joints already are.
+    for waypoint in motion.waypoints.iter().skip(1) {
+      interval.tick().await;
+
+      let waypoint = waypoint
+        .iter()
+        .filter(|(joint, _)| pose.contains(*joint))
+        .collect::<Pose>();
+      self.command_joints(lease, waypoint.into_commands()).await?;
+    }
+
+    Ok(())
+  }
+
+  /// Commands the joints in `group` to stay where they currently are.
+  pub async fn hold(
+    &self,
+    lease: &Lease,
+    group: JointGroup,
+  ) -> eyre::Result<Pose> {
+    let pose = self.read_pose(group).await?;
+    self.move_to(lease, pose.clone()).await?;
+
+    Ok(pose)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn validate_checks_kbot_limits() {
+    let pose = Pose::new().with(Joint::LeftAnkle, Some(Axis::Pitch), 20.0);
+    assert!(pose.validate::<KBot>(None).is_ok());
+
+    let pose = Pose::new().with(Joint::LeftAnkle, Some(Axis::Pitch), 90.0);
+    assert!(pose.validate::<KBot>(None).is_err());
+  }
+
+  #[test]
+  fn mirrored_poses_stay_within_limits() {
+    let pose = Pose::new()
+      .with(Joint::RightShoulder, Some(Axis::Pitch), 90.0)
+      .with(Joint::RightShoulder, Some(Axis::Yaw), 90.0)
+      .with(Joint::RightElbow, Some(Axis::Yaw), 180.0);
+
+    assert!(pose.symmetric::<KBot>(None).validate::<KBot>(None).is_ok());
+  }
+}
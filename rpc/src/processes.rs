@@ -0,0 +1,280 @@
+use std::{
+  collections::BTreeMap,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{net::TcpStream, sync::Mutex};
+
+use crate::{
+  inference::check, proto::process_manager::KClipStartRequest, Client,
+};
+
+/// Port the robot's WebRTC video streamer listens on.
+pub const VIDEO_STREAM_PORT: u16 = 8083;
+
+/// The robot's camera is served by an RTSPtoWeb instance on
+/// [`VIDEO_STREAM_PORT`], which restreams the camera's RTSP feed over WebRTC.
+/// Starting the stream registers the camera with it and stopping removes it.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct VideoStreamConfig {
+  /// Stream ID the web UI plays, channel 0 of which is the camera.
+  pub stream_id: String,
+  /// RTSP URL of the camera. Streams can only be stopped without one.
+  pub source_url: Option<String>,
+  /// Basic auth credentials of the streamer's HTTP API.
+  pub username: String,
+  pub password: String,
+}
+
+impl Default for VideoStreamConfig {
+  fn default() -> Self {
+    Self {
+      stream_id: "s1".to_string(),
+      source_url: None,
+      // RTSPtoWeb's defaults.
+      username: "demo".to_string(),
+      password: "demo".to_string(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipStatus {
+  pub clip_uuid: Option<String>,
+  pub action: Option<String>,
+  /// Seconds since the Unix epoch.
+  pub started_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoStreamStatus {
+  pub port: u16,
+  pub stream_id: String,
+  pub reachable: bool,
+  /// Whether the streamer has the camera stream registered.
+  pub running: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessStatus {
+  /// The KClip recording started through this client, if any.
+  pub kclip: Option<ClipStatus>,
+  pub video_stream: VideoStreamStatus,
+}
+
+/// Typed wrapper around the KOS process manager.
+///
+/// KOS has no way to query running processes, so the state of anything
+/// started here is tracked locally.
+#[derive(Debug)]
+pub struct ProcessManager {
+  client: Option<Client>,
+  video: VideoStreamConfig,
+  http: reqwest::Client,
+  kclip: Mutex<Option<ClipStatus>>,
+}
+
+/// Reply of the RTSPtoWeb HTTP API, `status` being `1` on success.
+#[derive(Deserialize, Debug)]
+struct StreamerResponse {
+  status: i32,
+  payload: serde_json::Value,
+}
+
+impl ProcessManager {
+  pub fn new(client: Option<Client>, video: VideoStreamConfig) -> Self {
+    Self {
+      client,
+      video,
+      http: reqwest::Client::new(),
+      kclip: Mutex::new(None),
+    }
+  }
+
+  /// Starts a KClip recording, returning its UUID.
+  #[tracing::instrument(level = "debug", skip(self))]
+  pub async fn start_kclip(
+    &self,
+    action: Option<String>,
+  ) -> eyre::Result<Option<String>> {
+    let mut kclip = self.kclip.lock().await;
+    if let Some(clip) = kclip.as_ref() {
+      return Err(eyre::eyre!("KClip {:?} is already running", clip.clip_uuid));
+    }
+
+    let client = self.client()?;
+    let response = client
+      .measure("processes", "start_k_clip", async {
+        Ok(
+          client
+            .processes
+            .lock()
+            .await
+            .start_k_clip(KClipStartRequest {
+              action: action.clone(),
+            })
+            .await?
+            .into_inner(),
+        )
+      })
+      .await?;
+
+    check(response.error)?;
+
+    *kclip = Some(ClipStatus {
+      clip_uuid: response.clip_uuid.clone(),
+      action,
+      started_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs(),
+    });
+
+    Ok(response.clip_uuid)
+  }
+
+  /// Stops the running KClip recording, returning its UUID.
+  #[tracing::instrument(level = "debug", skip(self))]
+  pub async fn stop_kclip(&self) -> eyre::Result<Option<String>> {
+    let mut kclip = self.kclip.lock().await;
+
+    let client = self.client()?;
+    let response = client
+      .measure("processes", "stop_k_clip", async {
+        Ok(
+          client
+            .processes
+            .lock()
+            .await
+            .stop_k_clip(())
+            .await?
+            .into_inner(),
+        )
+      })
+      .await?;
+
+    check(response.error)?;
+
+    kclip.take();
+
+    Ok(response.clip_uuid)
+  }
+
+  /// Starts streaming the camera to the web UI.
+  pub async fn start_video_stream(&self) -> eyre::Result<()> {
+    let Some(source_url) = &self.video.source_url else {
+      return Err(eyre::eyre!("No camera source URL is configured"));
+    };
+
+    let mut channels = BTreeMap::new();
+    channels.insert("0", json!({ "url": source_url, "on_demand": false }));
+
+    self
+      .streamer(reqwest::Method::POST, "add")?
+      .json(&json!({ "name": self.video.stream_id, "channels": channels }))
+      .send()
+      .await?
+      .json::<StreamerResponse>()
+      .await?
+      .check()
+  }
+
+  /// Stops the camera stream started by [`Self::start_video_stream`].
+  pub async fn stop_video_stream(&self) -> eyre::Result<()> {
+    self
+      .streamer(reqwest::Method::GET, "delete")?
+      .send()
+      .await?
+      .json::<StreamerResponse>()
+      .await?
+      .check()
+  }
+
+  pub async fn status(&self) -> ProcessStatus {
+    let host = self.client.as_ref().and_then(|client| client.uri.host());
+    let (reachable, running) = match host {
+      Some(host) if probe(host, VIDEO_STREAM_PORT).await => {
+        (true, self.video_stream_running().await)
+      }
+      _ => (false, false),
+    };
+
+    ProcessStatus {
+      kclip: self.kclip.lock().await.clone(),
+      video_stream: VideoStreamStatus {
+        port: VIDEO_STREAM_PORT,
+        stream_id: self.video.stream_id.clone(),
+        reachable,
+        running,
+      },
+    }
+  }
+
+  async fn video_stream_running(&self) -> bool {
+    let Ok(request) = self.streamer(reqwest::Method::GET, "info") else {
+      return false;
+    };
+
+    match request.send().await {
+      Ok(response) => response
+        .json::<StreamerResponse>()
+        .await
+        .is_ok_and(|response| response.status == 1),
+      Err(_) => false,
+    }
+  }
+
+  fn client(&self) -> eyre::Result<&Client> {
+    self
+      .client
+      .as_ref()
+      .ok_or_else(|| eyre::eyre!("Not connected to KOS"))
+  }
+
+  /// Request to `/stream/{stream_id}/{action}` of the streamer's API.
+  fn streamer(
+    &self,
+    method: reqwest::Method,
+    action: &str,
+  ) -> eyre::Result<reqwest::RequestBuilder> {
+    let host = self
+      .client()?
+      .uri
+      .host()
+      .ok_or_else(|| eyre::eyre!("KOS URI has no host"))?;
+    let url = format!(
+      "http://{host}:{VIDEO_STREAM_PORT}/stream/{}/{action}",
+      self.video.stream_id
+    );
+
+    Ok(
+      self
+        .http
+        .request(method, url)
+        .basic_auth(&self.video.username, Some(&self.video.password))
+        .timeout(Duration::from_secs(5)),
+    )
+  }
+}
+
+impl StreamerResponse {
+  fn check(self) -> eyre::Result<()> {
+    if self.status != 1 {
+      return Err(eyre::eyre!("Video streamer error: {}", self.payload));
+    }
+
+    Ok(())
+  }
+}
+
+/// Returns whether a TCP connection to `host:port` can be opened.
+async fn probe(host: &str, port: u16) -> bool {
+  let connect = TcpStream::connect((host, port));
+
+  matches!(
+    tokio::time::timeout(Duration::from_millis(500), connect).await,
+    Ok(Ok(_))
+  )
+}
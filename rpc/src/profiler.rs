@@ -0,0 +1,385 @@
+use std::{
+  collections::BTreeMap,
+  future::Future,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::metrics::Metrics;
+
+/// Histogram buckets per doubling of duration, bounding the error of a
+/// reported percentile to about 9%.
+const BUCKETS_PER_OCTAVE: f64 = 8.0;
+
+/// Buckets from 1 µs up to about 2 minutes.
+const BUCKETS: usize = 27 * BUCKETS_PER_OCTAVE as usize;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ProfilerConfig {
+  /// Time budget of one tick keyed by loop name, e.g. `"walk"`. Ticks of
+  /// loops without one are never counted as missing their deadline.
+  pub deadlines_ms: BTreeMap<String, f64>,
+  /// How often the timings of each running loop are summarized in the logs.
+  pub log_interval_ms: u64,
+}
+
+impl Default for ProfilerConfig {
+  fn default() -> Self {
+    Self {
+      // The walking policy is trained at 50 Hz.
+      deadlines_ms: BTreeMap::from([("walk".to_string(), 20.0)]),
+      log_interval_ms: 10_000,
+    }
+  }
+}
+
+/// Log-scale histogram of durations.
+#[derive(Debug, Clone)]
+struct Histogram {
+  counts: Vec<u64>,
+  count: u64,
+  sum: Duration,
+  max: Duration,
+}
+
+impl Default for Histogram {
+  fn default() -> Self {
+    Self {
+      counts: vec![0; BUCKETS],
+      count: 0,
+      sum: Duration::ZERO,
+      max: Duration::ZERO,
+    }
+  }
+}
+
+impl Histogram {
+  fn record(&mut self, duration: Duration) {
+    let micros = duration.as_secs_f64() * 1e6;
+    let bucket = (micros.max(1.0).log2() * BUCKETS_PER_OCTAVE) as usize;
+
+    self.counts[bucket.min(BUCKETS - 1)] += 1;
+    self.count += 1;
+    self.sum += duration;
+    self.max = self.max.max(duration);
+  }
+
+  /// Upper bound of the bucket holding the `quantile`, e.g. `0.99`. The last
+  /// bucket has no upper bound, so the maximum is reported for it.
+  fn percentile(&self, quantile: f64) -> Duration {
+    let rank = (quantile * self.count as f64).ceil().max(1.0) as u64;
+
+    let mut seen = 0;
+    for (bucket, count) in self.counts.iter().enumerate() {
+      seen += count;
+      if seen >= rank && bucket < BUCKETS - 1 {
+        let micros = ((bucket + 1) as f64 / BUCKETS_PER_OCTAVE).exp2();
+        return Duration::from_secs_f64(micros / 1e6).min(self.max);
+      }
+    }
+
+    self.max
+  }
+
+  fn report(&self, name: &str) -> StageReport {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+    StageReport {
+      name: name.to_string(),
+      count: self.count,
+      mean_ms: ms(self.sum) / self.count.max(1) as f64,
+      p50_ms: ms(self.percentile(0.5)),
+      p99_ms: ms(self.percentile(0.99)),
+      max_ms: ms(self.max),
+    }
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StageReport {
+  pub name: String,
+  pub count: u64,
+  pub mean_ms: f64,
+  pub p50_ms: f64,
+  pub p99_ms: f64,
+  pub max_ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LoopReport {
+  pub name: String,
+  pub deadline_ms: Option<f64>,
+  pub deadline_misses: u64,
+  /// Whole ticks, from the start of the first stage to the end of the last.
+  pub tick: StageReport,
+  /// In the order the loop first ran them.
+  pub stages: Vec<StageReport>,
+}
+
+#[derive(Debug)]
+struct LoopProfile {
+  tick: Histogram,
+  stages: Vec<(&'static str, Histogram)>,
+  deadline_misses: u64,
+  last_logged: Instant,
+}
+
+impl LoopProfile {
+  fn new() -> Self {
+    Self {
+      tick: Histogram::default(),
+      stages: Vec::new(),
+      deadline_misses: 0,
+      last_logged: Instant::now(),
+    }
+  }
+
+  fn report(&self, name: &str, deadline_ms: Option<f64>) -> LoopReport {
+    LoopReport {
+      name: name.to_string(),
+      deadline_ms,
+      deadline_misses: self.deadline_misses,
+      tick: self.tick.report("tick"),
+      stages: self
+        .stages
+        .iter()
+        .map(|(stage, histogram)| histogram.report(stage))
+        .collect(),
+    }
+  }
+}
+
+/// Times the stages of control loop ticks, e.g. the sensor reads, inference
+/// and commands of the walk loop, and counts ticks that overrun their
+/// deadline.
+#[derive(Debug)]
+pub struct Profiler {
+  config: ProfilerConfig,
+  metrics: Arc<Metrics>,
+  loops: Mutex<BTreeMap<String, LoopProfile>>,
+}
+
+impl Profiler {
+  pub fn new(config: ProfilerConfig, metrics: Arc<Metrics>) -> Self {
+    Self {
+      config,
+      metrics,
+      loops: Mutex::new(BTreeMap::new()),
+    }
+  }
+
+  /// Starts timing one tick of the loop `name`. The tick is recorded when
+  /// the returned timer is dropped, so ticks that bail out early count too.
+  pub fn tick(&self, name: &'static str) -> TickTimer<'_> {
+    TickTimer {
+      profiler: self,
+      name,
+      start: Instant::now(),
+      stages: Vec::new(),
+    }
+  }
+
+  pub fn report(&self) -> Vec<LoopReport> {
+    self
+      .loops
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|(name, profile)| profile.report(name, self.deadline_ms(name)))
+      .collect()
+  }
+
+  /// Forgets everything recorded so far, e.g. before a benchmark run.
+  pub fn reset(&self) {
+    self.loops.lock().unwrap().clear();
+  }
+
+  /// Logs a summary of the loop `name`, e.g. when it stops.
+  pub fn log(&self, name: &str) {
+    let report = self
+      .loops
+      .lock()
+      .unwrap()
+      .get(name)
+      .map(|profile| profile.report(name, self.deadline_ms(name)));
+
+    if let Some(report) = report {
+      log_report(&report);
+    }
+  }
+
+  fn deadline_ms(&self, name: &str) -> Option<f64> {
+    self.config.deadlines_ms.get(name).copied()
+  }
+
+  fn record(
+    &self,
+    name: &'static str,
+    tick: Duration,
+    stages: &[(&'static str, Duration)],
+  ) {
+    let deadline_ms = self.deadline_ms(name);
+    let missed = deadline_ms
+      .is_some_and(|deadline| tick.as_secs_f64() * 1000.0 > deadline);
+
+    for (stage, duration) in stages {
+      self.metrics.loop_stage(name, stage, *duration);
+    }
+    self.metrics.loop_stage(name, "tick", tick);
+    if missed {
+      self.metrics.deadline_miss(name);
+      tracing::debug!(
+        loop_name = name,
+        tick_ms = tick.as_secs_f64() * 1000.0,
+        ?stages,
+        "Tick missed its deadline"
+      );
+    }
+
+    let report = {
+      let mut loops = self.loops.lock().unwrap();
+      let profile = loops
+        .entry(name.to_string())
+        .or_insert_with(LoopProfile::new);
+
+      profile.tick.record(tick);
+      for (stage, duration) in stages {
+        match profile.stages.iter_mut().find(|(name, _)| name == stage) {
+          Some((_, histogram)) => histogram.record(*duration),
+          None => {
+            let mut histogram = Histogram::default();
+            histogram.record(*duration);
+            profile.stages.push((stage, histogram));
+          }
+        }
+      }
+      if missed {
+        profile.deadline_misses += 1;
+      }
+
+      let interval = Duration::from_millis(self.config.log_interval_ms);
+      (profile.last_logged.elapsed() >= interval).then(|| {
+        profile.last_logged = Instant::now();
+        profile.report(name, deadline_ms)
+      })
+    };
+
+    if let Some(report) = report {
+      log_report(&report);
+    }
+  }
+}
+
+fn log_report(report: &LoopReport) {
+  let stages = report
+    .stages
+    .iter()
+    .map(|stage| {
+      format!(
+        "{} p50 {:.2} ms p99 {:.2} ms",
+        stage.name, stage.p50_ms, stage.p99_ms
+      )
+    })
+    .collect::<Vec<_>>()
+    .join(", ");
+
+  tracing::info!(
+    loop_name = report.name,
+    ticks = report.tick.count,
+    p50_ms = report.tick.p50_ms,
+    p99_ms = report.tick.p99_ms,
+    max_ms = report.tick.max_ms,
+    deadline_misses = report.deadline_misses,
+    stages,
+    "Loop timing"
+  );
+}
+
+/// Timings of one tick, see [`Profiler::tick`].
+#[derive(Debug)]
+pub struct TickTimer<'a> {
+  profiler: &'a Profiler,
+  name: &'static str,
+  start: Instant,
+  stages: Vec<(&'static str, Duration)>,
+}
+
+impl TickTimer<'_> {
+  /// Runs `stage` to completion and records how long it took.
+  pub async fn time<T>(
+    &mut self,
+    stage: &'static str,
+    future: impl Future<Output = T>,
+  ) -> T {
+    let start = Instant::now();
+    let output = future.await;
+    self.stages.push((stage, start.elapsed()));
+
+    output
+  }
+
+  /// Time spent in `stage` so far this tick.
+  pub fn stage(&self, stage: &str) -> Duration {
+    self
+      .stages
+      .iter()
+      .filter(|(name, _)| *name == stage)
+      .map(|(_, duration)| *duration)
+      .sum()
+  }
+}
+
+impl Drop for TickTimer<'_> {
+  fn drop(&mut self) {
+    self
+      .profiler
+      .record(self.name, self.start.elapsed(), &self.stages);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn percentiles_are_bucket_upper_bounds() {
+    let mut histogram = Histogram::default();
+    assert_eq!(histogram.percentile(0.5), Duration::ZERO);
+
+    for ms in 1..=100 {
+      histogram.record(Duration::from_millis(ms));
+    }
+
+    // At most one bucket, 2^(1/8) or about 9%, above the exact value.
+    let bound = |ms: f64| {
+      Duration::from_secs_f64(ms / 1000.0)
+        ..=Duration::from_secs_f64(ms / 1000.0 * 2f64.powf(0.125))
+    };
+    assert!(bound(50.0).contains(&histogram.percentile(0.5)));
+    assert!(bound(90.0).contains(&histogram.percentile(0.9)));
+    // Never above the largest recorded duration.
+    assert_eq!(histogram.percentile(0.99), Duration::from_millis(100));
+    assert_eq!(histogram.percentile(1.0), Duration::from_millis(100));
+    assert!(bound(1.0).contains(&histogram.percentile(0.0)));
+
+    let report = histogram.report("tick");
+    assert_eq!(report.count, 100);
+    assert!((report.mean_ms - 50.5).abs() < 1e-9);
+    assert_eq!(report.max_ms, 100.0);
+  }
+
+  #[test]
+  fn durations_outside_the_buckets_are_kept() {
+    let mut histogram = Histogram::default();
+    histogram.record(Duration::from_nanos(10));
+    assert_eq!(histogram.counts[0], 1);
+    assert_eq!(histogram.percentile(0.5), Duration::from_nanos(10));
+
+    let mut histogram = Histogram::default();
+    histogram.record(Duration::from_secs(600));
+    assert_eq!(histogram.counts[BUCKETS - 1], 1);
+    assert_eq!(histogram.percentile(0.5), Duration::from_secs(600));
+  }
+}
@@ -0,0 +1,776 @@
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::BufWriter,
+  path::PathBuf,
+  sync::{Arc, Mutex},
+  time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+  sync::{
+    broadcast,
+    mpsc::{self, error::TrySendError},
+  },
+  task::JoinHandle,
+};
+
+use crate::{
+  hal::{ActuatorBackend, Hal, ImuBackend},
+  mcap::McapWriter,
+  proto::{
+    actuator::{
+      ActuatorCommand, ActuatorStateResponse, CalibrateActuatorRequest,
+      ConfigureActuatorRequest,
+    },
+    common::{ActionResponse, ActionResult},
+    imu::{EulerAnglesResponse, ImuValuesResponse, QuaternionResponse},
+  },
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RecorderConfig {
+  /// Where recordings are written.
+  pub directory: PathBuf,
+  /// Messages that may wait for the file writer. Once it falls this far
+  /// behind, new messages are dropped and counted rather than buffered.
+  pub queue: usize,
+}
+
+impl Default for RecorderConfig {
+  fn default() -> Self {
+    Self {
+      directory: "recordings".into(),
+      queue: 4096,
+    }
+  }
+}
+
+/// A channel in a recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+  /// [`CommandsMessage`]
+  Commands,
+  /// [`StatesMessage`]
+  ActuatorStates,
+  /// [`ImuSample`]
+  Imu,
+  /// [`Orientation`]
+  Orientation,
+  /// [`crate::policy::Observation`]
+  PolicyObservation,
+  /// [`crate::policy::PolicyActions`]
+  PolicyActions,
+  /// [`LoopTiming`]
+  LoopTiming,
+}
+
+impl Topic {
+  pub const ALL: [Topic; 7] = [
+    Topic::Commands,
+    Topic::ActuatorStates,
+    Topic::Imu,
+    Topic::Orientation,
+    Topic::PolicyObservation,
+    Topic::PolicyActions,
+    Topic::LoopTiming,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Topic::Commands => "/actuators/commands",
+      Topic::ActuatorStates => "/actuators/states",
+      Topic::Imu => "/imu",
+      Topic::Orientation => "/imu/orientation",
+      Topic::PolicyObservation => "/policy/observation",
+      Topic::PolicyActions => "/policy/actions",
+      Topic::LoopTiming => "/walk/timing",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Topic> {
+    Topic::ALL.into_iter().find(|topic| topic.name() == name)
+  }
+
+  /// Schema name and JSON schema of the messages on this topic.
+  pub fn schema(self) -> (&'static str, Value) {
+    let number = json!({ "type": "number" });
+    let optional = json!({ "type": ["number", "null"] });
+    let vector = json!({ "type": "array", "items": number, "minItems": 3 });
+    let numbers = json!({ "type": "array", "items": number });
+
+    match self {
+      Topic::Commands => (
+        "kbot.Commands",
+        json!({
+          "type": "object",
+          "properties": {
+            "commands": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "actuator_id": { "type": "integer" },
+                  "position": optional,
+                  "velocity": optional,
+                  "torque": optional,
+                },
+              },
+            },
+          },
+        }),
+      ),
+      Topic::ActuatorStates => (
+        "kbot.ActuatorStates",
+        json!({
+          "type": "object",
+          "properties": {
+            "states": {
+              "type": "array",
+              "items": {
+                "type": "object",
+                "properties": {
+                  "actuator_id": { "type": "integer" },
+                  "online": { "type": "boolean" },
+                  "position": optional,
+                  "velocity": optional,
+                  "torque": optional,
+                  "temperature": optional,
+                  "current": optional,
+                },
+              },
+            },
+          },
+        }),
+      ),
+      Topic::Imu => (
+        "kbot.Imu",
+        json!({
+          "type": "object",
+          "properties": { "accel": vector, "gyro": vector },
+        }),
+      ),
+      Topic::Orientation => (
+        "kbot.Orientation",
+        json!({
+          "type": "object",
+          "properties": { "roll": number, "pitch": number, "yaw": number },
+        }),
+      ),
+      Topic::PolicyObservation => (
+        "kbot.PolicyObservation",
+        json!({
+          "type": "object",
+          "properties": {
+            "base_ang_vel": vector,
+            "accel": vector,
+            "commands": vector,
+            "dof_pos": numbers,
+            "dof_vel": numbers,
+            "actions": numbers,
+          },
+        }),
+      ),
+      Topic::PolicyActions => (
+        "kbot.PolicyActions",
+        json!({
+          "type": "object",
+          "properties": {
+            "actions": numbers,
+            "targets": {
+              "type": "object",
+              "additionalProperties": number,
+            },
+          },
+        }),
+      ),
+      Topic::LoopTiming => (
+        "kbot.LoopTiming",
+        json!({
+          "type": "object",
+          "properties": { "period_ms": number, "inference_ms": number },
+        }),
+      ),
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RecordedCommand {
+  pub actuator_id: u32,
+  pub position: Option<f64>,
+  pub velocity: Option<f64>,
+  pub torque: Option<f64>,
+}
+
+impl From<&ActuatorCommand> for RecordedCommand {
+  fn from(command: &ActuatorCommand) -> Self {
+    Self {
+      actuator_id: command.actuator_id,
+      position: command.position,
+      velocity: command.velocity,
+      torque: command.torque,
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CommandsMessage {
+  pub commands: Vec<RecordedCommand>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RecordedState {
+  pub actuator_id: u32,
+  pub online: bool,
+  pub position: Option<f64>,
+  pub velocity: Option<f64>,
+  pub torque: Option<f64>,
+  pub temperature: Option<f64>,
+  pub current: Option<f64>,
+}
+
+impl From<&ActuatorStateResponse> for RecordedState {
+  fn from(state: &ActuatorStateResponse) -> Self {
+    Self {
+      actuator_id: state.actuator_id,
+      online: state.online,
+      position: state.position,
+      velocity: state.velocity,
+      torque: state.torque,
+      temperature: state.temperature,
+      current: state.current.map(f64::from),
+    }
+  }
+}
+
+impl From<&RecordedState> for ActuatorStateResponse {
+  fn from(state: &RecordedState) -> Self {
+    Self {
+      actuator_id: state.actuator_id,
+      online: state.online,
+      position: state.position,
+      velocity: state.velocity,
+      torque: state.torque,
+      temperature: state.temperature,
+      current: state.current.map(|current| current as f32),
+      ..Default::default()
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct StatesMessage {
+  pub states: Vec<RecordedState>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ImuSample {
+  /// Acceleration in m/s^2.
+  pub accel: [f64; 3],
+  /// Angular velocity in deg/s.
+  pub gyro: [f64; 3],
+}
+
+impl From<&ImuValuesResponse> for ImuSample {
+  fn from(values: &ImuValuesResponse) -> Self {
+    Self {
+      accel: [values.accel_x, values.accel_y, values.accel_z],
+      gyro: [values.gyro_x, values.gyro_y, values.gyro_z],
+    }
+  }
+}
+
+impl From<&ImuSample> for ImuValuesResponse {
+  fn from(sample: &ImuSample) -> Self {
+    let [accel_x, accel_y, accel_z] = sample.accel;
+    let [gyro_x, gyro_y, gyro_z] = sample.gyro;
+
+    Self {
+      accel_x,
+      accel_y,
+      accel_z,
+      gyro_x,
+      gyro_y,
+      gyro_z,
+      ..Default::default()
+    }
+  }
+}
+
+/// Orientation of the IMU in degrees.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Orientation {
+  pub roll: f64,
+  pub pitch: f64,
+  pub yaw: f64,
+}
+
+impl From<&EulerAnglesResponse> for Orientation {
+  fn from(angles: &EulerAnglesResponse) -> Self {
+    Self {
+      roll: angles.roll,
+      pitch: angles.pitch,
+      yaw: angles.yaw,
+    }
+  }
+}
+
+/// Timing of one walk loop tick.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LoopTiming {
+  /// Time since the previous tick started, in milliseconds.
+  pub period_ms: f64,
+  /// Time spent waiting for the policy, in milliseconds.
+  pub inference_ms: f64,
+}
+
+/// A message as it is recorded, handed to live subscribers.
+#[derive(Debug, Clone)]
+pub struct LiveMessage {
+  pub topic: Topic,
+  /// Nanoseconds since the Unix epoch.
+  pub log_time: u64,
+  /// JSON matching [`Topic::schema`].
+  pub data: Arc<[u8]>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RecordingStatus {
+  pub path: PathBuf,
+  /// Seconds since the Unix epoch.
+  pub started_at: u64,
+  pub messages: u64,
+  /// Messages dropped because the file writer fell behind.
+  pub dropped: u64,
+  /// Why the file writer stopped before the recording was stopped. Nothing
+  /// more is written once it is set.
+  pub error: Option<String>,
+}
+
+struct Session {
+  status: RecordingStatus,
+  messages: mpsc::Sender<(Topic, u64, Vec<u8>)>,
+  writer: JoinHandle<std::io::Result<()>>,
+  /// Set by the writer if it fails.
+  error: Arc<Mutex<Option<String>>>,
+}
+
+impl Session {
+  fn status(&self) -> RecordingStatus {
+    let mut status = self.status.clone();
+    if self.writer.is_finished() {
+      status.error = Some(
+        self
+          .error
+          .lock()
+          .unwrap()
+          .clone()
+          .unwrap_or_else(|| "Writer stopped".to_string()),
+      );
+    }
+
+    status
+  }
+}
+
+/// Writes robot I/O to MCAP files while a recording is running, and hands it
+/// to live subscribers such as the Foxglove server.
+///
+/// Messages are JSON with a JSON schema per channel, logged at nanoseconds
+/// since the Unix epoch as measured from a monotonic clock, so they are
+/// ordered even if the wall clock jumps.
+pub struct Recorder {
+  config: RecorderConfig,
+  epoch_ns: u64,
+  start: Instant,
+  session: Mutex<Option<Session>>,
+  live: broadcast::Sender<LiveMessage>,
+}
+
+impl std::fmt::Debug for Recorder {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Recorder")
+      .field("config", &self.config)
+      .finish_non_exhaustive()
+  }
+}
+
+impl Recorder {
+  pub fn new(config: RecorderConfig) -> Self {
+    Self {
+      config,
+      epoch_ns: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64,
+      start: Instant::now(),
+      session: Mutex::new(None),
+      live: broadcast::channel(256).0,
+    }
+  }
+
+  /// Nanoseconds since the Unix epoch, never going backwards.
+  pub fn now(&self) -> u64 {
+    self.epoch_ns + self.start.elapsed().as_nanos() as u64
+  }
+
+  pub fn status(&self) -> Option<RecordingStatus> {
+    let session = self.session.lock().unwrap();
+
+    session.as_ref().map(Session::status)
+  }
+
+  /// Where the recording called `name` is stored.
+  pub fn path(&self, name: &str) -> eyre::Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+      return Err(eyre::eyre!("Invalid recording name {name:?}"));
+    }
+
+    Ok(self.config.directory.join(format!("{name}.mcap")))
+  }
+
+  /// Starts writing `<directory>/<name>.mcap`, named after the current time
+  /// if no name is given.
+  pub fn start(&self, name: Option<String>) -> eyre::Result<RecordingStatus> {
+    let mut session = self.session.lock().unwrap();
+    if let Some(session) = session.as_ref() {
+      return Err(eyre::eyre!(
+        "Already recording to {}",
+        session.status.path.display()
+      ));
+    }
+
+    let started_at = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_secs();
+    let name = name.unwrap_or_else(|| format!("kbot-{started_at}"));
+    let path = self.path(&name)?;
+
+    std::fs::create_dir_all(&self.config.directory)?;
+    let file = File::create_new(&path)?;
+
+    let mut mcap = McapWriter::new(BufWriter::new(file), "kbot")?;
+    let mut channels = HashMap::new();
+    for topic in Topic::ALL {
+      let (name, schema) = topic.schema();
+      let schema_id =
+        mcap.add_schema(name, "jsonschema", &serde_json::to_vec(&schema)?)?;
+      channels
+        .insert(topic, mcap.add_channel(schema_id, topic.name(), "json")?);
+    }
+
+    let (messages, mut receiver) =
+      mpsc::channel::<(Topic, u64, Vec<u8>)>(self.config.queue.max(1));
+    let error = Arc::new(Mutex::new(None));
+    let writer = tokio::task::spawn_blocking({
+      let error = error.clone();
+      move || {
+        let result = write_messages(mcap, &channels, &mut receiver);
+
+        if let Err(e) = &result {
+          tracing::error!(error = %e, "Recording failed");
+          *error.lock().unwrap() = Some(e.to_string());
+        }
+
+        result
+      }
+    });
+
+    let status = RecordingStatus {
+      path,
+      started_at,
+      messages: 0,
+      dropped: 0,
+      error: None,
+    };
+
+    *session = Some(Session {
+      status: status.clone(),
+      messages,
+      writer,
+      error,
+    });
+
+    Ok(status)
+  }
+
+  /// Stops the running recording and waits for the file to be finished.
+  pub async fn stop(&self) -> eyre::Result<RecordingStatus> {
+    let Some(session) = self.session.lock().unwrap().take() else {
+      return Err(eyre::eyre!("Not recording"));
+    };
+
+    drop(session.messages);
+    session.writer.await??;
+
+    Ok(session.status)
+  }
+
+  /// Receives every message logged from now on, whether or not a recording
+  /// is running.
+  pub fn subscribe(&self) -> broadcast::Receiver<LiveMessage> {
+    self.live.subscribe()
+  }
+
+  /// Logs `message` on `topic` if a recording is running or anyone is
+  /// subscribed.
+  pub fn record<T: Serialize>(&self, topic: Topic, message: &T) {
+    self.log(topic, message, true);
+  }
+
+  /// Hands `message` to live subscribers only, leaving it out of a running
+  /// recording. For reads made just to feed a live view, which would
+  /// otherwise fill recordings with samples the robot never acted on.
+  pub fn publish<T: Serialize>(&self, topic: Topic, message: &T) {
+    self.log(topic, message, false);
+  }
+
+  fn log<T: Serialize>(&self, topic: Topic, message: &T, to_file: bool) {
+    let mut session = self.session.lock().unwrap();
+    let session = session.as_mut().filter(|_| to_file);
+    if session.is_none() && self.live.receiver_count() == 0 {
+      return;
+    }
+
+    let data = match serde_json::to_vec(message) {
+      Ok(data) => data,
+      Err(e) => {
+        tracing::warn!(topic = topic.name(), error = %e, "Failed to record");
+        return;
+      }
+    };
+    let log_time = self.now();
+
+    if self.live.receiver_count() > 0 {
+      let message = LiveMessage {
+        topic,
+        log_time,
+        data: data.as_slice().into(),
+      };
+      self.live.send(message).ok();
+    }
+
+    if let Some(session) = session {
+      match session.messages.try_send((topic, log_time, data)) {
+        Ok(()) => session.status.messages += 1,
+        Err(TrySendError::Full(_)) => session.status.dropped += 1,
+        // The writer failed, which `status` reports.
+        Err(TrySendError::Closed(_)) => {}
+      }
+    }
+  }
+}
+
+/// Writes messages to `mcap` until every sender is dropped, then finishes the
+/// file.
+fn write_messages(
+  mut mcap: McapWriter<BufWriter<File>>,
+  channels: &HashMap<Topic, u16>,
+  receiver: &mut mpsc::Receiver<(Topic, u64, Vec<u8>)>,
+) -> std::io::Result<()> {
+  while let Some((topic, log_time, data)) = receiver.blocking_recv() {
+    mcap.write_message(channels[&topic], log_time, &data)?;
+  }
+
+  mcap.finish()?;
+
+  Ok(())
+}
+
+impl Hal {
+  /// Logs every actuator command, actuator state, IMU sample and orientation
+  /// that passes through this HAL to `recorder`.
+  pub fn recorded(self, recorder: Arc<Recorder>) -> Hal {
+    Hal {
+      actuators: Arc::new(RecordedActuators {
+        inner: self.actuators,
+        recorder: recorder.clone(),
+      }),
+      imu: Arc::new(RecordedImu {
+        inner: self.imu,
+        recorder,
+      }),
+      ..self
+    }
+  }
+}
+
+#[derive(Debug)]
+struct RecordedActuators {
+  inner: Arc<dyn ActuatorBackend>,
+  recorder: Arc<Recorder>,
+}
+
+#[async_trait]
+impl ActuatorBackend for RecordedActuators {
+  async fn command_actuators(
+    &self,
+    commands: Vec<ActuatorCommand>,
+  ) -> eyre::Result<Vec<ActionResult>> {
+    self.recorder.record(
+      Topic::Commands,
+      &CommandsMessage {
+        commands: commands.iter().map(RecordedCommand::from).collect(),
+      },
+    );
+
+    self.inner.command_actuators(commands).await
+  }
+
+  async fn configure_actuator(
+    &self,
+    request: ConfigureActuatorRequest,
+  ) -> eyre::Result<ActionResponse> {
+    self.inner.configure_actuator(request).await
+  }
+
+  async fn calibrate_actuator(
+    &self,
+    request: CalibrateActuatorRequest,
+  ) -> eyre::Result<()> {
+    self.inner.calibrate_actuator(request).await
+  }
+
+  async fn get_actuators_state(
+    &self,
+    actuator_ids: Vec<u32>,
+  ) -> eyre::Result<Vec<ActuatorStateResponse>> {
+    let states = self.inner.get_actuators_state(actuator_ids).await?;
+
+    self.recorder.record(
+      Topic::ActuatorStates,
+      &StatesMessage {
+        states: states.iter().map(RecordedState::from).collect(),
+      },
+    );
+
+    Ok(states)
+  }
+}
+
+#[derive(Debug)]
+struct RecordedImu {
+  inner: Arc<dyn ImuBackend>,
+  recorder: Arc<Recorder>,
+}
+
+#[async_trait]
+impl ImuBackend for RecordedImu {
+  async fn get_values(&self) -> eyre::Result<ImuValuesResponse> {
+    let values = self.inner.get_values().await?;
+
+    self.recorder.record(Topic::Imu, &ImuSample::from(&values));
+
+    Ok(values)
+  }
+
+  async fn get_euler(&self) -> eyre::Result<EulerAnglesResponse> {
+    let angles = self.inner.get_euler().await?;
+
+    self
+      .recorder
+      .record(Topic::Orientation, &Orientation::from(&angles));
+
+    Ok(angles)
+  }
+
+  async fn get_quaternion(&self) -> eyre::Result<QuaternionResponse> {
+    self.inner.get_quaternion().await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mcap::read_messages;
+
+  #[tokio::test]
+  async fn recordings_read_back_through_mcap() {
+    let directory = std::env::temp_dir()
+      .join(format!("kbot-recorder-{}", std::process::id()));
+    let recorder = Recorder::new(RecorderConfig {
+      directory: directory.clone(),
+      queue: 16,
+    });
+
+    let started = recorder.start(Some("round_trip".to_string())).unwrap();
+    recorder.record(
+      Topic::Orientation,
+      &Orientation {
+        roll: 1.0,
+        pitch: 2.0,
+        yaw: 3.0,
+      },
+    );
+    recorder.record(
+      Topic::LoopTiming,
+      &LoopTiming {
+        period_ms: 20.0,
+        inference_ms: 5.0,
+      },
+    );
+    assert!(recorder.status().unwrap().error.is_none());
+
+    let stopped = recorder.stop().await.unwrap();
+    assert_eq!(stopped.messages, 2);
+    assert_eq!(stopped.dropped, 0);
+
+    let bytes = std::fs::read(&started.path).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    let messages = read_messages(&bytes).unwrap();
+
+    let topics = messages
+      .iter()
+      .map(|message| message.topic.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(topics, ["/imu/orientation", "/walk/timing"]);
+    assert!(messages[0].log_time <= messages[1].log_time);
+
+    let orientation =
+      serde_json::from_slice::<Orientation>(&messages[0].data).unwrap();
+    assert_eq!(
+      [orientation.roll, orientation.pitch, orientation.yaw],
+      [1.0, 2.0, 3.0]
+    );
+    let timing =
+      serde_json::from_slice::<LoopTiming>(&messages[1].data).unwrap();
+    assert_eq!(timing.inference_ms, 5.0);
+  }
+
+  #[tokio::test]
+  async fn published_messages_stay_out_of_recordings() {
+    let directory = std::env::temp_dir()
+      .join(format!("kbot-recorder-publish-{}", std::process::id()));
+    let recorder = Recorder::new(RecorderConfig {
+      directory: directory.clone(),
+      queue: 16,
+    });
+    let mut live = recorder.subscribe();
+
+    let started = recorder.start(Some("publish".to_string())).unwrap();
+    let orientation = Orientation {
+      roll: 1.0,
+      pitch: 2.0,
+      yaw: 3.0,
+    };
+    recorder.publish(Topic::Orientation, &orientation);
+    recorder.record(Topic::Orientation, &orientation);
+
+    let stopped = recorder.stop().await.unwrap();
+    assert_eq!(stopped.messages, 1);
+    let bytes = std::fs::read(&started.path).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(read_messages(&bytes).unwrap().len(), 1);
+
+    // Live subscribers see both.
+    for _ in 0..2 {
+      assert_eq!(live.try_recv().unwrap().topic, Topic::Orientation);
+    }
+    assert!(live.try_recv().is_err());
+  }
+}
@@ -0,0 +1,322 @@
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::{
+  hal::{ActuatorBackend, ImuBackend},
+  mcap::{self, McapMessage},
+  policy::{policy_actuator_ids, Observation, WalkPolicy, POLICY_JOINTS},
+  proto::{
+    actuator::{
+      ActuatorCommand, ActuatorStateResponse, CalibrateActuatorRequest,
+      ConfigureActuatorRequest,
+    },
+    common::{ActionResponse, ActionResult},
+    imu::{EulerAnglesResponse, ImuValuesResponse, QuaternionResponse},
+  },
+  recorder::{CommandsMessage, ImuSample, StatesMessage, Topic},
+  Robot,
+};
+
+/// One walk loop tick reconstructed from a recording.
+#[derive(Debug, Clone)]
+struct Tick {
+  log_time: u64,
+  imu: ImuValuesResponse,
+  states: Vec<ActuatorStateResponse>,
+  commands: [f64; 3],
+  /// Positions sent to the policy actuators after the tick, if any.
+  sent: Option<HashMap<u32, f64>>,
+}
+
+/// A recorded walk, split into the ticks of the walk loop.
+#[derive(Debug, Clone)]
+pub struct Replay {
+  actuator_ids: Vec<u32>,
+  ticks: Vec<Tick>,
+}
+
+impl Replay {
+  pub async fn load<R: Robot>(path: impl AsRef<Path>) -> eyre::Result<Self> {
+    let bytes = tokio::fs::read(path).await?;
+
+    Self::parse::<R>(&mcap::read_messages(&bytes)?)
+  }
+
+  /// Every recorded policy observation starts a tick. Its inputs are the
+  /// latest IMU sample and actuator states logged before it, which are the
+  /// readings the observation was built from.
+  pub fn parse<R: Robot>(messages: &[McapMessage]) -> eyre::Result<Self> {
+    let actuator_ids = policy_actuator_ids::<R>()?;
+
+    let mut imu = None;
+    let mut states = HashMap::new();
+    let mut ticks = Vec::new();
+    for message in messages {
+      match Topic::from_name(&message.topic) {
+        Some(Topic::Imu) => {
+          let sample: ImuSample = serde_json::from_slice(&message.data)?;
+          imu = Some(ImuValuesResponse::from(&sample));
+        }
+        Some(Topic::ActuatorStates) => {
+          let message: StatesMessage = serde_json::from_slice(&message.data)?;
+          for state in &message.states {
+            states.insert(state.actuator_id, state.into());
+          }
+        }
+        Some(Topic::PolicyObservation) => {
+          let obs: Observation = serde_json::from_slice(&message.data)?;
+          let Some(imu) = imu.clone() else {
+            continue;
+          };
+
+          ticks.push(Tick {
+            log_time: message.log_time,
+            imu,
+            states: actuator_ids
+              .iter()
+              .filter_map(|id| states.get(id).cloned())
+              .collect(),
+            commands: obs.commands,
+            sent: None,
+          });
+        }
+        Some(Topic::Commands) => {
+          let Some(tick) = ticks.last_mut().filter(|tick| tick.sent.is_none())
+          else {
+            continue;
+          };
+
+          let message: CommandsMessage = serde_json::from_slice(&message.data)?;
+          let sent = message
+            .commands
+            .iter()
+            .filter(|command| actuator_ids.contains(&command.actuator_id))
+            .filter_map(|command| {
+              Some((command.actuator_id, command.position?))
+            })
+            .collect::<HashMap<_, _>>();
+          if !sent.is_empty() {
+            tick.sent = Some(sent);
+          }
+        }
+        Some(Topic::Orientation | Topic::PolicyActions | Topic::LoopTiming)
+        | None => {}
+      }
+    }
+
+    Ok(Self {
+      actuator_ids,
+      ticks,
+    })
+  }
+
+  /// Feeds every tick through the observation builder and `policy`, and
+  /// compares the resulting commands with the ones sent on the robot.
+  pub async fn run<R: Robot>(
+    &self,
+    policy: &mut WalkPolicy,
+  ) -> eyre::Result<ReplayReport> {
+    let backend = ReplayBackend::default();
+
+    let mut ticks = Vec::with_capacity(self.ticks.len());
+    for tick in &self.ticks {
+      *backend.tick.lock().unwrap() = Some(tick.clone());
+
+      let obs =
+        Observation::read::<R>(&backend, &backend, tick.commands).await?;
+      let joints = policy.infer(&obs).await?;
+
+      let commands = joints
+        .into_commands()
+        .into_iter()
+        .filter_map(|(joint, axis, command)| {
+          Some(ActuatorCommand {
+            actuator_id: R::get_actuator_id(joint, axis)?,
+            position: command.position,
+            velocity: command.velocity,
+            torque: command.torque,
+          })
+        })
+        .collect();
+      backend.command_actuators(commands).await?;
+
+      let commanded = std::mem::take(&mut *backend.commanded.lock().unwrap());
+      let position = |id: &u32| {
+        commanded
+          .iter()
+          .find(|command| command.actuator_id == *id)
+          .and_then(|command| command.position)
+      };
+
+      ticks.push(ReplayTick {
+        log_time: tick.log_time,
+        replayed: self.actuator_ids.iter().map(position).collect(),
+        sent: tick.sent.as_ref().map(|sent| {
+          self
+            .actuator_ids
+            .iter()
+            .map(|id| sent.get(id).copied())
+            .collect()
+        }),
+      });
+    }
+
+    Ok(ReplayReport::new(&self.actuator_ids, ticks))
+  }
+}
+
+/// Positions in degrees, ordered like [`POLICY_JOINTS`].
+#[derive(Serialize, Debug, Clone)]
+pub struct ReplayTick {
+  /// Nanoseconds since the Unix epoch.
+  pub log_time: u64,
+  pub replayed: Vec<Option<f64>>,
+  /// `None` if nothing was sent after this tick on the robot.
+  pub sent: Option<Vec<Option<f64>>>,
+}
+
+/// How far the replayed commands of one joint are from the sent ones, in
+/// degrees.
+#[derive(Serialize, Debug, Clone)]
+pub struct JointDifference {
+  pub name: &'static str,
+  pub actuator_id: u32,
+  pub samples: usize,
+  pub mean_abs: f64,
+  pub rms: f64,
+  pub max_abs: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReplayReport {
+  pub joints: Vec<JointDifference>,
+  /// Largest difference of any joint in any tick.
+  pub max_abs: f64,
+  /// Ticks that had no sent commands to compare against.
+  pub unmatched_ticks: usize,
+  pub ticks: Vec<ReplayTick>,
+}
+
+impl ReplayReport {
+  fn new(actuator_ids: &[u32], ticks: Vec<ReplayTick>) -> Self {
+    let joints = actuator_ids
+      .iter()
+      .enumerate()
+      .map(|(index, actuator_id)| {
+        let differences = ticks
+          .iter()
+          .filter_map(|tick| {
+            let replayed = tick.replayed[index]?;
+            let sent = tick.sent.as_ref()?[index]?;
+            Some((replayed - sent).abs())
+          })
+          .collect::<Vec<_>>();
+        let samples = differences.len().max(1) as f64;
+
+        JointDifference {
+          name: POLICY_JOINTS[index],
+          actuator_id: *actuator_id,
+          samples: differences.len(),
+          mean_abs: differences.iter().sum::<f64>() / samples,
+          rms: (differences.iter().map(|d| d * d).sum::<f64>() / samples)
+            .sqrt(),
+          max_abs: differences.iter().copied().fold(0.0, f64::max),
+        }
+      })
+      .collect::<Vec<_>>();
+
+    Self {
+      max_abs: joints.iter().map(|joint| joint.max_abs).fold(0.0, f64::max),
+      unmatched_ticks: ticks.iter().filter(|tick| tick.sent.is_none()).count(),
+      joints,
+      ticks,
+    }
+  }
+}
+
+/// Serves the IMU and actuator readings of the current tick and keeps the
+/// commands sent to it instead of moving anything.
+#[derive(Debug, Default)]
+struct ReplayBackend {
+  tick: Mutex<Option<Tick>>,
+  commanded: Mutex<Vec<ActuatorCommand>>,
+}
+
+impl ReplayBackend {
+  fn tick(&self) -> eyre::Result<Tick> {
+    self
+      .tick
+      .lock()
+      .unwrap()
+      .clone()
+      .ok_or_else(|| eyre::eyre!("No tick is being replayed"))
+  }
+}
+
+#[async_trait]
+impl ActuatorBackend for ReplayBackend {
+  async fn command_actuators(
+    &self,
+    commands: Vec<ActuatorCommand>,
+  ) -> eyre::Result<Vec<ActionResult>> {
+    let results = commands
+      .iter()
+      .map(|command| ActionResult {
+        actuator_id: command.actuator_id,
+        success: true,
+        error: None,
+      })
+      .collect();
+    self.commanded.lock().unwrap().extend(commands);
+
+    Ok(results)
+  }
+
+  async fn configure_actuator(
+    &self,
+    _request: ConfigureActuatorRequest,
+  ) -> eyre::Result<ActionResponse> {
+    Ok(ActionResponse {
+      success: true,
+      error: None,
+    })
+  }
+
+  async fn calibrate_actuator(
+    &self,
+    _request: CalibrateActuatorRequest,
+  ) -> eyre::Result<()> {
+    Ok(())
+  }
+
+  async fn get_actuators_state(
+    &self,
+    actuator_ids: Vec<u32>,
+  ) -> eyre::Result<Vec<ActuatorStateResponse>> {
+    Ok(
+      self
+        .tick()?
+        .states
+        .into_iter()
+        .filter(|state| actuator_ids.contains(&state.actuator_id))
+        .collect(),
+    )
+  }
+}
+
+#[async_trait]
+impl ImuBackend for ReplayBackend {
+  async fn get_values(&self) -> eyre::Result<ImuValuesResponse> {
+    Ok(self.tick()?.imu)
+  }
+
+  async fn get_euler(&self) -> eyre::Result<EulerAnglesResponse> {
+    Err(eyre::eyre!("Orientation is not recorded"))
+  }
+
+  async fn get_quaternion(&self) -> eyre::Result<QuaternionResponse> {
+    Err(eyre::eyre!("Orientation is not recorded"))
+  }
+}
@@ -0,0 +1,316 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  arbiter::{Lease, Priority},
+  pose::{joint_limits, JointAxis, JointGroup},
+  proto::actuator::ActuatorStateResponse,
+  ActuatorCommand, KBot, Robot,
+};
+
+/// Largest wiggle, in degrees, the self-test will ever command.
+const MAX_WIGGLE: f64 = 10.0;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SelfTestConfig {
+  /// How far, in degrees, each joint is moved from where it started.
+  pub wiggle: f64,
+  /// How long to wait for a joint to reach each wiggle target.
+  pub settle_ms: u64,
+  /// Fraction of the wiggle a joint must cover to count as moving.
+  pub min_motion: f64,
+}
+
+impl Default for SelfTestConfig {
+  fn default() -> Self {
+    Self {
+      wiggle: 3.0,
+      settle_ms: 300,
+      min_motion: 0.5,
+    }
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ActuatorTestResult {
+  pub actuator_id: u32,
+  pub online: bool,
+  pub start_position: Option<f64>,
+  /// Displacement measured after commanding the wiggle, which goes the
+  /// other way for joints near their upper limit.
+  pub moved: Option<f64>,
+  /// Distance from the start position after commanding it back.
+  pub returned_error: Option<f64>,
+  pub passed: bool,
+  /// The joint was too close to its limits to wiggle either way, so it was
+  /// not moved.
+  pub skipped: bool,
+  pub error: Option<String>,
+}
+
+impl ActuatorTestResult {
+  fn new(actuator_id: u32) -> Self {
+    Self {
+      actuator_id,
+      online: false,
+      start_position: None,
+      moved: None,
+      returned_error: None,
+      passed: false,
+      skipped: false,
+      error: None,
+    }
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SelfTestReport {
+  /// Whether every actuator that was not skipped passed.
+  pub passed: bool,
+  pub actuators: Vec<ActuatorTestResult>,
+}
+
+impl KBot {
+  /// Checks that every actuator responds and moves the right way when
+  /// commanded, one at a time, returning each to where it started. Fails if
+  /// another controller owns any of the actuators.
+  pub async fn self_test(
+    &self,
+    config: &SelfTestConfig,
+  ) -> eyre::Result<SelfTestReport> {
+    let lease = self.arbiter.acquire(
+      "self_test",
+      Priority::Pose,
+      JointGroup::WholeBody.actuators::<Self>(),
+    )?;
+
+    let mut actuators = Vec::new();
+
+    for actuator_id in Self::list_actuator_ids() {
+      let result = match self.test_actuator(&lease, actuator_id, config).await {
+        Ok(result) => result,
+        Err(e) => ActuatorTestResult {
+          error: Some(e.to_string()),
+          ..ActuatorTestResult::new(actuator_id)
+        },
+      };
+
+      tracing::info!(
+        actuator_id,
+        passed = result.passed,
+        "Self-tested actuator"
+      );
+
+      actuators.push(result);
+    }
+
+    let report = SelfTestReport {
+      passed: actuators
+        .iter()
+        .all(|result| result.passed || result.skipped),
+      actuators,
+    };
+
+    *self.last_self_test.lock().await = Some(report.clone());
+
+    Ok(report)
+  }
+
+  async fn test_actuator(
+    &self,
+    lease: &Lease,
+    actuator_id: u32,
+    config: &SelfTestConfig,
+  ) -> eyre::Result<ActuatorTestResult> {
+    let settle = Duration::from_millis(config.settle_ms);
+    let mut result = ActuatorTestResult::new(actuator_id);
+
+    let Some(start) = self.read_actuator(actuator_id).await? else {
+      result.error = Some("No state reported".to_string());
+      return Ok(result);
+    };
+
+    result.online = start.online;
+    if !start.online {
+      result.error = Some("Actuator is offline".to_string());
+      return Ok(result);
+    }
+
+    let Some(start_position) = start.position else {
+      result.error = Some("No position reported".to_string());
+      return Ok(result);
+    };
+    result.start_position = Some(start_position);
+
+    let Some(wiggle) = self.wiggle(actuator_id, start_position, config) else {
+      result.skipped = true;
+      result.error = Some(format!(
+        "Too close to its limits to move {:.1}° either way",
+        config.wiggle.abs()
+      ));
+      return Ok(result);
+    };
+
+    // Whatever happens on the way out, the joint is sent back to where it
+    // started before anything is reported.
+    let moved = async {
+      self
+        .move_actuator(lease, actuator_id, start_position + wiggle)
+        .await?;
+      tokio::time::sleep(settle).await;
+
+      eyre::Ok(
+        self
+          .read_actuator(actuator_id)
+          .await?
+          .and_then(|state| state.position)
+          .map(|position| position - start_position),
+      )
+    }
+    .await;
+
+    self
+      .move_actuator(lease, actuator_id, start_position)
+      .await?;
+    let moved = moved?;
+    tokio::time::sleep(settle).await;
+    let returned_error = self
+      .read_actuator(actuator_id)
+      .await?
+      .and_then(|state| state.position)
+      .map(|position| (position - start_position).abs());
+
+    let min_motion = wiggle.abs() * config.min_motion;
+    let moved_forward =
+      moved.is_some_and(|moved| moved * wiggle.signum() >= min_motion);
+    let came_back = returned_error.is_some_and(|error| error <= min_motion);
+
+    result.moved = moved;
+    result.returned_error = returned_error;
+    result.passed = moved_forward && came_back;
+    result.error = match (moved_forward, came_back) {
+      (true, true) => None,
+      (false, _) => Some(format!(
+        "Expected to move {wiggle:+.1}°, moved {:+.1}°",
+        moved.unwrap_or_default()
+      )),
+      (true, false) => Some("Did not return to start position".to_string()),
+    };
+
+    Ok(result)
+  }
+
+  /// The wiggle to test with: `config.wiggle` or its opposite, whichever
+  /// keeps the joint within its limits, or `None` if neither does.
+  fn wiggle(
+    &self,
+    actuator_id: u32,
+    start_position: f64,
+    config: &SelfTestConfig,
+  ) -> Option<f64> {
+    let wiggle = config.wiggle.clamp(-MAX_WIGGLE, MAX_WIGGLE);
+    let limits = JointAxis::from_actuator_id::<Self>(actuator_id)
+      .and_then(|joint| joint_limits::<Self>(joint, self.description.as_ref()));
+
+    let Some([min, max]) = limits else {
+      return Some(wiggle);
+    };
+
+    [wiggle, -wiggle]
+      .into_iter()
+      .find(|wiggle| (min..=max).contains(&(start_position + wiggle)))
+  }
+
+  async fn read_actuator(
+    &self,
+    actuator_id: u32,
+  ) -> eyre::Result<Option<ActuatorStateResponse>> {
+    Ok(
+      self
+        .hal
+        .actuators
+        .get_actuators_state(vec![actuator_id])
+        .await?
+        .into_iter()
+        .find(|state| state.actuator_id == actuator_id),
+    )
+  }
+
+  async fn move_actuator(
+    &self,
+    lease: &Lease,
+    actuator_id: u32,
+    position: f64,
+  ) -> eyre::Result<()> {
+    self
+      .command_actuators(
+        lease,
+        vec![ActuatorCommand {
+          actuator_id,
+          position: Some(position),
+          velocity: None,
+          torque: None,
+        }],
+      )
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tests::{config, simulated_with};
+
+  #[tokio::test]
+  async fn actuators_are_wiggled_within_limits_and_returned() {
+    let (kbot, simulator) = simulated_with(config()).await;
+    let config = SelfTestConfig {
+      settle_ms: 150,
+      ..Default::default()
+    };
+
+    // Too close to its upper limit to move up, so it has to move down.
+    let lease = kbot.arbiter.acquire("test", Priority::Pose, [35]).unwrap();
+    kbot.move_actuator(&lease, 35, 59.0).await.unwrap();
+    drop(lease);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    simulator.set_rejecting(34, true);
+    simulator.set_online(44, false);
+
+    let report = kbot.self_test(&config).await.unwrap();
+    assert!(!report.passed);
+    assert_eq!(report.actuators.len(), KBot::list_actuator_ids().len());
+
+    for result in &report.actuators {
+      match result.actuator_id {
+        34 => {
+          assert!(!result.passed);
+          assert!(result.error.as_ref().unwrap().contains("rejected"));
+        }
+        44 => {
+          assert!(!result.passed && !result.online);
+        }
+        actuator_id => {
+          assert!(result.passed, "{actuator_id}: {:?}", result.error);
+
+          let start = result.start_position.unwrap();
+          let moved = result.moved.unwrap();
+          let joint = JointAxis::from_actuator_id::<KBot>(actuator_id).unwrap();
+          let [min, max] = joint_limits::<KBot>(joint, None).unwrap();
+          assert!((min..=max).contains(&(start + moved)));
+          assert!(result.returned_error.unwrap() < 0.5);
+        }
+      }
+    }
+
+    let ankle = report
+      .actuators
+      .iter()
+      .find(|result| result.actuator_id == 35)
+      .unwrap();
+    assert!(ankle.moved.unwrap() < 0.0);
+    assert!(kbot.arbiter.owners().is_empty());
+  }
+}
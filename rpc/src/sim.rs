@@ -0,0 +1,692 @@
+use std::{
+  collections::BTreeMap,
+  net::SocketAddr,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use async_trait::async_trait;
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+  description::RobotDescription,
+  hal::{ActuatorBackend, Hal, ImuBackend, LedMatrixBackend, SoundBackend},
+  pose::{joint_limits, JointAxis},
+  proto::{
+    actuator::{
+      actuator_service_server::{ActuatorService, ActuatorServiceServer},
+      ActuatorCommand, ActuatorStateResponse, CalibrateActuatorRequest,
+      CommandActuatorsRequest, CommandActuatorsResponse,
+      ConfigureActuatorRequest, GetActuatorsStateRequest,
+      GetActuatorsStateResponse,
+    },
+    common::{self, ActionResponse, ActionResult},
+    google::longrunning::Operation,
+    imu::{
+      imu_service_server::{ImuService, ImuServiceServer},
+      EulerAnglesResponse, ImuAdvancedValuesResponse, ImuValuesResponse,
+      QuaternionResponse, ZeroImuRequest,
+    },
+    sound::AudioConfig,
+  },
+  Robot,
+};
+
+/// Standard gravity, in m/s^2.
+const GRAVITY: f64 = 9.81;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SimConfig {
+  /// Where to also serve the simulated actuator and IMU services over gRPC
+  /// for external tools, if anywhere. Port 0 picks a free port.
+  pub addr: Option<SocketAddr>,
+  /// How often the actuators are stepped.
+  pub step_ms: u64,
+  /// Time constant, in milliseconds, of each actuator's first-order response
+  /// to a position command.
+  pub time_constant_ms: f64,
+  /// Fastest any joint can move, in degrees per second.
+  pub max_velocity: f64,
+  /// Torque limit for actuators that were not configured with one.
+  pub max_torque: f64,
+  /// Roll, pitch and yaw of the torso in degrees, as reported by the IMU.
+  pub orientation: [f64; 3],
+}
+
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self {
+      addr: None,
+      step_ms: 2,
+      time_constant_ms: 50.0,
+      max_velocity: 360.0,
+      max_torque: 40.0,
+      orientation: [0.0; 3],
+    }
+  }
+}
+
+/// One simulated actuator. Positions are in degrees on the motor side, before
+/// the zero offset is applied.
+#[derive(Debug, Clone)]
+struct SimActuator {
+  position: f64,
+  velocity: f64,
+  torque: f64,
+  zero: f64,
+  target: Option<f64>,
+  target_velocity: Option<f64>,
+  target_torque: f64,
+  kp: f64,
+  kd: f64,
+  max_torque: Option<f64>,
+  /// Speed limit while driving into a hard stop during calibration.
+  calibration_speed: Option<f64>,
+  torque_enabled: bool,
+  limits: Option<(f64, f64)>,
+  /// Whether the actuator reports its state at all.
+  online: bool,
+  /// Whether every command and configuration sent to it fails.
+  rejecting: bool,
+  temperature: f64,
+}
+
+impl SimActuator {
+  fn new(limits: Option<(f64, f64)>) -> Self {
+    Self {
+      position: 0.0,
+      velocity: 0.0,
+      torque: 0.0,
+      zero: 0.0,
+      target: None,
+      target_velocity: None,
+      target_torque: 0.0,
+      kp: 0.0,
+      kd: 0.0,
+      max_torque: None,
+      calibration_speed: None,
+      torque_enabled: false,
+      limits,
+      online: true,
+      rejecting: false,
+      temperature: 30.0,
+    }
+  }
+
+  /// Tracks the commanded position with a first-order lag, slowed down
+  /// whenever the PD torque needed to do so exceeds the torque limit.
+  fn step(&mut self, config: &SimConfig, dt: f64) {
+    if !self.torque_enabled {
+      self.velocity = 0.0;
+      self.torque = 0.0;
+      return;
+    }
+
+    let tau = (config.time_constant_ms / 1000.0).max(dt);
+    let error = self.target.map(|target| target - self.position);
+    let mut velocity = match error {
+      Some(error) => error / tau + self.target_velocity.unwrap_or_default(),
+      None => self.target_velocity.unwrap_or_default(),
+    };
+
+    let max_velocity = self
+      .calibration_speed
+      .unwrap_or(config.max_velocity)
+      .min(config.max_velocity);
+    velocity = velocity.clamp(-max_velocity, max_velocity);
+
+    let max_torque = self.max_torque.unwrap_or(config.max_torque);
+    let torque = self.kp * error.unwrap_or_default().to_radians()
+      - self.kd * self.velocity.to_radians()
+      + self.target_torque;
+    if torque.abs() > max_torque {
+      velocity *= max_torque / torque.abs();
+    }
+    self.torque = torque.clamp(-max_torque, max_torque);
+
+    let mut position = self.position + velocity * dt;
+    if let Some((min, max)) = self.limits {
+      position = position.clamp(min + self.zero, max + self.zero);
+    }
+
+    self.velocity = (position - self.position) / dt;
+    self.position = position;
+  }
+
+  fn state(&self, actuator_id: u32) -> ActuatorStateResponse {
+    ActuatorStateResponse {
+      actuator_id,
+      online: true,
+      position: Some(self.position - self.zero),
+      velocity: Some(self.velocity),
+      torque: Some(self.torque),
+      temperature: Some(self.temperature),
+      voltage: Some(24.0),
+      current: Some((self.torque / 10.0) as f32),
+      ..Default::default()
+    }
+  }
+}
+
+/// Kinematic stand-in for the robot, usable directly as a [`Hal`] or served
+/// over the KOS actuator and IMU APIs for external tools.
+///
+/// Every actuator of the robot tracks its commands as a first-order system
+/// with velocity and torque limits and stops at its joint limits, which also
+/// act as the hard stops for calibration. The torso is fixed in place, so the
+/// IMU only ever reads gravity.
+///
+/// A robot run on the simulator has no KOS client, so model management,
+/// KClips and walking with an on-robot model do not work against it. Through
+/// the [`Hal`], the LED matrix and speaker accept anything; only the actuator
+/// and IMU services are served over gRPC.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+  config: SimConfig,
+  actuators: Arc<Mutex<BTreeMap<u32, SimActuator>>>,
+}
+
+impl Simulator {
+  /// Simulates every actuator of `R`, with the joint limits from
+  /// `description` where it has them.
+  pub fn new<R: Robot>(
+    config: SimConfig,
+    description: Option<&RobotDescription>,
+  ) -> Self {
+    let actuators = R::list_actuator_ids()
+      .into_iter()
+      .map(|actuator_id| {
+        let limits = JointAxis::from_actuator_id::<R>(actuator_id)
+          .and_then(|joint| joint_limits::<R>(joint, description))
+          .map(|[min, max]| (min, max));
+        (actuator_id, SimActuator::new(limits))
+      })
+      .collect();
+
+    Self {
+      config,
+      actuators: Arc::new(Mutex::new(actuators)),
+    }
+  }
+
+  /// Serves the actuator and IMU services at `addr`. Returns the address the
+  /// server is listening on.
+  pub async fn serve(&self, addr: SocketAddr) -> eyre::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let addr = listener.local_addr()?;
+
+    let server = Server::builder()
+      .add_service(ActuatorServiceServer::new(self.clone()))
+      .add_service(ImuServiceServer::new(self.clone()));
+
+    tokio::spawn(async move {
+      if let Err(e) = server
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+      {
+        tracing::error!(error = %e, "Simulator server stopped");
+      }
+    });
+
+    Ok(addr)
+  }
+
+  /// Drives the simulator directly, without going through gRPC.
+  pub fn hal(&self) -> Hal {
+    let simulator = Arc::new(self.clone());
+
+    Hal {
+      actuators: simulator.clone(),
+      imu: simulator.clone(),
+      led_matrix: simulator.clone(),
+      sound: simulator,
+    }
+  }
+
+  /// Starts stepping the actuators in the background.
+  pub fn spawn(&self) {
+    let simulator = self.clone();
+
+    tokio::spawn(async move {
+      let step = Duration::from_millis(simulator.config.step_ms.max(1));
+      let mut interval = tokio::time::interval(step);
+
+      loop {
+        interval.tick().await;
+
+        for actuator in simulator.actuators.lock().unwrap().values_mut() {
+          actuator.step(&simulator.config, step.as_secs_f64());
+        }
+      }
+    });
+  }
+
+  fn orientation(&self) -> UnitQuaternion<f64> {
+    let [roll, pitch, yaw] = self.config.orientation;
+
+    UnitQuaternion::from_euler_angles(
+      roll.to_radians(),
+      pitch.to_radians(),
+      yaw.to_radians(),
+    )
+  }
+
+  /// Runs `f` on an actuator, failing with the message to report if it is
+  /// unknown, offline or rejecting requests.
+  fn with_responsive_actuator<T>(
+    &self,
+    actuator_id: u32,
+    f: impl FnOnce(&mut SimActuator) -> T,
+  ) -> Result<T, String> {
+    self
+      .with_actuator(actuator_id, |actuator| {
+        if actuator.online && !actuator.rejecting {
+          Ok(f(actuator))
+        } else {
+          Err(format!(
+            "Simulated actuator {actuator_id} rejected the request"
+          ))
+        }
+      })
+      .unwrap_or_else(|| Err(unknown_actuator(actuator_id)))
+  }
+
+  fn with_actuator<T>(
+    &self,
+    actuator_id: u32,
+    f: impl FnOnce(&mut SimActuator) -> T,
+  ) -> Option<T> {
+    self.actuators.lock().unwrap().get_mut(&actuator_id).map(f)
+  }
+}
+
+/// Lets tests inject faults and inspect how actuators were configured.
+#[cfg(test)]
+impl Simulator {
+  /// Stops the actuator from reporting its state and answering requests.
+  pub(crate) fn set_online(&self, actuator_id: u32, online: bool) {
+    self.with_actuator(actuator_id, |actuator| actuator.online = online);
+  }
+
+  /// Makes every command and configuration sent to the actuator fail, while
+  /// it keeps reporting its state.
+  pub(crate) fn set_rejecting(&self, actuator_id: u32, rejecting: bool) {
+    self.with_actuator(actuator_id, |actuator| actuator.rejecting = rejecting);
+  }
+
+  pub(crate) fn set_temperature(&self, actuator_id: u32, temperature: f64) {
+    self.with_actuator(actuator_id, |actuator| {
+      actuator.temperature = temperature
+    });
+  }
+
+  pub(crate) fn kp(&self, actuator_id: u32) -> Option<f64> {
+    self.with_actuator(actuator_id, |actuator| actuator.kp)
+  }
+
+  pub(crate) fn max_torque(&self, actuator_id: u32) -> Option<f64> {
+    self.with_actuator(actuator_id, |actuator| actuator.max_torque)?
+  }
+
+  pub(crate) fn torque_enabled(&self, actuator_id: u32) -> Option<bool> {
+    self.with_actuator(actuator_id, |actuator| actuator.torque_enabled)
+  }
+}
+
+fn unknown_actuator(actuator_id: u32) -> String {
+  format!("No simulated actuator {actuator_id}")
+}
+
+#[async_trait]
+impl ActuatorBackend for Simulator {
+  async fn command_actuators(
+    &self,
+    commands: Vec<ActuatorCommand>,
+  ) -> eyre::Result<Vec<ActionResult>> {
+    Ok(
+      commands
+        .into_iter()
+        .map(|command| {
+          let result =
+            self.with_responsive_actuator(command.actuator_id, |actuator| {
+              actuator.target =
+                command.position.map(|position| position + actuator.zero);
+              actuator.target_velocity = command.velocity;
+              actuator.target_torque = command.torque.unwrap_or_default();
+              actuator.calibration_speed = None;
+            });
+
+          ActionResult {
+            actuator_id: command.actuator_id,
+            success: result.is_ok(),
+            error: result
+              .err()
+              .map(|message| common::Error { code: 0, message }),
+          }
+        })
+        .collect(),
+    )
+  }
+
+  async fn configure_actuator(
+    &self,
+    request: ConfigureActuatorRequest,
+  ) -> eyre::Result<ActionResponse> {
+    if self.with_actuator(request.actuator_id, |_| ()).is_none() {
+      return Err(eyre::eyre!(unknown_actuator(request.actuator_id)));
+    }
+
+    let result =
+      self.with_responsive_actuator(request.actuator_id, |actuator| {
+        if let Some(kp) = request.kp {
+          actuator.kp = kp;
+        }
+        if let Some(kd) = request.kd {
+          actuator.kd = kd;
+        }
+        if let Some(max_torque) = request.max_torque {
+          actuator.max_torque = Some(max_torque);
+        }
+        if let Some(torque_enabled) = request.torque_enabled {
+          actuator.torque_enabled = torque_enabled;
+          actuator.target = torque_enabled.then_some(actuator.position);
+          actuator.target_velocity = None;
+        }
+        if request.zero_position == Some(true) {
+          actuator.zero = actuator.position;
+          actuator.target = actuator.target.map(|_| actuator.position);
+        }
+      });
+
+    Ok(ActionResponse {
+      success: result.is_ok(),
+      error: result
+        .err()
+        .map(|message| common::Error { code: 0, message }),
+    })
+  }
+
+  async fn calibrate_actuator(
+    &self,
+    request: CalibrateActuatorRequest,
+  ) -> eyre::Result<()> {
+    // Drive slowly into the lower hard stop, like the real calibration does.
+    self
+      .with_responsive_actuator(request.actuator_id, |actuator| {
+        let Some((min, _)) = actuator.limits else {
+          return Err(eyre::eyre!(
+            "Simulated actuator {} has no hard stop to calibrate against",
+            request.actuator_id
+          ));
+        };
+
+        actuator.torque_enabled = true;
+        actuator.target = Some(min + actuator.zero);
+        actuator.target_velocity = None;
+        actuator.calibration_speed = request.calibration_speed;
+
+        Ok(())
+      })
+      .map_err(|message| eyre::eyre!(message))?
+  }
+
+  async fn get_actuators_state(
+    &self,
+    actuator_ids: Vec<u32>,
+  ) -> eyre::Result<Vec<ActuatorStateResponse>> {
+    let actuators = self.actuators.lock().unwrap();
+
+    Ok(
+      actuator_ids
+        .into_iter()
+        .map(|actuator_id| match actuators.get(&actuator_id) {
+          Some(actuator) if actuator.online => actuator.state(actuator_id),
+          _ => ActuatorStateResponse {
+            actuator_id,
+            online: false,
+            ..Default::default()
+          },
+        })
+        .collect(),
+    )
+  }
+}
+
+#[async_trait]
+impl ImuBackend for Simulator {
+  async fn get_values(&self) -> eyre::Result<ImuValuesResponse> {
+    let gravity =
+      self.orientation().inverse() * Vector3::new(0.0, 0.0, GRAVITY);
+
+    Ok(ImuValuesResponse {
+      accel_x: gravity.x,
+      accel_y: gravity.y,
+      accel_z: gravity.z,
+      gyro_x: 0.0,
+      gyro_y: 0.0,
+      gyro_z: 0.0,
+      ..Default::default()
+    })
+  }
+
+  async fn get_euler(&self) -> eyre::Result<EulerAnglesResponse> {
+    let [roll, pitch, yaw] = self.config.orientation;
+
+    Ok(EulerAnglesResponse {
+      roll,
+      pitch,
+      yaw,
+      error: None,
+    })
+  }
+
+  async fn get_quaternion(&self) -> eyre::Result<QuaternionResponse> {
+    let orientation = self.orientation();
+
+    Ok(QuaternionResponse {
+      x: orientation.i,
+      y: orientation.j,
+      z: orientation.k,
+      w: orientation.w,
+      error: None,
+    })
+  }
+}
+
+/// The simulator has no LED matrix or speaker, so both accept anything.
+#[async_trait]
+impl LedMatrixBackend for Simulator {
+  async fn write_buffer(
+    &self,
+    _buffer: Vec<u8>,
+  ) -> eyre::Result<ActionResponse> {
+    Ok(ActionResponse {
+      success: true,
+      error: None,
+    })
+  }
+}
+
+#[async_trait]
+impl SoundBackend for Simulator {
+  async fn play_audio(
+    &self,
+    _config: AudioConfig,
+    _audio: Vec<u8>,
+  ) -> eyre::Result<ActionResponse> {
+    Ok(ActionResponse {
+      success: true,
+      error: None,
+    })
+  }
+}
+
+fn status(error: eyre::Report) -> Status {
+  Status::invalid_argument(error.to_string())
+}
+
+#[tonic::async_trait]
+impl ActuatorService for Simulator {
+  async fn command_actuators(
+    &self,
+    request: Request<CommandActuatorsRequest>,
+  ) -> Result<Response<CommandActuatorsResponse>, Status> {
+    let results =
+      ActuatorBackend::command_actuators(self, request.into_inner().commands)
+        .await
+        .map_err(status)?;
+
+    Ok(Response::new(CommandActuatorsResponse { results }))
+  }
+
+  async fn configure_actuator(
+    &self,
+    request: Request<ConfigureActuatorRequest>,
+  ) -> Result<Response<ActionResponse>, Status> {
+    ActuatorBackend::configure_actuator(self, request.into_inner())
+      .await
+      .map(Response::new)
+      .map_err(status)
+  }
+
+  async fn calibrate_actuator(
+    &self,
+    request: Request<CalibrateActuatorRequest>,
+  ) -> Result<Response<Operation>, Status> {
+    let request = request.into_inner();
+    let actuator_id = request.actuator_id;
+
+    ActuatorBackend::calibrate_actuator(self, request)
+      .await
+      .map_err(status)?;
+
+    Ok(Response::new(Operation {
+      name: format!("calibrate/{actuator_id}"),
+      done: true,
+      ..Default::default()
+    }))
+  }
+
+  async fn get_actuators_state(
+    &self,
+    request: Request<GetActuatorsStateRequest>,
+  ) -> Result<Response<GetActuatorsStateResponse>, Status> {
+    let states = ActuatorBackend::get_actuators_state(
+      self,
+      request.into_inner().actuator_ids,
+    )
+    .await
+    .map_err(status)?;
+
+    Ok(Response::new(GetActuatorsStateResponse { states }))
+  }
+}
+
+#[tonic::async_trait]
+impl ImuService for Simulator {
+  async fn get_values(
+    &self,
+    _request: Request<()>,
+  ) -> Result<Response<ImuValuesResponse>, Status> {
+    ImuBackend::get_values(self)
+      .await
+      .map(Response::new)
+      .map_err(status)
+  }
+
+  async fn get_advanced_values(
+    &self,
+    _request: Request<()>,
+  ) -> Result<Response<ImuAdvancedValuesResponse>, Status> {
+    Ok(Response::new(ImuAdvancedValuesResponse::default()))
+  }
+
+  async fn get_euler(
+    &self,
+    _request: Request<()>,
+  ) -> Result<Response<EulerAnglesResponse>, Status> {
+    ImuBackend::get_euler(self)
+      .await
+      .map(Response::new)
+      .map_err(status)
+  }
+
+  async fn get_quaternion(
+    &self,
+    _request: Request<()>,
+  ) -> Result<Response<QuaternionResponse>, Status> {
+    ImuBackend::get_quaternion(self)
+      .await
+      .map(Response::new)
+      .map_err(status)
+  }
+
+  async fn calibrate(
+    &self,
+    _request: Request<()>,
+  ) -> Result<Response<Operation>, Status> {
+    Ok(Response::new(Operation {
+      name: "calibrate/imu".to_string(),
+      done: true,
+      ..Default::default()
+    }))
+  }
+
+  async fn zero(
+    &self,
+    _request: Request<ZeroImuRequest>,
+  ) -> Result<Response<ActionResponse>, Status> {
+    Ok(Response::new(ActionResponse {
+      success: true,
+      error: None,
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::KBot;
+
+  #[test]
+  fn actuators_stop_at_their_joint_limits() {
+    let simulator = Simulator::new::<KBot>(SimConfig::default(), None);
+    let config = SimConfig::default();
+
+    simulator
+      .with_actuator(35, |actuator| {
+        assert_eq!(actuator.limits, Some((-60.0, 60.0)));
+
+        actuator.torque_enabled = true;
+        actuator.target = Some(90.0);
+        for _ in 0..1000 {
+          actuator.step(&config, 0.01);
+        }
+
+        assert_eq!(actuator.position, 60.0);
+      })
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn calibration_needs_a_hard_stop() {
+    let simulator = Simulator::new::<KBot>(SimConfig::default(), None);
+    simulator.with_actuator(35, |actuator| actuator.limits = None);
+
+    let request = |actuator_id| CalibrateActuatorRequest {
+      actuator_id,
+      ..Default::default()
+    };
+
+    assert!(ActuatorBackend::calibrate_actuator(&simulator, request(34))
+      .await
+      .is_ok());
+    assert!(ActuatorBackend::calibrate_actuator(&simulator, request(35))
+      .await
+      .is_err());
+  }
+}
@@ -0,0 +1,125 @@
+use std::{
+  sync::Arc,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::Instrument;
+
+use crate::{
+  hal::Hal,
+  kinematics::{EndEffector, KinematicModel},
+  pose::{JointGroup, Pose},
+  profiler::Profiler,
+  KBot,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TelemetryConfig {
+  /// How often a frame is sampled while anyone is subscribed.
+  pub interval_ms: u64,
+}
+
+impl Default for TelemetryConfig {
+  fn default() -> Self {
+    Self { interval_ms: 50 }
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TelemetryFrame {
+  /// Milliseconds since the Unix epoch.
+  pub timestamp_ms: u64,
+  pub joints: Pose,
+  /// Empty when no robot description is loaded.
+  pub end_effectors: Vec<EndEffector>,
+}
+
+/// Periodically samples joint positions and the end effector poses computed
+/// from them.
+#[derive(Debug)]
+pub struct Telemetry {
+  hal: Hal,
+  config: TelemetryConfig,
+  kinematics: Option<Arc<KinematicModel>>,
+  profiler: Arc<Profiler>,
+  frames: broadcast::Sender<TelemetryFrame>,
+}
+
+impl Telemetry {
+  pub fn new(
+    hal: Hal,
+    config: TelemetryConfig,
+    kinematics: Option<Arc<KinematicModel>>,
+    profiler: Arc<Profiler>,
+  ) -> Self {
+    let (frames, _) = broadcast::channel(16);
+
+    Self {
+      hal,
+      config,
+      kinematics,
+      profiler,
+      frames,
+    }
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<TelemetryFrame> {
+    self.frames.subscribe()
+  }
+
+  /// Samples on an interval, skipping the actuator reads when nobody is
+  /// subscribed.
+  pub fn spawn(self: &Arc<Self>) {
+    let telemetry = self.clone();
+
+    tokio::spawn(
+      async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(
+          telemetry.config.interval_ms,
+        ));
+
+        loop {
+          interval.tick().await;
+
+          if telemetry.frames.receiver_count() == 0 {
+            continue;
+          }
+
+          let mut tick = telemetry.profiler.tick("telemetry");
+          match tick.time("sample", telemetry.sample()).await {
+            Ok(frame) => {
+              telemetry.frames.send(frame).ok();
+            }
+            Err(e) => tracing::warn!(error = %e, "Failed to sample telemetry"),
+          }
+        }
+      }
+      .instrument(tracing::info_span!("telemetry")),
+    );
+  }
+
+  pub async fn sample(&self) -> eyre::Result<TelemetryFrame> {
+    let states = self
+      .hal
+      .actuators
+      .get_actuators_state(JointGroup::WholeBody.actuators::<KBot>())
+      .await?;
+
+    let joints = Pose::from_states::<KBot>(&states);
+
+    Ok(TelemetryFrame {
+      timestamp_ms: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64,
+      end_effectors: self
+        .kinematics
+        .as_ref()
+        .map(|kinematics| kinematics.forward(&joints))
+        .unwrap_or_default(),
+      joints,
+    })
+  }
+}
@@ -0,0 +1,314 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Longest step the ramp takes at once, so the first tick after a pause
+/// does not jump straight to the target.
+const MAX_RAMP_STEP: Duration = Duration::from_millis(100);
+
+/// Velocity the walk policy is asked to follow.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct VelocityCommand {
+  /// Forward velocity in m/s.
+  pub x: f64,
+  /// Lateral velocity in m/s, positive to the left.
+  pub y: f64,
+  /// Yaw rate in rad/s, positive counterclockwise.
+  pub yaw: f64,
+}
+
+impl VelocityCommand {
+  /// `[x, y, yaw]`, the order of the policy's `commands` input.
+  pub fn to_array(self) -> [f64; 3] {
+    [self.x, self.y, self.yaw]
+  }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct VelocityConfig {
+  /// Allowed `[min, max]` of each field of a [`VelocityCommand`].
+  pub x: [f64; 2],
+  pub y: [f64; 2],
+  pub yaw: [f64; 2],
+  /// Largest change of `x` and `y` per second, in m/s².
+  pub linear_acceleration: f64,
+  /// Largest change of `yaw` per second, in rad/s².
+  pub yaw_acceleration: f64,
+  /// Target until an operator sets one.
+  pub initial: VelocityCommand,
+  /// Once an operator has set a target and then none for this long, the
+  /// target drops to standing still and the command ramps down to it. Keeps
+  /// the robot from walking off when the operator's connection drops, while
+  /// a walk that nobody steers keeps the initial target. `None` keeps the
+  /// last target forever.
+  pub deadman_ms: Option<u64>,
+}
+
+impl Default for VelocityConfig {
+  fn default() -> Self {
+    Self {
+      x: [-0.3, 1.0],
+      y: [-0.3, 0.3],
+      yaw: [-1.0, 1.0],
+      linear_acceleration: 1.0,
+      yaw_acceleration: 2.0,
+      initial: VelocityCommand {
+        x: 0.6,
+        y: 0.0,
+        yaw: 0.0,
+      },
+      deadman_ms: Some(1000),
+    }
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VelocityStatus {
+  /// What the operator asked for, within the limits.
+  pub target: VelocityCommand,
+  /// What the walk loop currently sends, ramping towards `target`.
+  pub current: VelocityCommand,
+  pub limits: VelocityConfig,
+}
+
+#[derive(Debug)]
+struct RampState {
+  target: VelocityCommand,
+  current: VelocityCommand,
+  last_step: Option<Instant>,
+  /// When an operator last set the target, refreshed when a walk starts.
+  /// The deadman only runs once this is set.
+  last_set: Option<Instant>,
+  /// Set by [`WalkCommand::end`] until the next walk starts.
+  ending: bool,
+}
+
+/// Live velocity command of the walk loop. Operators set a target, which is
+/// clamped to the configured limits, and every tick of the loop moves the
+/// command it sends towards it no faster than the acceleration limits.
+#[derive(Debug)]
+pub struct WalkCommand {
+  config: VelocityConfig,
+  state: Mutex<RampState>,
+}
+
+impl WalkCommand {
+  pub fn new(config: VelocityConfig) -> Self {
+    let target = clamp(&config, config.initial);
+
+    Self {
+      config,
+      state: Mutex::new(RampState {
+        target,
+        current: VelocityCommand::default(),
+        last_step: None,
+        last_set: None,
+        ending: false,
+      }),
+    }
+  }
+
+  /// Sets the target and returns it as clamped to the limits.
+  pub fn set(&self, command: VelocityCommand) -> eyre::Result<VelocityCommand> {
+    if !command.to_array().iter().all(|value| value.is_finite()) {
+      return Err(eyre::eyre!("Velocity command {command:?} is not finite"));
+    }
+
+    let target = clamp(&self.config, command);
+    let mut state = self.state.lock().unwrap();
+    state.target = target;
+    state.last_set = Some(Instant::now());
+
+    Ok(target)
+  }
+
+  /// Sets the target to standing still.
+  pub fn stop(&self) {
+    let mut state = self.state.lock().unwrap();
+    state.target = VelocityCommand::default();
+    state.last_set = Some(Instant::now());
+  }
+
+  /// Restarts the ramp from standing still, for a walk that is starting.
+  pub fn start(&self) {
+    let mut state = self.state.lock().unwrap();
+    state.current = VelocityCommand::default();
+    state.last_step = None;
+    state.last_set = state.last_set.map(|_| Instant::now());
+    state.ending = false;
+  }
+
+  /// Asks the running walk to end after its current tick.
+  pub fn end(&self) {
+    self.state.lock().unwrap().ending = true;
+  }
+
+  /// Whether [`WalkCommand::end`] was called since the walk started.
+  pub fn ending(&self) -> bool {
+    self.state.lock().unwrap().ending
+  }
+
+  /// Moves the current command towards the target by the time since the
+  /// last step and returns it. Drops the target first if the deadman ran out.
+  pub fn step(&self) -> VelocityCommand {
+    let mut state = self.state.lock().unwrap();
+    let now = Instant::now();
+
+    let expired = match (self.config.deadman_ms, state.last_set) {
+      (Some(deadman), Some(last_set)) => {
+        now - last_set >= Duration::from_millis(deadman)
+      }
+      _ => false,
+    };
+    if expired && state.target != VelocityCommand::default() {
+      tracing::warn!(
+        target = ?state.target,
+        "No velocity command received, stopping"
+      );
+      state.target = VelocityCommand::default();
+    }
+
+    let dt = state
+      .last_step
+      .map_or(Duration::ZERO, |last| now - last)
+      .min(MAX_RAMP_STEP)
+      .as_secs_f64();
+    state.last_step = Some(now);
+
+    let linear = self.config.linear_acceleration * dt;
+    let yaw = self.config.yaw_acceleration * dt;
+    state.current = VelocityCommand {
+      x: approach(state.current.x, state.target.x, linear),
+      y: approach(state.current.y, state.target.y, linear),
+      yaw: approach(state.current.yaw, state.target.yaw, yaw),
+    };
+
+    state.current
+  }
+
+  pub fn status(&self) -> VelocityStatus {
+    let state = self.state.lock().unwrap();
+
+    VelocityStatus {
+      target: state.target,
+      current: state.current,
+      limits: self.config.clone(),
+    }
+  }
+}
+
+fn clamp(config: &VelocityConfig, command: VelocityCommand) -> VelocityCommand {
+  let within = |value: f64, [min, max]: [f64; 2]| value.clamp(min, max);
+
+  VelocityCommand {
+    x: within(command.x, config.x),
+    y: within(command.y, config.y),
+    yaw: within(command.yaw, config.yaw),
+  }
+}
+
+/// Moves `from` towards `to` by at most `step`.
+fn approach(from: f64, to: f64, step: f64) -> f64 {
+  from + (to - from).clamp(-step, step)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(deadman_ms: Option<u64>) -> VelocityConfig {
+    VelocityConfig {
+      // Reaches any target within one step.
+      linear_acceleration: 100.0,
+      yaw_acceleration: 100.0,
+      deadman_ms,
+      ..Default::default()
+    }
+  }
+
+  fn forward(x: f64) -> VelocityCommand {
+    VelocityCommand {
+      x,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn targets_are_clamped_and_ramped_towards() {
+    let command = WalkCommand::new(VelocityConfig {
+      linear_acceleration: 1.0,
+      ..config(None)
+    });
+
+    assert_eq!(command.set(forward(5.0)).unwrap(), forward(1.0));
+    assert!(command.set(forward(f64::NAN)).is_err());
+
+    command.step();
+    std::thread::sleep(Duration::from_millis(20));
+    let current = command.step();
+    // At most 1 m/s² for at most MAX_RAMP_STEP.
+    assert!(current.x > 0.0 && current.x <= 0.1);
+  }
+
+  #[test]
+  fn targets_decay_without_commands() {
+    let command = WalkCommand::new(config(Some(100)));
+    command.start();
+    command.set(forward(0.5)).unwrap();
+
+    command.step();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(command.step(), forward(0.5));
+
+    std::thread::sleep(Duration::from_millis(100));
+    command.step();
+    assert_eq!(command.status().target, VelocityCommand::default());
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(command.step(), VelocityCommand::default());
+
+    // A new command starts the walk again.
+    command.set(forward(0.5)).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(command.step(), forward(0.5));
+  }
+
+  #[test]
+  fn targets_are_kept_without_a_deadman() {
+    let command = WalkCommand::new(config(None));
+    command.set(forward(0.5)).unwrap();
+
+    command.step();
+    std::thread::sleep(Duration::from_millis(40));
+    assert_eq!(command.step(), forward(0.5));
+  }
+
+  #[test]
+  fn the_deadman_waits_for_an_operator() {
+    let command = WalkCommand::new(config(Some(50)));
+    command.start();
+
+    command.step();
+    std::thread::sleep(Duration::from_millis(80));
+    assert_eq!(command.step(), config(None).initial);
+  }
+
+  #[test]
+  fn stopping_ramps_down_to_standing_still() {
+    let command = WalkCommand::new(config(Some(100)));
+    command.start();
+    command.set(forward(0.5)).unwrap();
+    command.step();
+
+    command.stop();
+    assert_eq!(command.status().target, VelocityCommand::default());
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(command.step(), VelocityCommand::default());
+
+    command.set(forward(0.5)).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(command.step(), forward(0.5));
+  }
+}
@@ -0,0 +1,560 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::Instrument;
+
+use crate::{
+  arbiter::Lease,
+  events::RobotEvent,
+  fall::FallDetector,
+  hal::{check_response, check_results, Hal},
+  health::HealthMonitor,
+  profiler::Profiler,
+  proto::actuator::ConfigureActuatorRequest,
+  ActuatorCommand, Axis, Joint, JointCommand, KBot, Robot,
+};
+
+/// How often registered controllers are checked for staleness.
+const CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Safe state a controller's joints are put in when it goes silent.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchdogAction {
+  /// Command each joint to stay where it currently is. Skipped after a fall,
+  /// when no joint may be commanded.
+  Hold,
+  /// Turn torque off.
+  Relax,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ControllerTimeout {
+  pub timeout_ms: u64,
+  pub action: WatchdogAction,
+  /// Only explicit heartbeats keep the controller alive, not its commands.
+  /// Use this when the controller runs on the robot but is supervised by a
+  /// remote client that may disappear.
+  pub require_heartbeat: bool,
+}
+
+impl Default for ControllerTimeout {
+  fn default() -> Self {
+    Self {
+      timeout_ms: 500,
+      action: WatchdogAction::Hold,
+      require_heartbeat: false,
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct WatchdogConfig {
+  pub default: ControllerTimeout,
+  /// Overrides keyed by controller name, e.g. `"walk"`.
+  #[serde(default)]
+  pub controllers: BTreeMap<String, ControllerTimeout>,
+}
+
+impl WatchdogConfig {
+  pub fn timeout(&self, controller: &str) -> ControllerTimeout {
+    self
+      .controllers
+      .get(controller)
+      .cloned()
+      .unwrap_or_else(|| self.default.clone())
+  }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ControllerStatus {
+  pub name: String,
+  pub timeout: ControllerTimeout,
+  /// Milliseconds since the controller was last heard from.
+  pub silent_ms: u128,
+  pub actuators: BTreeSet<u32>,
+  pub tripped: bool,
+}
+
+/// A controller that just tripped, see [`Watchdog::expired`].
+#[derive(Debug)]
+struct Expired {
+  name: String,
+  action: WatchdogAction,
+  actuators: Vec<u32>,
+  lease: Arc<Lease>,
+}
+
+#[derive(Debug)]
+struct Entry {
+  id: u64,
+  timeout: ControllerTimeout,
+  last_seen: Instant,
+  actuators: BTreeSet<u32>,
+  tripped: bool,
+  abandoned: bool,
+  /// Kept past the handle of an abandoned controller, so the lease is only
+  /// released once its joints are in a safe state and nobody else commands
+  /// them in the meantime.
+  lease: Arc<Lease>,
+}
+
+/// Puts joints in a safe state when the controller commanding them stops
+/// sending commands or heartbeats.
+///
+/// Controllers register with the [`Lease`] they hold through
+/// [`Watchdog::register`] (or [`KBot::acquire_controller`]) and send commands
+/// through the returned [`ControllerHandle`]. Once a controller trips, its
+/// commands are rejected until it is [reset](Watchdog::reset).
+#[derive(Debug)]
+pub struct Watchdog {
+  hal: Hal,
+  config: WatchdogConfig,
+  health: Arc<HealthMonitor>,
+  fall_detector: Arc<FallDetector>,
+  events: broadcast::Sender<RobotEvent>,
+  profiler: Arc<Profiler>,
+  next_id: AtomicU64,
+  controllers: std::sync::Mutex<BTreeMap<String, Entry>>,
+}
+
+impl Watchdog {
+  pub fn new(
+    hal: Hal,
+    config: WatchdogConfig,
+    health: Arc<HealthMonitor>,
+    fall_detector: Arc<FallDetector>,
+    events: broadcast::Sender<RobotEvent>,
+    profiler: Arc<Profiler>,
+  ) -> Self {
+    Self {
+      hal,
+      config,
+      health,
+      fall_detector,
+      events,
+      profiler,
+      next_id: AtomicU64::new(0),
+      controllers: std::sync::Mutex::new(BTreeMap::new()),
+    }
+  }
+
+  /// Starts watching the holder of `lease`, replacing any previous
+  /// controller of the same name.
+  pub fn register(self: &Arc<Self>, lease: Lease) -> ControllerHandle {
+    let name = lease.controller().to_string();
+    let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+    let lease = Arc::new(lease);
+
+    self.controllers.lock().unwrap().insert(
+      name.clone(),
+      Entry {
+        id,
+        timeout: self.config.timeout(&name),
+        last_seen: Instant::now(),
+        actuators: BTreeSet::new(),
+        tripped: false,
+        abandoned: false,
+        lease: lease.clone(),
+      },
+    );
+
+    ControllerHandle {
+      name,
+      id,
+      lease,
+      watchdog: self.clone(),
+      finished: false,
+    }
+  }
+
+  /// Keeps the controller `name` alive.
+  pub fn heartbeat(&self, name: &str) -> eyre::Result<()> {
+    let mut controllers = self.controllers.lock().unwrap();
+    let Some(entry) = controllers.get_mut(name) else {
+      return Err(eyre::eyre!("No controller named {name}"));
+    };
+
+    if entry.tripped {
+      return Err(eyre::eyre!("Controller {name} was stopped by the watchdog"));
+    }
+
+    entry.last_seen = Instant::now();
+
+    Ok(())
+  }
+
+  /// Clears a tripped controller so it can command joints again.
+  pub fn reset(&self, name: &str) -> eyre::Result<()> {
+    let mut controllers = self.controllers.lock().unwrap();
+    let Some(entry) = controllers.get_mut(name) else {
+      return Err(eyre::eyre!("No controller named {name}"));
+    };
+
+    entry.tripped = false;
+    entry.last_seen = Instant::now();
+
+    Ok(())
+  }
+
+  pub fn status(&self) -> Vec<ControllerStatus> {
+    self
+      .controllers
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|(name, entry)| ControllerStatus {
+        name: name.clone(),
+        timeout: entry.timeout.clone(),
+        silent_ms: entry.last_seen.elapsed().as_millis(),
+        actuators: entry.actuators.clone(),
+        tripped: entry.tripped,
+      })
+      .collect()
+  }
+
+  pub fn spawn(self: &Arc<Self>) {
+    let watchdog = self.clone();
+
+    tokio::spawn(
+      async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+        loop {
+          interval.tick().await;
+
+          let mut tick = watchdog.profiler.tick("watchdog");
+          // The lease of an abandoned controller lives until the end of
+          // the iteration, after its joints were made safe.
+          for Expired {
+            name,
+            action,
+            actuators,
+            lease: _lease,
+          } in watchdog.expired()
+          {
+            tracing::warn!(
+              controller = %name,
+              ?actuators,
+              ?action,
+              "Controller went silent"
+            );
+
+            if let Err(e) = tick
+              .time("make_safe", watchdog.make_safe(action, &actuators))
+              .await
+            {
+              tracing::error!(
+                controller = %name,
+                error = %e,
+                "Failed to secure controller's joints"
+              );
+            }
+
+            watchdog
+              .events
+              .send(RobotEvent::WatchdogTripped {
+                controller: name,
+                action,
+              })
+              .ok();
+          }
+        }
+      }
+      .instrument(tracing::info_span!("watchdog")),
+    );
+  }
+
+  /// Marks stale controllers as tripped and returns what to secure.
+  /// Preempted controllers are dropped instead, since their joints already
+  /// belong to someone else.
+  fn expired(&self) -> Vec<Expired> {
+    let mut controllers = self.controllers.lock().unwrap();
+    let mut expired = Vec::new();
+
+    controllers.retain(|_, entry| !entry.lease.is_preempted());
+
+    for (name, entry) in controllers.iter_mut() {
+      let timeout = Duration::from_millis(entry.timeout.timeout_ms);
+      if entry.tripped
+        || (!entry.abandoned && entry.last_seen.elapsed() < timeout)
+      {
+        continue;
+      }
+
+      entry.tripped = true;
+      expired.push(Expired {
+        name: name.clone(),
+        action: entry.timeout.action,
+        actuators: entry.actuators.iter().copied().collect(),
+        lease: entry.lease.clone(),
+      });
+    }
+
+    // Abandoned controllers have nobody left to reset them.
+    controllers.retain(|_, entry| !entry.abandoned);
+
+    expired
+  }
+
+  async fn make_safe(
+    &self,
+    action: WatchdogAction,
+    actuator_ids: &[u32],
+  ) -> eyre::Result<()> {
+    if actuator_ids.is_empty() {
+      return Ok(());
+    }
+
+    match action {
+      WatchdogAction::Hold if self.fall_detector.has_fallen() => {
+        tracing::info!(?actuator_ids, "Not holding joints after a fall");
+      }
+      WatchdogAction::Hold => {
+        let states = self
+          .hal
+          .actuators
+          .get_actuators_state(actuator_ids.to_vec())
+          .await?;
+
+        let commands = states
+          .into_iter()
+          .filter_map(|state| {
+            Some(ActuatorCommand {
+              actuator_id: state.actuator_id,
+              position: Some(state.position?),
+              velocity: None,
+              torque: None,
+            })
+          })
+          .collect::<Vec<_>>();
+
+        self.health.record_commands(&commands).await;
+
+        check_results(&self.hal.actuators.command_actuators(commands).await?)?;
+      }
+      WatchdogAction::Relax => {
+        for &actuator_id in actuator_ids {
+          let response = self
+            .hal
+            .actuators
+            .configure_actuator(ConfigureActuatorRequest {
+              actuator_id,
+              torque_enabled: Some(false),
+              ..Default::default()
+            })
+            .await?;
+
+          check_response(actuator_id, &response)?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn feed(&self, name: &str, id: u64, actuators: &[u32]) -> eyre::Result<()> {
+    let mut controllers = self.controllers.lock().unwrap();
+    let Some(entry) = controllers.get_mut(name).filter(|entry| entry.id == id)
+    else {
+      return Err(eyre::eyre!("Controller {name} is no longer registered"));
+    };
+
+    if entry.tripped {
+      return Err(eyre::eyre!("Controller {name} was stopped by the watchdog"));
+    }
+
+    entry.actuators.extend(actuators);
+    if !entry.timeout.require_heartbeat {
+      entry.last_seen = Instant::now();
+    }
+
+    Ok(())
+  }
+
+  /// Unregisters a controller. An abandoned one, whose lease is still
+  /// held, is only removed once its joints were made safe.
+  fn release(&self, name: &str, id: u64, abandoned: bool) {
+    let mut controllers = self.controllers.lock().unwrap();
+
+    if controllers.get(name).is_some_and(|entry| entry.id == id) {
+      if abandoned {
+        if let Some(entry) = controllers.get_mut(name) {
+          entry.abandoned = true;
+        }
+      } else {
+        controllers.remove(name);
+      }
+    }
+  }
+}
+
+/// A registered controller. Dropping it without calling
+/// [`ControllerHandle::finish`] trips the watchdog immediately, so a control
+/// loop that dies or is cancelled leaves its joints in a safe state. A
+/// controller that was preempted is released quietly instead, since its
+/// joints already belong to someone else.
+#[derive(Debug)]
+pub struct ControllerHandle {
+  name: String,
+  id: u64,
+  lease: Arc<Lease>,
+  watchdog: Arc<Watchdog>,
+  finished: bool,
+}
+
+impl ControllerHandle {
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn heartbeat(&self) -> eyre::Result<()> {
+    self.watchdog.heartbeat(&self.name)
+  }
+
+  pub fn lease(&self) -> &Lease {
+    &self.lease
+  }
+
+  /// Sends commands on behalf of this controller, feeding the watchdog.
+  pub async fn command_joints(
+    &self,
+    kbot: &KBot,
+    commands: Vec<(Joint, Option<Axis>, JointCommand)>,
+  ) -> eyre::Result<()> {
+    let actuators = commands
+      .iter()
+      .filter_map(|(joint, axis, _)| KBot::get_actuator_id(*joint, *axis))
+      .collect::<Vec<_>>();
+
+    self.lease.check(&actuators)?;
+    self.watchdog.feed(&self.name, self.id, &actuators)?;
+
+    kbot.command_joints(&self.lease, commands).await
+  }
+
+  /// Unregisters the controller after it stopped on purpose.
+  pub fn finish(mut self) {
+    self.finished = true;
+    self.watchdog.release(&self.name, self.id, false);
+  }
+}
+
+impl Drop for ControllerHandle {
+  fn drop(&mut self) {
+    if !self.finished {
+      let abandoned = !self.lease.is_preempted();
+      self.watchdog.release(&self.name, self.id, abandoned);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    arbiter::{Arbiter, Priority},
+    fall::FallConfig,
+    gains::{GainManager, GainProfiles},
+    health::HealthConfig,
+    metrics::Metrics,
+    profiler::ProfilerConfig,
+    sim::{SimConfig, Simulator},
+  };
+
+  fn watchdog(events: broadcast::Sender<RobotEvent>) -> Arc<Watchdog> {
+    let hal = Simulator::new::<KBot>(SimConfig::default(), None).hal();
+    let gains = GainManager::new::<KBot>(
+      hal.clone(),
+      Vec::new(),
+      GainProfiles::default(),
+    )
+    .unwrap();
+    let metrics = Arc::new(Metrics::new().unwrap());
+    let profiler = Arc::new(Profiler::new(ProfilerConfig::default(), metrics));
+    let health = Arc::new(HealthMonitor::new(
+      hal.clone(),
+      HealthConfig::default(),
+      Vec::new(),
+      Arc::new(gains),
+      profiler.clone(),
+    ));
+    let fall_detector = Arc::new(FallDetector::new(
+      hal.clone(),
+      FallConfig::default(),
+      Vec::new(),
+      health.clone(),
+      events.clone(),
+      profiler.clone(),
+    ));
+
+    Arc::new(Watchdog::new(
+      hal,
+      WatchdogConfig::default(),
+      health,
+      fall_detector,
+      events,
+      profiler,
+    ))
+  }
+
+  #[test]
+  fn abandoned_leases_outlive_the_safe_state() {
+    let (events, _) = broadcast::channel(16);
+    let watchdog = watchdog(events.clone());
+    let arbiter = Arc::new(Arbiter::new(events));
+    let claim = || arbiter.acquire("pose", Priority::Policy, [34]);
+
+    let lease = arbiter.acquire("walk", Priority::Policy, [34]).unwrap();
+    drop(watchdog.register(lease));
+    assert!(claim().is_err());
+
+    let expired = watchdog.expired();
+    assert_eq!(expired.len(), 1);
+    assert!(claim().is_err());
+
+    drop(expired);
+    assert!(claim().is_ok());
+  }
+
+  #[test]
+  fn finished_controllers_release_their_lease() {
+    let (events, _) = broadcast::channel(16);
+    let watchdog = watchdog(events.clone());
+    let arbiter = Arc::new(Arbiter::new(events));
+
+    let lease = arbiter.acquire("walk", Priority::Policy, [34]).unwrap();
+    watchdog.register(lease).finish();
+
+    assert!(watchdog.expired().is_empty());
+    assert!(arbiter.acquire("pose", Priority::Policy, [34]).is_ok());
+  }
+
+  #[test]
+  fn preempted_controllers_are_not_secured() {
+    let (events, _) = broadcast::channel(16);
+    let watchdog = watchdog(events.clone());
+    let arbiter = Arc::new(Arbiter::new(events));
+
+    let lease = arbiter.acquire("walk", Priority::Policy, [34]).unwrap();
+    let handle = watchdog.register(lease);
+    let _teleop = arbiter.acquire("teleop", Priority::Teleop, [34]).unwrap();
+
+    if let Some(entry) = watchdog.controllers.lock().unwrap().get_mut("walk") {
+      entry.actuators.insert(34);
+      entry.last_seen -= Duration::from_secs(1);
+    }
+
+    assert!(watchdog.expired().is_empty());
+    assert!(watchdog.status().is_empty());
+    assert!(handle.heartbeat().is_err());
+  }
+}